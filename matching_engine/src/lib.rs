@@ -1,7 +1,45 @@
+// pyo3 0.19's #[pymethods] expansion trips this lint on newer compilers
+#![allow(non_local_definitions)]
+
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashMap};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, VecDeque};
+use std::ops::Bound;
+
+/// Number of filled/cancelled orders kept around for `get_order` lookups
+const FINISHED_ORDER_CACHE_SIZE: usize = 1024;
+
+/// Resolution of the fixed-point keys price levels and stops are stored under: prices
+/// closer together than 1e-8 are the same price
+const PRICE_UNITS_PER_PRICE: f64 = 1e8;
+
+// A price as a whole number of PRICE_UNITS_PER_PRICE units
+fn price_units(price: f64) -> i64 {
+    (price * PRICE_UNITS_PER_PRICE).round() as i64
+}
+
+// The float nearest to the exact value of `units` price units
+fn units_price(units: i64) -> f64 {
+    units as f64 / PRICE_UNITS_PER_PRICE
+}
+
+/// Price increment used for peg offsets until a book sets its own
+const DEFAULT_TICK_SIZE: f64 = 0.01;
+
+fn default_tick_size() -> f64 {
+    DEFAULT_TICK_SIZE
+}
+
+fn default_next_client_session_id() -> u64 {
+    1
+}
+
+fn default_finished_order_retention() -> Option<usize> {
+    Some(FINISHED_ORDER_CACHE_SIZE)
+}
 
 /// Python module Enums
 #[pyclass]
@@ -28,1071 +66,9516 @@ pub enum PyOrderStatus {
     Rejected,
 }
 
-/// Order type enum: Market or Limit
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub enum OrderType {
-    Market,
-    Limit,
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PyMatchingPolicy {
+    PriceTime,
+    ProRata,
+    TopOrderProRata,
 }
 
-/// Order side enum: Buy or Sell
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub enum OrderSide {
-    Buy,
-    Sell,
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PyHiddenPriority {
+    BehindDisplayed,
+    TimePriority,
 }
 
-/// Order status enum
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub enum OrderStatus {
-    New,
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PyRejectReason {
+    PriceCollar,
+    PriceBand,
+    AuctionClosed,
+    TradingHalted,
+    MarketClosed,
+    DuplicateClientOrderId,
+    SessionClosed,
+    PostOnlyWouldCross,
+    FillOrKillUnfilled,
+    MinimumQuantityUnmet,
+    NothingToReduce,
+    NoLiquidity,
+    InvalidQuantity,
+    InvalidPrice,
+    AlreadyExpired,
+    MissingOwner,
+    QuoteSetRejected,
+    DuplicateOrderId,
+}
+
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PyAuditAction {
+    Accepted,
+    Amended,
     PartiallyFilled,
     Filled,
     Cancelled,
+    Expired,
     Rejected,
+    TradeBusted,
 }
 
-/// Order struct representing a single order in the order book
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Order {
-    pub id: u64,
-    pub side: OrderSide,
-    pub order_type: OrderType,
-    pub price: Option<f64>, // None for market orders
-    pub quantity: f64,
-    pub filled_quantity: f64,
-    pub status: OrderStatus,
-    pub timestamp: u64,
-    pub symbol: Option<String>,
-    // Cache remaining quantity for performance
-    pub remaining_quantity: f64,
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PyAuctionSession {
+    Open,
+    Close,
+    Volatility,
 }
 
-impl Order {
-    pub fn new(
-        id: u64,
-        side: OrderSide,
-        order_type: OrderType,
-        price: Option<f64>,
-        quantity: f64,
-        timestamp: u64,
-        symbol: Option<String>,
-    ) -> Self {
-        Order {
-            id,
-            side,
-            order_type,
-            price,
-            quantity,
-            filled_quantity: 0.0,
-            status: OrderStatus::New,
-            timestamp,
-            symbol,
-            remaining_quantity: quantity,
-        }
-    }
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PyTradingState {
+    PreOpen,
+    ContinuousTrading,
+    Auction,
+    Halted,
+    Closed,
 }
 
-/// Trade struct representing a single trade
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Trade {
-    pub id: u64,
-    pub buy_order_id: u64,
-    pub sell_order_id: u64,
-    pub price: f64,
-    pub quantity: f64,
-    pub timestamp: u64,
-    pub symbol: Option<String>,
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PyAssetClass {
+    Equity,
+    Future,
+    Option,
+    Fx,
+    Crypto,
 }
 
-/// PriceLevel struct for aggregating orders at the same price
-#[derive(Debug, Clone)]
-pub struct PriceLevel {
-    pub price: f64,
-    pub orders: Vec<Order>,
-    pub total_quantity_cache: f64,
-    pub is_dirty: bool,
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PyLiquidity {
+    Maker,
+    Taker,
 }
 
-impl PriceLevel {
-    pub fn new(price: f64) -> Self {
-        PriceLevel {
-            price,
-            orders: Vec::with_capacity(16), // Pre-allocate to avoid frequent reallocations
-            total_quantity_cache: 0.0,
-            is_dirty: false,
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PySpreadLeg {
+    Front,
+    Back,
+    Spread,
+}
+
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PyIcebergRefill {
+    BackOfLevel,
+    KeepPriority,
+}
+
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PyShortSaleRule {
+    Off,
+    TickTest,
+}
+
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PyTimeInForce {
+    Gtc,
+    Ioc,
+    Fok,
+    Gtd,
+    Day,
+}
+
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PyStopTrigger {
+    LastTrade,
+    BestBid,
+    BestAsk,
+    Midpoint,
+}
+
+#[pyclass]
+#[derive(Clone, Copy)]
+pub enum PyPeg {
+    Midpoint,
+    Primary,
+    Market,
+}
+
+impl From<OrderSide> for PyOrderSide {
+    fn from(side: OrderSide) -> Self {
+        match side {
+            OrderSide::Buy => PyOrderSide::Buy,
+            OrderSide::Sell => PyOrderSide::Sell,
         }
     }
+}
 
-    pub fn add_order(&mut self, order: Order) {
-        self.total_quantity_cache += order.remaining_quantity;
-        self.orders.push(order);
+impl From<OrderType> for PyOrderType {
+    fn from(order_type: OrderType) -> Self {
+        match order_type {
+            OrderType::Market => PyOrderType::Market,
+            OrderType::Limit => PyOrderType::Limit,
+        }
     }
+}
 
-    pub fn remove_order(&mut self, order_id: u64) -> Option<Order> {
-        if let Some(pos) = self.orders.iter().position(|o| o.id == order_id) {
-            let order = self.orders.swap_remove(pos); // Use swap_remove for O(1) removal
-            self.is_dirty = true;
-            Some(order)
-        } else {
-            None
+impl From<OrderStatus> for PyOrderStatus {
+    fn from(status: OrderStatus) -> Self {
+        match status {
+            OrderStatus::New => PyOrderStatus::New,
+            OrderStatus::PartiallyFilled => PyOrderStatus::PartiallyFilled,
+            OrderStatus::Filled => PyOrderStatus::Filled,
+            OrderStatus::Cancelled => PyOrderStatus::Cancelled,
+            OrderStatus::Rejected => PyOrderStatus::Rejected,
         }
     }
+}
 
-    pub fn update_quantity_cache(&mut self) {
-        if self.is_dirty {
-            self.total_quantity_cache = self.orders.iter().map(|o| o.remaining_quantity).sum();
-            self.is_dirty = false;
+impl From<AuditAction> for PyAuditAction {
+    fn from(action: AuditAction) -> Self {
+        match action {
+            AuditAction::Accepted => PyAuditAction::Accepted,
+            AuditAction::Amended => PyAuditAction::Amended,
+            AuditAction::PartiallyFilled => PyAuditAction::PartiallyFilled,
+            AuditAction::Filled => PyAuditAction::Filled,
+            AuditAction::Cancelled => PyAuditAction::Cancelled,
+            AuditAction::Expired => PyAuditAction::Expired,
+            AuditAction::Rejected => PyAuditAction::Rejected,
+            AuditAction::TradeBusted => PyAuditAction::TradeBusted,
         }
     }
+}
 
-    pub fn total_quantity(&mut self) -> f64 {
-        self.update_quantity_cache();
-        self.total_quantity_cache
+impl From<RejectReason> for PyRejectReason {
+    fn from(reason: RejectReason) -> Self {
+        match reason {
+            RejectReason::PriceCollar => PyRejectReason::PriceCollar,
+            RejectReason::PriceBand => PyRejectReason::PriceBand,
+            RejectReason::AuctionClosed => PyRejectReason::AuctionClosed,
+            RejectReason::TradingHalted => PyRejectReason::TradingHalted,
+            RejectReason::MarketClosed => PyRejectReason::MarketClosed,
+            RejectReason::DuplicateClientOrderId => PyRejectReason::DuplicateClientOrderId,
+            RejectReason::SessionClosed => PyRejectReason::SessionClosed,
+            RejectReason::PostOnlyWouldCross => PyRejectReason::PostOnlyWouldCross,
+            RejectReason::FillOrKillUnfilled => PyRejectReason::FillOrKillUnfilled,
+            RejectReason::MinimumQuantityUnmet => PyRejectReason::MinimumQuantityUnmet,
+            RejectReason::NothingToReduce => PyRejectReason::NothingToReduce,
+            RejectReason::NoLiquidity => PyRejectReason::NoLiquidity,
+            RejectReason::InvalidQuantity => PyRejectReason::InvalidQuantity,
+            RejectReason::InvalidPrice => PyRejectReason::InvalidPrice,
+            RejectReason::AlreadyExpired => PyRejectReason::AlreadyExpired,
+            RejectReason::MissingOwner => PyRejectReason::MissingOwner,
+            RejectReason::QuoteSetRejected => PyRejectReason::QuoteSetRejected,
+            RejectReason::DuplicateOrderId => PyRejectReason::DuplicateOrderId,
+        }
     }
+}
 
-    pub fn is_empty(&self) -> bool {
-        self.orders.is_empty()
+impl From<PyAuctionSession> for AuctionSession {
+    fn from(session: PyAuctionSession) -> Self {
+        match session {
+            PyAuctionSession::Open => AuctionSession::Open,
+            PyAuctionSession::Close => AuctionSession::Close,
+            PyAuctionSession::Volatility => AuctionSession::Volatility,
+        }
     }
 }
 
-/// Batch of orders to process efficiently
-#[derive(Debug, Default, Clone)]
-pub struct OrderBatch {
-    pub buy_market_orders: Vec<Order>,
-    pub sell_market_orders: Vec<Order>,
-    pub buy_limit_orders: Vec<Order>,
-    pub sell_limit_orders: Vec<Order>,
+impl From<PyTradingState> for TradingState {
+    fn from(state: PyTradingState) -> Self {
+        match state {
+            PyTradingState::PreOpen => TradingState::PreOpen,
+            PyTradingState::ContinuousTrading => TradingState::ContinuousTrading,
+            PyTradingState::Auction => TradingState::Auction,
+            PyTradingState::Halted => TradingState::Halted,
+            PyTradingState::Closed => TradingState::Closed,
+        }
+    }
 }
 
-impl OrderBatch {
-    pub fn new() -> Self {
-        OrderBatch {
-            buy_market_orders: Vec::with_capacity(16),
-            sell_market_orders: Vec::with_capacity(16),
-            buy_limit_orders: Vec::with_capacity(32),
-            sell_limit_orders: Vec::with_capacity(32),
+impl From<TradingState> for PyTradingState {
+    fn from(state: TradingState) -> Self {
+        match state {
+            TradingState::PreOpen => PyTradingState::PreOpen,
+            TradingState::ContinuousTrading => PyTradingState::ContinuousTrading,
+            TradingState::Auction => PyTradingState::Auction,
+            TradingState::Halted => PyTradingState::Halted,
+            TradingState::Closed => PyTradingState::Closed,
         }
     }
+}
 
-    pub fn add_order(&mut self, order: Order) {
-        match (order.side, order.order_type) {
-            (OrderSide::Buy, OrderType::Market) => self.buy_market_orders.push(order),
-            (OrderSide::Sell, OrderType::Market) => self.sell_market_orders.push(order),
-            (OrderSide::Buy, OrderType::Limit) => self.buy_limit_orders.push(order),
-            (OrderSide::Sell, OrderType::Limit) => self.sell_limit_orders.push(order),
+impl From<PyAssetClass> for AssetClass {
+    fn from(asset_class: PyAssetClass) -> Self {
+        match asset_class {
+            PyAssetClass::Equity => AssetClass::Equity,
+            PyAssetClass::Future => AssetClass::Future,
+            PyAssetClass::Option => AssetClass::Option,
+            PyAssetClass::Fx => AssetClass::Fx,
+            PyAssetClass::Crypto => AssetClass::Crypto,
         }
     }
+}
 
-    pub fn sort(&mut self) {
-        // Sort market orders by timestamp (FIFO)
-        self.buy_market_orders.sort_by_key(|o| o.timestamp);
-        self.sell_market_orders.sort_by_key(|o| o.timestamp);
-
-        // Sort limit orders by price (best price first) then timestamp
-        self.buy_limit_orders.sort_by(|a, b| {
-            let a_price = a.price.unwrap_or(0.0);
-            let b_price = b.price.unwrap_or(0.0);
-            b_price
-                .partial_cmp(&a_price)
-                .unwrap_or(Ordering::Equal)
-                .then_with(|| a.timestamp.cmp(&b.timestamp))
-        });
+impl From<AssetClass> for PyAssetClass {
+    fn from(asset_class: AssetClass) -> Self {
+        match asset_class {
+            AssetClass::Equity => PyAssetClass::Equity,
+            AssetClass::Future => PyAssetClass::Future,
+            AssetClass::Option => PyAssetClass::Option,
+            AssetClass::Fx => PyAssetClass::Fx,
+            AssetClass::Crypto => PyAssetClass::Crypto,
+        }
+    }
+}
 
-        self.sell_limit_orders.sort_by(|a, b| {
-            let a_price = a.price.unwrap_or(f64::MAX);
-            let b_price = b.price.unwrap_or(f64::MAX);
-            a_price
-                .partial_cmp(&b_price)
-                .unwrap_or(Ordering::Equal)
-                .then_with(|| a.timestamp.cmp(&b.timestamp))
-        });
+impl From<Liquidity> for PyLiquidity {
+    fn from(liquidity: Liquidity) -> Self {
+        match liquidity {
+            Liquidity::Maker => PyLiquidity::Maker,
+            Liquidity::Taker => PyLiquidity::Taker,
+        }
     }
+}
 
-    pub fn is_empty(&self) -> bool {
-        self.buy_market_orders.is_empty()
-            && self.sell_market_orders.is_empty()
-            && self.buy_limit_orders.is_empty()
-            && self.sell_limit_orders.is_empty()
+impl From<PySpreadLeg> for SpreadLeg {
+    fn from(leg: PySpreadLeg) -> Self {
+        match leg {
+            PySpreadLeg::Front => SpreadLeg::Front,
+            PySpreadLeg::Back => SpreadLeg::Back,
+            PySpreadLeg::Spread => SpreadLeg::Spread,
+        }
     }
+}
 
-    pub fn len(&self) -> usize {
-        self.buy_market_orders.len()
-            + self.sell_market_orders.len()
-            + self.buy_limit_orders.len()
-            + self.sell_limit_orders.len()
+impl From<SpreadLeg> for PySpreadLeg {
+    fn from(leg: SpreadLeg) -> Self {
+        match leg {
+            SpreadLeg::Front => PySpreadLeg::Front,
+            SpreadLeg::Back => PySpreadLeg::Back,
+            SpreadLeg::Spread => PySpreadLeg::Spread,
+        }
     }
 }
 
-/// OrderBook struct with optimized implementation
-#[derive(Debug)]
-pub struct OrderBook {
-    // Price levels for improved locality and reduced cloning
-    buy_price_levels: BTreeMap<i64, PriceLevel>, // Negative price (int bits) as key for correct sorting
-    sell_price_levels: BTreeMap<i64, PriceLevel>, // Price bits as key
+impl From<PyIcebergRefill> for IcebergRefill {
+    fn from(refill: PyIcebergRefill) -> Self {
+        match refill {
+            PyIcebergRefill::BackOfLevel => IcebergRefill::BackOfLevel,
+            PyIcebergRefill::KeepPriority => IcebergRefill::KeepPriority,
+        }
+    }
+}
 
-    // Fast lookups
-    orders_by_id: HashMap<u64, (OrderSide, i64)>, // Map order ID to side and price key
+impl From<PyShortSaleRule> for ShortSaleRule {
+    fn from(rule: PyShortSaleRule) -> Self {
+        match rule {
+            PyShortSaleRule::Off => ShortSaleRule::Off,
+            PyShortSaleRule::TickTest => ShortSaleRule::TickTest,
+        }
+    }
+}
 
-    // Order and trade IDs
-    next_order_id: u64,
-    next_trade_id: u64,
+impl From<TimeInForce> for PyTimeInForce {
+    fn from(time_in_force: TimeInForce) -> Self {
+        match time_in_force {
+            TimeInForce::Gtc => PyTimeInForce::Gtc,
+            TimeInForce::Ioc => PyTimeInForce::Ioc,
+            TimeInForce::Fok => PyTimeInForce::Fok,
+            TimeInForce::Gtd { .. } => PyTimeInForce::Gtd,
+            TimeInForce::Day => PyTimeInForce::Day,
+        }
+    }
+}
 
-    // Trades with pre-allocated capacity
-    trades: Vec<Trade>,
+impl From<PyStopTrigger> for StopTrigger {
+    fn from(trigger: PyStopTrigger) -> Self {
+        match trigger {
+            PyStopTrigger::LastTrade => StopTrigger::LastTrade,
+            PyStopTrigger::BestBid => StopTrigger::BestBid,
+            PyStopTrigger::BestAsk => StopTrigger::BestAsk,
+            PyStopTrigger::Midpoint => StopTrigger::Midpoint,
+        }
+    }
+}
 
-    // Statistics
-    stats: OrderBookStats,
+impl From<PyHiddenPriority> for HiddenPriority {
+    fn from(priority: PyHiddenPriority) -> Self {
+        match priority {
+            PyHiddenPriority::BehindDisplayed => HiddenPriority::BehindDisplayed,
+            PyHiddenPriority::TimePriority => HiddenPriority::TimePriority,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct OrderBookStats {
-    pub orders_processed: u64,
-    pub trades_executed: u64,
+/// Order type enum: Market or Limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderType {
+    Market,
+    Limit,
 }
 
-impl OrderBook {
-    pub fn new() -> Self {
-        OrderBook {
-            buy_price_levels: BTreeMap::new(),
-            sell_price_levels: BTreeMap::new(),
-            orders_by_id: HashMap::with_capacity(1024),
-            next_order_id: 1,
-            next_trade_id: 1,
-            trades: Vec::with_capacity(1000),
-            stats: OrderBookStats::default(),
+/// Order side enum: Buy or Sell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+/// Order status enum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Rejected,
+}
+
+/// Why an order ended `Rejected`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectReason {
+    /// Limit price further through the opposite side than the book's price collar allows
+    PriceCollar,
+    /// Limit price outside the book's static or dynamic price band
+    PriceBand,
+    /// The auction the order targets has already run
+    AuctionClosed,
+    /// Submitted while trading is halted
+    TradingHalted,
+    /// Submitted while the market is closed
+    MarketClosed,
+    /// The owner already has a live order under the same client order id
+    DuplicateClientOrderId,
+    /// The client session the order was submitted under is not open
+    SessionClosed,
+    /// Post-only order that would have taken liquidity
+    PostOnlyWouldCross,
+    /// Fill-or-kill order that could not fill in full on arrival
+    FillOrKillUnfilled,
+    /// Order whose minimum execution quantity could not be met on arrival
+    MinimumQuantityUnmet,
+    /// Reduce-only order whose owner has no position on the other side to reduce
+    NothingToReduce,
+    /// Market order that found nothing to trade against
+    NoLiquidity,
+    /// Quantity breaks the book's quantity rules or the order's own display or minimum
+    /// execution quantity
+    InvalidQuantity,
+    /// Prices that contradict each other, like a bracket or a crossed quote
+    InvalidPrice,
+    /// Expiry time already passed on arrival
+    AlreadyExpired,
+    /// Order requires an owner it was not given
+    MissingOwner,
+    /// Another quote in an all-or-nothing quote set was invalid
+    QuoteSetRejected,
+    /// Caller-supplied order id already belongs to a known order
+    DuplicateOrderId,
+}
+
+/// Auction an on-open or on-close order targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum AuctionSession {
+    Open,
+    Close,
+    /// Volatility interruption, which can run any number of times per session
+    Volatility,
+}
+
+/// Limits on how far prices may stray from a fixed reference price (static band) and from
+/// the book's current reference price (dynamic band). A band is off while it is `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PriceBands {
+    /// Reference for the static band, e.g. the previous close
+    pub static_reference: Option<f64>,
+    /// Largest distance from `static_reference`
+    pub static_band: Option<ProtectionBand>,
+    /// Largest distance from `OrderBook::reference_price`
+    pub dynamic_band: Option<ProtectionBand>,
+    pub on_breach: BandBreach,
+}
+
+/// What happens when an order would trade outside the price bands
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BandBreach {
+    /// Refuse the execution: matching stops at the band and the rest of the order is
+    /// handled as if the book ended there
+    #[default]
+    Reject,
+    /// Halt trading, holding the rest of the order until trading resumes
+    Halt,
+}
+
+/// Price test applied to short sales in continuous matching. Auctions are not restricted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShortSaleRule {
+    #[default]
+    Off,
+    /// Uptick rule: a short sale may only execute above the last trade price, or at it
+    /// if the last trade was above the last different price before it (a zero-plus tick)
+    TickTest,
+}
+
+/// Circuit breaker that interrupts continuous trading with a volatility auction
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VolatilityInterruption {
+    /// Largest move allowed away from any trade price within `window`
+    pub band: ProtectionBand,
+    /// How far back, in timestamp units, trade prices count as references
+    pub window: u64,
+    /// How long the auction's call phase lasts before `advance_time` uncrosses it
+    pub auction_duration: u64,
+}
+
+/// Phase of the trading day, which decides whether orders are accepted and matched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TradingState {
+    /// Call phase of the opening auction: orders are accepted and held without matching
+    PreOpen,
+    /// Orders match as they arrive
+    #[default]
+    ContinuousTrading,
+    /// Call phase of a closing, volatility or unscheduled auction
+    Auction,
+    /// New orders are rejected and nothing matches; cancels are still accepted
+    Halted,
+    /// Like `Halted`, outside trading hours
+    Closed,
+}
+
+/// Outcome of an auction uncross, see `OrderBook::run_auction`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AuctionResult {
+    /// Single price every auction trade executed at
+    pub price: f64,
+    /// Quantity executed
+    pub volume: f64,
+    /// Buy minus sell quantity willing to trade at `price` that was left unmatched
+    pub imbalance: f64,
+}
+
+impl AuctionResult {
+    /// Side with quantity left unmatched at the auction price, if any
+    pub fn imbalance_side(&self) -> Option<OrderSide> {
+        if self.imbalance > 0.0 {
+            Some(OrderSide::Buy)
+        } else if self.imbalance < 0.0 {
+            Some(OrderSide::Sell)
+        } else {
+            None
+        }
+    }
+}
+
+/// How long a limit order remains eligible to rest in the book
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeInForce {
+    /// Good-till-cancelled: rests until filled or cancelled
+    #[default]
+    Gtc,
+    /// Good-till-date (a.k.a. good-till-time): also removed by `OrderBook::expire_orders`
+    /// once `expire_at` is reached. Must expire after the order's own timestamp.
+    Gtd { expire_at: u64 },
+    /// Immediate-or-cancel: match what is available on entry and cancel the remainder
+    Ioc,
+    /// Fill-or-kill: fill the whole quantity on entry or reject without trading
+    Fok,
+    /// Good for the current trading session only: rests like `Gtc` until
+    /// `OrderBook::end_session` cancels it
+    Day,
+}
+
+impl TimeInForce {
+    /// Expiry timestamp of a `Gtd` order
+    pub fn expire_at(&self) -> Option<u64> {
+        match *self {
+            TimeInForce::Gtd { expire_at } => Some(expire_at),
+            _ => None,
+        }
+    }
+}
+
+/// Price a stop order compares with its stop price to decide when to trigger
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StopTrigger {
+    /// Price of the last trade
+    #[default]
+    LastTrade,
+    /// Best displayed bid
+    BestBid,
+    /// Best displayed ask
+    BestAsk,
+    /// Midpoint of the best displayed bid and ask
+    Midpoint,
+}
+
+/// Book state a conditional order waits for before it is submitted. Displayed quantities
+/// and prices exclude hidden orders, as in depth snapshots.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BookCondition {
+    /// Displayed quantity at `price` on `side` is at least `quantity`
+    DepthAtLeast {
+        side: OrderSide,
+        price: f64,
+        quantity: f64,
+    },
+    /// Displayed quantity at the best price on `side` is at least `quantity`
+    BestDepthAtLeast { side: OrderSide, quantity: f64 },
+    /// Both sides have a displayed price and the spread is at most this wide
+    SpreadAtMost(f64),
+    /// Every one of the conditions holds
+    All(Vec<BookCondition>),
+    /// At least one of the conditions holds
+    Any(Vec<BookCondition>),
+}
+
+/// Reference price a pegged limit order tracks instead of a fixed price
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Peg {
+    /// Midpoint of the best bid and offer of non-pegged orders
+    Midpoint,
+    /// Best non-pegged price on the order's own side, shifted by `offset_ticks` ticks
+    /// (positive is a higher price)
+    Primary { offset_ticks: i64 },
+    /// Best non-pegged price on the opposite side, shifted by `offset_ticks` ticks
+    /// (positive is a higher price)
+    Market { offset_ticks: i64 },
+}
+
+/// Distance from a reference price, used by the book's price protections
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ProtectionBand {
+    /// This many ticks of the book's tick size
+    Ticks(u32),
+    /// This percentage of the reference price
+    Percent(f64),
+}
+
+impl ProtectionBand {
+    fn width(&self, reference_price: f64, tick_size: f64) -> f64 {
+        match *self {
+            ProtectionBand::Ticks(ticks) => ticks as f64 * tick_size,
+            ProtectionBand::Percent(percent) => reference_price * percent / 100.0,
+        }
+    }
+}
+
+/// What happens to the part of a market order that could not be filled on entry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MarketRemainder {
+    /// Drop the remainder: the order ends `PartiallyFilled`, or `Rejected` if nothing filled
+    #[default]
+    Cancel,
+    /// Rest the remainder as a limit order at the order's last execution price. Orders
+    /// that filled nothing are still rejected.
+    ConvertToLimit,
+}
+
+/// What `OrderBook::clear_with` keeps or resets besides the orders themselves, which are
+/// always removed. Configuration (matching policy, fees, tick size...) is always kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClearOptions {
+    /// Restart order and trade ids at 1. Kept trades may then share ids with new ones, and
+    /// `fills` no longer reports them.
+    pub reset_ids: bool,
+    /// Keep the trade log and the last trade price
+    pub keep_trades: bool,
+    /// Keep book and session statistics, session history, accrued fees and positions
+    pub keep_statistics: bool,
+}
+
+/// Quantity constraints applied to every submitted order. Zero disables a rule.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuantityRules {
+    /// Order quantities must be a whole multiple of this increment
+    pub lot_size: f64,
+    /// Smallest quantity an order may be submitted with
+    pub min_quantity: f64,
+    /// Largest quantity an order may be submitted with
+    #[serde(default)]
+    pub max_quantity: f64,
+    /// Cancel a partially filled order's remainder once it drops below `min_quantity`
+    /// instead of leaving an untradeable sliver resting in the book
+    pub cancel_dust: bool,
+}
+
+impl QuantityRules {
+    pub fn validate(&self, quantity: f64) -> Result<(), OrderError> {
+        if quantity < self.min_quantity {
+            return Err(OrderError::QuantityBelowMinimum {
+                quantity,
+                min_quantity: self.min_quantity,
+            });
+        }
+        if self.max_quantity > 0.0 && quantity > self.max_quantity {
+            return Err(OrderError::QuantityAboveMaximum {
+                quantity,
+                max_quantity: self.max_quantity,
+            });
+        }
+        if self.lot_size > 0.0 {
+            let lots = quantity / self.lot_size;
+            if (lots - lots.round()).abs() > 1e-9 * lots.max(1.0) {
+                return Err(OrderError::QuantityNotLotMultiple {
+                    quantity,
+                    lot_size: self.lot_size,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Whether a partially filled remainder should be cancelled rather than left resting
+    fn is_dust(&self, remaining_quantity: f64) -> bool {
+        self.cancel_dust && remaining_quantity > 0.0 && remaining_quantity < self.min_quantity
+    }
+}
+
+/// Valid price increments by price range, e.g. a 0.01 tick below 1.00 and 0.05 from
+/// there on. Each band `(from_price, tick_size)` applies from its price up to the next
+/// band's; prices below the first band use the first band's tick. An empty table accepts
+/// any price.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TickTable {
+    bands: Vec<(f64, f64)>,
+}
+
+impl TickTable {
+    pub fn new(mut bands: Vec<(f64, f64)>) -> Self {
+        bands.sort_by(|a, b| a.0.total_cmp(&b.0));
+        TickTable { bands }
+    }
+
+    /// A single tick size for every price
+    pub fn uniform(tick_size: f64) -> Self {
+        TickTable::new(vec![(0.0, tick_size)])
+    }
+
+    pub fn bands(&self) -> &[(f64, f64)] {
+        &self.bands
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bands.is_empty()
+    }
+
+    /// Tick size that applies at `price`, or `None` for an empty table
+    pub fn tick_size_at(&self, price: f64) -> Option<f64> {
+        let band = self
+            .bands
+            .iter()
+            .rev()
+            .find(|&&(from_price, _)| from_price <= price)
+            .or(self.bands.first())?;
+        Some(band.1)
+    }
+
+    pub fn is_on_tick(&self, price: f64) -> bool {
+        match self.tick_size_at(price) {
+            Some(tick_size) if tick_size > 0.0 => {
+                let ticks = price / tick_size;
+                (ticks - ticks.round()).abs() <= 1e-9 * ticks.abs().max(1.0)
+            }
+            _ => true,
+        }
+    }
+
+    /// Round an off-tick price to the nearest tick that is less aggressive for `side`:
+    /// down for buys, up for sells. On-tick prices are returned unchanged.
+    pub fn round_passive(&self, side: OrderSide, price: f64) -> f64 {
+        if self.is_on_tick(price) {
+            return price;
+        }
+        let tick_size = self.tick_size_at(price).unwrap();
+        let ticks = price / tick_size;
+        let ticks = match side {
+            OrderSide::Buy => ticks.floor(),
+            OrderSide::Sell => ticks.ceil(),
+        };
+        tick_multiple(ticks, tick_size)
+    }
+}
+
+// `ticks * tick_size`, computed so that e.g. 2001 ticks of 0.05 give exactly the float
+// a user would write as 100.05, and land on the same price level
+fn tick_multiple(ticks: f64, tick_size: f64) -> f64 {
+    let per_unit = 1.0 / tick_size;
+    if per_unit >= 1.0 && (per_unit - per_unit.round()).abs() < 1e-9 {
+        ticks / per_unit.round()
+    } else {
+        ticks * tick_size
+    }
+}
+
+/// What happens to an order whose limit price is not on the tick table
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OffTickPrice {
+    /// Refuse the order
+    #[default]
+    Reject,
+    /// Round the price to the nearest tick on the passive side: down for buys, up for sells
+    Round,
+}
+
+/// Reasons an order can be refused at submission
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderError {
+    QuantityBelowMinimum {
+        quantity: f64,
+        min_quantity: f64,
+    },
+    QuantityNotLotMultiple {
+        quantity: f64,
+        lot_size: f64,
+    },
+    QuantityAboveMaximum {
+        quantity: f64,
+        max_quantity: f64,
+    },
+    AlreadyExpired {
+        expire_at: u64,
+        timestamp: u64,
+    },
+    InvalidDisplayQuantity {
+        display_quantity: f64,
+        quantity: f64,
+    },
+    InvalidBracket {
+        take_profit: f64,
+        stop_loss: f64,
+    },
+    InvalidMinExecutionQuantity {
+        min_execution_quantity: f64,
+        quantity: f64,
+    },
+    ReduceOnlyWithoutOwner,
+    CrossedQuote {
+        bid_price: f64,
+        ask_price: f64,
+    },
+    QuoteSetRejected,
+    InvalidAmendQuantity {
+        quantity: f64,
+        filled_quantity: f64,
+    },
+    DuplicateOrderId {
+        order_id: u64,
+    },
+    PriceOffTick {
+        price: f64,
+        tick_size: f64,
+    },
+    MissingLimitPrice,
+    Refused {
+        reason: RejectReason,
+    },
+}
+
+impl std::fmt::Display for OrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderError::QuantityBelowMinimum {
+                quantity,
+                min_quantity,
+            } => write!(
+                f,
+                "quantity {quantity} is below the minimum order quantity {min_quantity}"
+            ),
+            OrderError::QuantityNotLotMultiple { quantity, lot_size } => {
+                write!(
+                    f,
+                    "quantity {quantity} is not a multiple of lot size {lot_size}"
+                )
+            }
+            OrderError::QuantityAboveMaximum {
+                quantity,
+                max_quantity,
+            } => write!(
+                f,
+                "quantity {quantity} is above the maximum order quantity {max_quantity}"
+            ),
+            OrderError::AlreadyExpired {
+                expire_at,
+                timestamp,
+            } => write!(
+                f,
+                "order expires at {expire_at}, which is not after its timestamp {timestamp}"
+            ),
+            OrderError::InvalidDisplayQuantity {
+                display_quantity,
+                quantity,
+            } => write!(
+                f,
+                "display quantity {display_quantity} must be positive and at most the order quantity {quantity}"
+            ),
+            OrderError::InvalidBracket {
+                take_profit,
+                stop_loss,
+            } => write!(
+                f,
+                "take-profit {take_profit} and stop-loss {stop_loss} are on the wrong sides of each other"
+            ),
+            OrderError::InvalidMinExecutionQuantity {
+                min_execution_quantity,
+                quantity,
+            } => write!(
+                f,
+                "minimum execution quantity {min_execution_quantity} must be positive and at most the order quantity {quantity}"
+            ),
+            OrderError::ReduceOnlyWithoutOwner => {
+                write!(f, "reduce-only orders need an owner_id to track a position")
+            }
+            OrderError::CrossedQuote {
+                bid_price,
+                ask_price,
+            } => write!(
+                f,
+                "quote bid {bid_price} must be below its ask {ask_price}"
+            ),
+            OrderError::QuoteSetRejected => {
+                write!(f, "another quote in the all-or-nothing set was rejected")
+            }
+            OrderError::InvalidAmendQuantity {
+                quantity,
+                filled_quantity,
+            } => write!(
+                f,
+                "amended quantity {quantity} must exceed the filled quantity {filled_quantity}"
+            ),
+            OrderError::DuplicateOrderId { order_id } => {
+                write!(f, "order id {order_id} is already in use")
+            }
+            OrderError::PriceOffTick { price, tick_size } => {
+                write!(f, "price {price} is not a multiple of tick size {tick_size}")
+            }
+            OrderError::MissingLimitPrice => write!(f, "limit orders require a price"),
+            OrderError::Refused { reason } => write!(f, "order refused: {reason:?}"),
+        }
+    }
+}
+
+impl OrderError {
+    /// Structured reason for the rejection, matching what a rejected order would carry
+    pub fn reject_reason(&self) -> RejectReason {
+        match self {
+            OrderError::QuantityBelowMinimum { .. }
+            | OrderError::QuantityNotLotMultiple { .. }
+            | OrderError::QuantityAboveMaximum { .. }
+            | OrderError::InvalidDisplayQuantity { .. }
+            | OrderError::InvalidMinExecutionQuantity { .. }
+            | OrderError::InvalidAmendQuantity { .. } => RejectReason::InvalidQuantity,
+            OrderError::InvalidBracket { .. }
+            | OrderError::CrossedQuote { .. }
+            | OrderError::PriceOffTick { .. }
+            | OrderError::MissingLimitPrice => RejectReason::InvalidPrice,
+            OrderError::AlreadyExpired { .. } => RejectReason::AlreadyExpired,
+            OrderError::ReduceOnlyWithoutOwner => RejectReason::MissingOwner,
+            OrderError::QuoteSetRejected => RejectReason::QuoteSetRejected,
+            OrderError::DuplicateOrderId { .. } => RejectReason::DuplicateOrderId,
+            OrderError::Refused { reason } => *reason,
+        }
+    }
+}
+
+impl std::error::Error for OrderError {}
+
+impl From<OrderError> for PyErr {
+    fn from(err: OrderError) -> Self {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+/// Broken book invariant found by `OrderBook::verify_integrity`
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntegrityError {
+    CrossedBook {
+        best_bid: f64,
+        best_ask: f64,
+    },
+    EmptyPriceLevel {
+        side: OrderSide,
+        price: f64,
+    },
+    /// The order id index and the price levels disagree about where an order rests
+    IndexMismatch {
+        order_id: u64,
+    },
+    QuantityNotConserved {
+        order_id: u64,
+        quantity: f64,
+        filled_quantity: f64,
+        remaining_quantity: f64,
+    },
+    /// The trade log holds more executions of an order than it has filled
+    OverFilled {
+        order_id: u64,
+        filled_quantity: f64,
+        traded_quantity: f64,
+    },
+}
+
+impl std::fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntegrityError::CrossedBook { best_bid, best_ask } => {
+                write!(f, "best bid {best_bid} is not below best ask {best_ask}")
+            }
+            IntegrityError::EmptyPriceLevel { side, price } => {
+                write!(f, "{side:?} price level {price} has no orders")
+            }
+            IntegrityError::IndexMismatch { order_id } => {
+                write!(f, "order {order_id} is not indexed where it rests")
+            }
+            IntegrityError::QuantityNotConserved {
+                order_id,
+                quantity,
+                filled_quantity,
+                remaining_quantity,
+            } => write!(
+                f,
+                "order {order_id} has filled {filled_quantity} and {remaining_quantity} remaining out of {quantity}"
+            ),
+            IntegrityError::OverFilled {
+                order_id,
+                filled_quantity,
+                traded_quantity,
+            } => write!(
+                f,
+                "order {order_id} traded {traded_quantity} but only filled {filled_quantity}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IntegrityError {}
+
+impl From<IntegrityError> for PyErr {
+    fn from(err: IntegrityError) -> Self {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+/// Optional per-order instructions supplied at submission
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OrderOptions {
+    /// Market orders only: cancel any quantity that would execute further than this many
+    /// basis points away from the best opposite price at order entry
+    pub max_slippage_bps: Option<f64>,
+    /// Market orders only: cancel any quantity that would execute at a worse price than
+    /// this (above it for buys, below it for sells), like a marketable limit order that
+    /// never rests
+    pub worst_price: Option<f64>,
+    /// Limit orders only: reject instead of matching if the order would cross the spread
+    pub post_only: bool,
+    /// Post-only orders only: instead of rejecting a crossing order, reprice it to join the
+    /// best price on its own side. Still rejected if its own side is empty.
+    pub post_only_reprice: bool,
+    /// Participant that owns the order, used for owner-scoped operations
+    pub owner_id: Option<u64>,
+    /// Caller-assigned reference (FIX clOrdID), unique per owner, that the order can be
+    /// queried, amended and cancelled by instead of its engine id
+    pub client_order_id: Option<String>,
+    /// Client session (see `OrderBook::open_session`) the order was submitted under. The
+    /// order is cancelled when the session closes or misses its heartbeat.
+    pub session_id: Option<u64>,
+    /// Enter the order under this id instead of the next engine id, e.g. to keep the
+    /// native ids of replayed historical data. Refused if the id belongs to a live order
+    /// or one still in the finished order cache; engine ids continue after the highest
+    /// id used.
+    pub order_id: Option<u64>,
+    pub time_in_force: TimeInForce,
+    /// Hold the order until its trigger price reaches this stop price (at or above for
+    /// buys, at or below for sells). A market order then executes immediately; a limit
+    /// order enters the book at its limit price.
+    pub stop_price: Option<f64>,
+    /// Stop orders only: the price compared with `stop_price`, re-evaluated whenever the
+    /// book changes
+    pub stop_trigger: StopTrigger,
+    /// Stop orders only: trigger when the price reaches `stop_price` from the other
+    /// direction (at or below for buys, at or above for sells), making a market-if-touched
+    /// or limit-if-touched order
+    pub if_touched: bool,
+    /// Hold the order until this condition on the book holds, re-evaluated whenever the
+    /// book changes, then submit it (as a stop order if it also has a `stop_price`)
+    pub condition: Option<BookCondition>,
+    /// Limit orders only: show at most this much of the order at a time (iceberg). When
+    /// the visible tranche is filled it is refreshed from the hidden reserve and requeued
+    /// according to the book's `IcebergRefill`.
+    pub display_quantity: Option<f64>,
+    /// Limit orders only: work at the `Peg` reference price instead of the order's price,
+    /// which becomes a cap the working price never goes through. Repriced whenever the
+    /// reference moves; until a reference exists the order works at its cap.
+    pub peg: Option<Peg>,
+    /// Once this entry order finishes with a fill, submit a take-profit limit and a
+    /// stop-loss stop-market order on the opposite side for the filled quantity, linked
+    /// as an OCO pair
+    pub bracket: Option<Bracket>,
+    /// Limit orders only: trade normally but never appear in depth snapshots, with
+    /// queue priority set by the book's `HiddenPriority`
+    pub hidden: bool,
+    /// Smallest quantity any single execution against this order may have (or all of
+    /// what is left, if less). On entry the order must trade at least this much if it
+    /// trades at all; while resting, counterparties that cannot meet it are passed over.
+    pub min_execution_quantity: Option<f64>,
+    /// All-or-none: only trade when the whole remaining quantity can be filled by a
+    /// single incoming order. Rests if it cannot trade on entry, but is rejected if it
+    /// would cross the spread without filling completely.
+    pub all_or_none: bool,
+    /// Market orders only: how to handle quantity left over after matching
+    pub market_remainder: MarketRemainder,
+    /// Only ever reduce the owner's open position: on entry the quantity is trimmed to
+    /// the position, or the order is rejected if it would only add to it, and resting
+    /// fills are capped at the position at the time. Requires `owner_id`.
+    pub reduce_only: bool,
+    /// Only trade in auction uncrossing: the order is held out of continuous matching and
+    /// never rests in the visible book, waiting for an auction to run
+    pub auction_only: bool,
+    /// On-open (OPG) or on-close (CLS) designation: like `auction_only`, but only for that
+    /// auction. Expires if the auction runs without it trading and is rejected on entry
+    /// once the auction has run.
+    pub auction_target: Option<AuctionSession>,
+    /// Limit orders only: hidden offset by which the order may trade through its displayed
+    /// price (higher for buys, lower for sells), both when it enters and against later
+    /// incoming limit orders. Depth only ever shows the displayed price.
+    pub discretion: Option<f64>,
+    /// Priority participant (e.g. designated market maker) order: served from the book's
+    /// `priority_share` of each incoming order at its price level before normal matching
+    pub priority: bool,
+    /// Sell orders only: short sale, restricted by the book's `ShortSaleRule`
+    pub short_sale: bool,
+}
+
+/// Order struct representing a single order in the order book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Order {
+    pub id: u64,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub price: Option<f64>, // None for market orders
+    pub quantity: f64,
+    pub filled_quantity: f64,
+    pub status: OrderStatus,
+    pub timestamp: u64,
+    pub symbol: Option<String>,
+    // Cache remaining quantity for performance
+    pub remaining_quantity: f64,
+    #[serde(default)]
+    pub options: OrderOptions,
+    // Unfilled part of the currently displayed iceberg tranche
+    #[serde(default)]
+    pub display_remaining: f64,
+    // Limit price of a pegged order; `price` holds its current working price
+    #[serde(default)]
+    pub peg_limit: Option<f64>,
+    #[serde(default)]
+    pub reject_reason: Option<RejectReason>,
+    // Number of amendments applied by `modify_order`
+    #[serde(default)]
+    pub version: u64,
+}
+
+impl Order {
+    pub fn new(
+        id: u64,
+        side: OrderSide,
+        order_type: OrderType,
+        price: Option<f64>,
+        quantity: f64,
+        timestamp: u64,
+        symbol: Option<String>,
+    ) -> Self {
+        Order {
+            id,
+            side,
+            order_type,
+            price,
+            quantity,
+            filled_quantity: 0.0,
+            status: OrderStatus::New,
+            timestamp,
+            symbol,
+            remaining_quantity: quantity,
+            options: OrderOptions::default(),
+            display_remaining: 0.0,
+            peg_limit: None,
+            reject_reason: None,
+            version: 0,
+        }
+    }
+
+    fn is_short_sale(&self) -> bool {
+        self.side == OrderSide::Sell && self.options.short_sale
+    }
+
+    // Most a resting order can trade before an iceberg refresh
+    fn matchable_quantity(&self) -> f64 {
+        match self.options.display_quantity {
+            Some(_) => self.display_remaining,
+            None => self.remaining_quantity,
+        }
+    }
+
+    // Most aggressive price a discretionary limit order is willing to trade at
+    fn discretion_price(&self) -> Option<f64> {
+        let price = self.price?;
+        let discretion = self.options.discretion?;
+        Some(match self.side {
+            OrderSide::Buy => price + discretion,
+            OrderSide::Sell => price - discretion,
+        })
+    }
+
+    // Whether a stop order triggers on a rising price rather than a falling one
+    fn triggers_on_rise(&self) -> bool {
+        (self.side == OrderSide::Buy) != self.options.if_touched
+    }
+
+    // Quantity shown in depth: the matchable quantity unless the order is hidden
+    fn visible_quantity(&self) -> f64 {
+        if self.options.hidden {
+            0.0
+        } else {
+            self.matchable_quantity()
+        }
+    }
+
+    // Smallest quantity a single execution against this order may have
+    fn min_execution_quantity(&self) -> Option<f64> {
+        if self.options.all_or_none {
+            Some(self.remaining_quantity)
+        } else {
+            self.options.min_execution_quantity
+        }
+    }
+
+    // Show the next iceberg tranche out of the hidden reserve
+    fn refresh_display(&mut self) {
+        if let Some(display_quantity) = self.options.display_quantity {
+            self.display_remaining = display_quantity.min(self.remaining_quantity);
+        }
+    }
+
+    // Apply a fill and update the order status accordingly. With `quantity_decimals` the
+    // quantities are updated in whole units, so filled and remaining quantity always add
+    // up to the order quantity exactly.
+    fn fill(&mut self, quantity: f64, quantity_decimals: Option<u32>) {
+        self.filled_quantity = add_quantities(self.filled_quantity, quantity, quantity_decimals);
+        self.remaining_quantity =
+            add_quantities(self.remaining_quantity, -quantity, quantity_decimals);
+        self.display_remaining =
+            add_quantities(self.display_remaining, -quantity, quantity_decimals).max(0.0);
+        if let Some(decimals) = quantity_decimals {
+            debug_assert_eq!(
+                quantity_units(self.filled_quantity, decimals)
+                    + quantity_units(self.remaining_quantity, decimals),
+                quantity_units(self.quantity, decimals),
+                "order {} does not conserve its quantity",
+                self.id
+            );
+        }
+
+        if self.filled_quantity >= self.quantity {
+            self.status = OrderStatus::Filled;
+        } else if self.filled_quantity > 0.0 {
+            self.status = OrderStatus::PartiallyFilled;
+        }
+    }
+}
+
+/// Point-in-time view of an order's state, returned by order queries
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderView {
+    pub id: u64,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub price: Option<f64>,
+    pub quantity: f64,
+    pub filled_quantity: f64,
+    pub remaining_quantity: f64,
+    pub status: OrderStatus,
+    pub timestamp: u64,
+    pub symbol: Option<String>,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    #[serde(default)]
+    pub reject_reason: Option<RejectReason>,
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+    #[serde(default)]
+    pub owner_id: Option<u64>,
+    /// Amendment revision, starting at 0 and incremented by each `modify_order`
+    #[serde(default)]
+    pub version: u64,
+}
+
+impl From<&Order> for OrderView {
+    fn from(order: &Order) -> Self {
+        OrderView {
+            id: order.id,
+            side: order.side,
+            order_type: order.order_type,
+            price: order.price,
+            quantity: order.quantity,
+            filled_quantity: order.filled_quantity,
+            remaining_quantity: order.remaining_quantity,
+            status: order.status,
+            timestamp: order.timestamp,
+            symbol: order.symbol.clone(),
+            time_in_force: order.options.time_in_force,
+            reject_reason: order.reject_reason,
+            client_order_id: order.options.client_order_id.clone(),
+            owner_id: order.options.owner_id,
+            version: order.version,
         }
     }
+}
+
+// Trades serialized before the aggressor was recorded read back as buyer-initiated
+fn default_aggressor_side() -> OrderSide {
+    OrderSide::Buy
+}
+
+/// Trade struct representing a single trade
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trade {
+    pub id: u64,
+    pub buy_order_id: u64,
+    pub sell_order_id: u64,
+    pub price: f64,
+    pub quantity: f64,
+    pub timestamp: u64,
+    pub symbol: Option<String>,
+    pub maker_fee: f64, // Fee charged to the resting order
+    pub taker_fee: f64, // Fee charged to the incoming order
+    #[serde(default)]
+    pub buyer_owner_id: Option<u64>,
+    #[serde(default)]
+    pub seller_owner_id: Option<u64>,
+    /// Cancelled after the fact by `OrderBook::bust_trade`
+    #[serde(default)]
+    pub busted: bool,
+    /// Side of the incoming order that took liquidity
+    #[serde(default = "default_aggressor_side")]
+    pub aggressor_side: OrderSide,
+    /// The resting order that provided liquidity, 0 for trades recorded before it was
+    #[serde(default)]
+    pub maker_order_id: u64,
+    /// The incoming order that took liquidity, 0 for trades recorded before it was
+    #[serde(default)]
+    pub taker_order_id: u64,
+    /// Buyer and seller are the same owner or in the same account group
+    #[serde(default)]
+    pub wash_trade: bool,
+    /// Limit price of the aggressor, `None` for market orders
+    #[serde(default)]
+    pub aggressor_limit_price: Option<f64>,
+    /// Trade report conditions, see `TradeFlags`
+    #[serde(default)]
+    pub flags: TradeFlags,
+    /// Price times quantity times the book's contract multiplier, in the quote currency
+    #[serde(default)]
+    pub notional: f64,
+}
+
+impl Trade {
+    /// Whether `order_id` provided or took liquidity in this trade, or `None` if the order
+    /// is not part of it
+    pub fn liquidity(&self, order_id: u64) -> Option<Liquidity> {
+        if order_id == self.maker_order_id {
+            Some(Liquidity::Maker)
+        } else if order_id == self.taker_order_id {
+            Some(Liquidity::Taker)
+        } else {
+            None
+        }
+    }
+
+    /// How much better than its limit price the aggressor traded, per unit of quantity,
+    /// or `None` for a market order
+    pub fn price_improvement(&self) -> Option<f64> {
+        let limit = price_units(self.aggressor_limit_price?);
+        let price = price_units(self.price);
+        Some(units_price(match self.aggressor_side {
+            OrderSide::Buy => limit - price,
+            OrderSide::Sell => price - limit,
+        }))
+    }
+}
+
+/// Set of conditions attached to a trade report, stored as a bitfield
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TradeFlags(u32);
+
+impl TradeFlags {
+    pub const NONE: TradeFlags = TradeFlags(0);
+    /// Executed in an auction uncross rather than continuous matching
+    pub const AUCTION: TradeFlags = TradeFlags(1 << 0);
+    /// Executed at a resting order's discretion price rather than its displayed price
+    pub const DISCRETION: TradeFlags = TradeFlags(1 << 1);
+    /// Same beneficial owner on both sides, see `Trade::wash_trade`
+    pub const WASH_TRADE: TradeFlags = TradeFlags(1 << 2);
+    /// One side is an implied order of a `SpreadMarket` or its leg execution
+    pub const IMPLIED: TradeFlags = TradeFlags(1 << 3);
+    /// Cancelled after the fact by `OrderBook::bust_trade`
+    pub const BUSTED: TradeFlags = TradeFlags(1 << 4);
+
+    pub const fn from_bits(bits: u32) -> Self {
+        TradeFlags(bits)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every flag of `other` is set
+    pub const fn contains(self, other: TradeFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: TradeFlags) {
+        self.0 |= other.0;
+    }
+}
+
+impl std::ops::BitOr for TradeFlags {
+    type Output = TradeFlags;
+
+    fn bitor(self, other: TradeFlags) -> TradeFlags {
+        TradeFlags(self.0 | other.0)
+    }
+}
+
+impl std::ops::BitOrAssign for TradeFlags {
+    fn bitor_assign(&mut self, other: TradeFlags) {
+        self.insert(other);
+    }
+}
+
+/// Role of an order in a trade
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Liquidity {
+    /// Resting order that provided liquidity
+    Maker,
+    /// Incoming order that took liquidity
+    Taker,
+}
+
+/// Kind of instrument a book trades
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssetClass {
+    #[default]
+    Equity,
+    Future,
+    Option,
+    Fx,
+    Crypto,
+}
+
+fn default_contract_multiplier() -> f64 {
+    1.0
+}
+
+/// Description of the instrument a book trades. The contract multiplier scales trade
+/// notional, and with it fees and notional-sized market orders.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstrumentMetadata {
+    /// Currency prices are quoted in, if known
+    #[serde(default)]
+    pub quote_currency: Option<String>,
+    /// Units of the underlying per unit of quantity, e.g. 50 for a future on an index
+    /// at $50 per point. Must be positive.
+    #[serde(default = "default_contract_multiplier")]
+    pub contract_multiplier: f64,
+    #[serde(default)]
+    pub asset_class: AssetClass,
+}
+
+impl Default for InstrumentMetadata {
+    fn default() -> Self {
+        InstrumentMetadata {
+            quote_currency: None,
+            contract_multiplier: default_contract_multiplier(),
+            asset_class: AssetClass::default(),
+        }
+    }
+}
+
+/// Maker/taker fee rates in basis points of trade notional
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    pub maker_bps: f64,
+    pub taker_bps: f64,
+}
+
+/// How an incoming order's quantity is shared among resting orders at one price level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum MatchingPolicy {
+    /// Fill resting orders strictly in time priority (FIFO)
+    #[default]
+    PriceTime,
+    /// Allocate proportionally to each resting order's remaining quantity
+    ProRata,
+    /// Give the first order in the queue `top_order_share` (clamped to 0..=1) of the
+    /// incoming quantity, then allocate the rest pro-rata among all orders including
+    /// what is left of the top order
+    TopOrderProRata { top_order_share: f64 },
+}
+
+/// Participant guaranteed a share of incoming marketable flow at the best price
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LeadMarketMaker {
+    pub owner_id: u64,
+    /// Fraction (clamped to 0..=1) of each incoming order the participant's orders at the
+    /// best price are filled from, in time priority, before any other allocation
+    pub share: f64,
+}
+
+/// Where hidden orders queue relative to displayed orders at the same price
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HiddenPriority {
+    /// Hidden orders only trade once every displayed order at the price is filled
+    #[default]
+    BehindDisplayed,
+    /// Hidden orders keep their time priority alongside displayed orders
+    TimePriority,
+}
+
+/// Where an iceberg order queues once its visible tranche is refreshed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IcebergRefill {
+    /// The refreshed tranche loses time priority and moves to the back of its level
+    #[default]
+    BackOfLevel,
+    /// The refreshed tranche keeps the order's original place in the queue
+    KeepPriority,
+}
+
+impl MatchingPolicy {
+    /// Quantity to fill against each resting order given the quantity each one has
+    /// `available`, in queue order.
+    ///
+    /// Pro-rata shares are rounded down to whole units; the leftover (including any
+    /// fractional remainder) is then handed out in time priority.
+    pub fn allocate(&self, incoming_quantity: f64, available: &[f64]) -> Vec<f64> {
+        self.allocate_in_lots(incoming_quantity, available, 1.0)
+    }
+
+    /// Like `allocate`, but pro-rata and top-order shares are rounded down to whole
+    /// multiples of `lot_size` (left unrounded if it is not positive) so no resting order
+    /// is left with a fraction of a lot. Shares within a rounding error of a lot boundary
+    /// count as reaching it. Orders whose share rounds down to nothing only trade from
+    /// the leftover, which still goes in time priority and so always fills the incoming
+    /// quantity as far as `available` allows.
+    pub fn allocate_in_lots(
+        &self,
+        incoming_quantity: f64,
+        available: &[f64],
+        lot_size: f64,
+    ) -> Vec<f64> {
+        let mut allocations = match *self {
+            MatchingPolicy::PriceTime => vec![0.0; available.len()],
+            MatchingPolicy::ProRata => pro_rata_shares(incoming_quantity, available, lot_size),
+            MatchingPolicy::TopOrderProRata { top_order_share } => {
+                let mut allocations = vec![0.0; available.len()];
+                let mut rest = available.to_vec();
+                let mut incoming_quantity = incoming_quantity;
+                if let Some(top) = available.iter().position(|&quantity| quantity > 0.0) {
+                    let share = incoming_quantity * top_order_share.clamp(0.0, 1.0);
+                    let share = round_down_to_lot(share, lot_size).min(available[top]);
+                    allocations[top] = share;
+                    rest[top] -= share;
+                    incoming_quantity -= share;
+                }
+                let shares = pro_rata_shares(incoming_quantity, &rest, lot_size);
+                for (allocation, share) in allocations.iter_mut().zip(shares) {
+                    *allocation += share;
+                }
+                allocations
+            }
+        };
+
+        // Distribute whatever is left in time priority
+        let mut leftover = incoming_quantity - allocations.iter().sum::<f64>();
+        for (allocation, quantity) in allocations.iter_mut().zip(available) {
+            if leftover <= 0.0 {
+                break;
+            }
+            let extra = (quantity - *allocation).min(leftover);
+            *allocation += extra;
+            leftover -= extra;
+        }
+
+        allocations
+    }
+}
+
+// Each order's pro-rata share of the incoming quantity, rounded down to the lot size.
+// Nothing is shared out if the incoming quantity can fill every order anyway.
+fn pro_rata_shares(incoming_quantity: f64, available: &[f64], lot_size: f64) -> Vec<f64> {
+    let total_quantity: f64 = available.iter().sum();
+    if incoming_quantity >= total_quantity {
+        return vec![0.0; available.len()];
+    }
+    available
+        .iter()
+        .map(|quantity| round_down_to_lot(incoming_quantity * quantity / total_quantity, lot_size))
+        .collect()
+}
+
+// A quantity as a whole number of units of `decimals` decimal places
+fn quantity_units(quantity: f64, decimals: u32) -> i64 {
+    (quantity * 10f64.powi(decimals as i32)).round() as i64
+}
+
+fn units_quantity(units: i64, decimals: u32) -> f64 {
+    units as f64 / 10f64.powi(decimals as i32)
+}
+
+// Quantities kept to fixed decimals must be a whole number of units
+fn validate_quantity_decimals(quantity: f64, decimals: Option<u32>) -> Result<(), OrderError> {
+    let Some(decimals) = decimals else {
+        return Ok(());
+    };
+    if units_quantity(quantity_units(quantity, decimals), decimals) != quantity {
+        return Err(OrderError::QuantityNotLotMultiple {
+            quantity,
+            lot_size: units_quantity(1, decimals),
+        });
+    }
+    Ok(())
+}
+
+// Limit orders must carry a price
+fn validate_limit_price(order_type: OrderType, price: Option<f64>) -> Result<(), OrderError> {
+    if order_type == OrderType::Limit && price.is_none() {
+        return Err(OrderError::MissingLimitPrice);
+    }
+    Ok(())
+}
+
+// `a + b`, computed in whole units when quantities are kept to fixed decimals
+fn add_quantities(a: f64, b: f64, decimals: Option<u32>) -> f64 {
+    match decimals {
+        Some(decimals) => units_quantity(
+            quantity_units(a, decimals) + quantity_units(b, decimals),
+            decimals,
+        ),
+        None => a + b,
+    }
+}
+
+// Shares within a rounding error of a lot boundary count as reaching it
+fn round_down_to_lot(quantity: f64, lot_size: f64) -> f64 {
+    if lot_size > 0.0 {
+        (quantity / lot_size + 1e-9).floor() * lot_size
+    } else {
+        quantity
+    }
+}
+
+/// PriceLevel struct for aggregating orders at the same price
+#[derive(Debug, Clone)]
+pub struct PriceLevel {
+    pub price: f64,
+    pub orders: Vec<Order>,
+    pub total_quantity_cache: f64,
+    // Remaining quantity including hidden orders and iceberg reserve
+    pub full_quantity_cache: f64,
+    pub is_dirty: bool,
+}
+
+impl PriceLevel {
+    pub fn new(price: f64) -> Self {
+        PriceLevel {
+            price,
+            orders: Vec::with_capacity(16), // Pre-allocate to avoid frequent reallocations
+            total_quantity_cache: 0.0,
+            full_quantity_cache: 0.0,
+            is_dirty: false,
+        }
+    }
+
+    pub fn add_order(&mut self, order: Order) {
+        self.total_quantity_cache += order.visible_quantity();
+        self.full_quantity_cache += order.remaining_quantity;
+        self.orders.push(order);
+    }
+
+    pub fn remove_order(&mut self, order_id: u64) -> Option<Order> {
+        if let Some(pos) = self.orders.iter().position(|o| o.id == order_id) {
+            // Shift the rest down rather than swap_remove so the queue stays in time priority
+            let order = self.orders.remove(pos);
+            self.is_dirty = true;
+            Some(order)
+        } else {
+            None
+        }
+    }
+
+    pub fn update_quantity_cache(&mut self) {
+        if self.is_dirty {
+            self.total_quantity_cache = self.orders.iter().map(|o| o.visible_quantity()).sum();
+            self.full_quantity_cache = self.orders.iter().map(|o| o.remaining_quantity).sum();
+            self.is_dirty = false;
+        }
+    }
+
+    /// Displayed quantity: hidden orders and iceberg reserve are left out
+    pub fn total_quantity(&mut self) -> f64 {
+        self.update_quantity_cache();
+        self.total_quantity_cache
+    }
+
+    /// True remaining quantity, including hidden orders and iceberg reserve
+    pub fn full_quantity(&mut self) -> f64 {
+        self.update_quantity_cache();
+        self.full_quantity_cache
+    }
+
+    // Read-only variant of `total_quantity` that sums on the fly if the cache is stale
+    fn current_quantity(&self) -> f64 {
+        if self.is_dirty {
+            self.orders.iter().map(|o| o.visible_quantity()).sum()
+        } else {
+            self.total_quantity_cache
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    fn has_displayed_orders(&self) -> bool {
+        self.orders.iter().any(|o| !o.options.hidden)
+    }
+}
+
+/// Bid and ask of a participant's two-sided quote
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Quote {
+    pub bid_price: f64,
+    pub bid_quantity: f64,
+    pub ask_price: f64,
+    pub ask_quantity: f64,
+}
+
+/// How `OrderBook::mass_quote` handles a set containing invalid quotes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MassQuoteMode {
+    /// Reject every quote in the set if any one of them is invalid
+    AllOrNothing,
+    /// Enter the valid quotes and reject only the invalid ones
+    BestEffort,
+}
+
+/// Take-profit and stop-loss prices for the exit orders of a bracket
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bracket {
+    pub take_profit: f64,
+    pub stop_loss: f64,
+}
+
+/// Links between orders whose lifecycles depend on each other
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OrderLinks {
+    oco: HashMap<u64, u64>,
+    // Bracket parents that have not finished yet, and the (take-profit, stop-loss)
+    // exits spawned for finished ones
+    bracket_parents: BTreeSet<u64>,
+    bracket_children: BTreeMap<u64, (u64, u64)>,
+    // Work queued by the matching path for `OrderBook::settle`
+    #[serde(skip)]
+    pending_cancels: Vec<u64>,
+    #[serde(skip)]
+    pending_brackets: Vec<Order>,
+}
+
+impl OrderLinks {
+    fn link_oco(&mut self, first: u64, second: u64) {
+        self.oco.insert(first, second);
+        self.oco.insert(second, first);
+    }
+
+    // Called whenever an order trades or leaves the book: break its OCO link and queue
+    // the sibling for cancellation, and queue the exits of a bracket parent that finished
+    // with something filled
+    fn touch(&mut self, order: &Order) {
+        if let Some(sibling) = self.oco.remove(&order.id) {
+            self.oco.remove(&sibling);
+            self.pending_cancels.push(sibling);
+        }
+
+        let finished = matches!(
+            order.status,
+            OrderStatus::Filled | OrderStatus::Cancelled | OrderStatus::Rejected
+        );
+        if finished && self.bracket_parents.remove(&order.id) && order.filled_quantity > 0.0 {
+            self.pending_brackets.push(order.clone());
+        }
+    }
+
+    // Carry an order's links over to the order replacing it
+    fn replace(&mut self, old_id: u64, new_id: u64) {
+        if let Some(sibling) = self.oco.remove(&old_id) {
+            self.link_oco(new_id, sibling);
+        }
+        if self.bracket_parents.remove(&old_id) {
+            self.bracket_parents.insert(new_id);
+        }
+    }
+
+    fn is_settled(&self) -> bool {
+        self.pending_cancels.is_empty() && self.pending_brackets.is_empty()
+    }
+}
+
+/// Resting order ids by owner, for participant-wide operations
+#[derive(Debug, Clone, Default)]
+struct OwnerIndex(HashMap<u64, BTreeSet<u64>>);
+
+impl OwnerIndex {
+    fn insert(&mut self, order: &Order) {
+        if let Some(owner_id) = order.options.owner_id {
+            self.0.entry(owner_id).or_default().insert(order.id);
+        }
+    }
+
+    fn remove(&mut self, order: &Order) {
+        let Some(owner_id) = order.options.owner_id else {
+            return;
+        };
+        if let Some(ids) = self.0.get_mut(&owner_id) {
+            ids.remove(&order.id);
+            if ids.is_empty() {
+                self.0.remove(&owner_id);
+            }
+        }
+    }
+
+    fn ids(&self, owner_id: u64) -> impl Iterator<Item = u64> + '_ {
+        self.0.get(&owner_id).into_iter().flatten().copied()
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Reference prices stop and if-touched orders trigger against
+#[derive(Debug, Clone, Copy)]
+struct TriggerPrices {
+    last_trade: Option<f64>,
+    best_bid: Option<f64>,
+    best_ask: Option<f64>,
+}
+
+impl TriggerPrices {
+    fn get(&self, trigger: StopTrigger) -> Option<f64> {
+        match trigger {
+            StopTrigger::LastTrade => self.last_trade,
+            StopTrigger::BestBid => self.best_bid,
+            StopTrigger::BestAsk => self.best_ask,
+            StopTrigger::Midpoint => Some((self.best_bid? + self.best_ask?) / 2.0),
+        }
+    }
+}
+
+/// Orders waiting for a trigger before they enter the book: stop and if-touched orders
+/// keyed by stop price, and conditional orders waiting for their book condition
+#[derive(Debug, Clone, Default)]
+struct TriggerBook {
+    // Stop and if-touched orders, split by the direction the trigger price has to move
+    // and keyed so they are checked in trigger order
+    rising: BTreeMap<i64, Vec<Order>>, // Stop price key, lowest stop first
+    falling: BTreeMap<i64, Vec<Order>>, // Negated stop price key, highest stop first
+    // Conditional orders in submission order
+    conditional: Vec<Order>,
+}
+
+impl TriggerBook {
+    fn insert_stop(&mut self, order: Order) {
+        let stop_price = order.options.stop_price.unwrap();
+        if order.triggers_on_rise() {
+            self.rising
+                .entry(OrderBook::price_to_key(stop_price, false))
+                .or_default()
+                .push(order);
+        } else {
+            self.falling
+                .entry(OrderBook::price_to_key(stop_price, true))
+                .or_default()
+                .push(order);
+        }
+    }
+
+    fn insert_conditional(&mut self, order: Order) {
+        self.conditional.push(order);
+    }
+
+    fn stop_orders(&self) -> impl Iterator<Item = &Order> {
+        self.rising.values().chain(self.falling.values()).flatten()
+    }
+
+    fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.stop_orders().chain(&self.conditional)
+    }
+
+    fn remove(&mut self, order_id: u64) -> Option<Order> {
+        for stops in [&mut self.rising, &mut self.falling] {
+            let found = stops.iter().find_map(|(&key, orders)| {
+                let pos = orders.iter().position(|o| o.id == order_id)?;
+                Some((key, pos))
+            });
+            if let Some((key, pos)) = found {
+                return Some(Self::take_stop(stops, key, pos));
+            }
+        }
+        let pos = self.conditional.iter().position(|o| o.id == order_id)?;
+        Some(self.conditional.remove(pos))
+    }
+
+    // Take the first stop whose trigger price has reached its stop price, if any
+    fn next_triggered_stop(&mut self, prices: TriggerPrices) -> Option<Order> {
+        // No stop can trigger beyond the most extreme trigger price available
+        let references = [prices.last_trade, prices.best_bid, prices.best_ask];
+        let highest = references.into_iter().flatten().reduce(f64::max)?;
+        let lowest = references.into_iter().flatten().reduce(f64::min)?;
+
+        // Buy stops and sell if-touched orders trigger once their trigger price is at or
+        // above the stop, lowest stop first
+        let triggered = self
+            .rising
+            .iter()
+            .take_while(|(&key, _)| OrderBook::key_to_price(key, false) <= highest)
+            .find_map(|(&key, orders)| {
+                let stop_price = OrderBook::key_to_price(key, false);
+                let pos = orders.iter().position(|o| {
+                    prices
+                        .get(o.options.stop_trigger)
+                        .is_some_and(|price| price >= stop_price)
+                })?;
+                Some((key, pos))
+            });
+        if let Some((key, pos)) = triggered {
+            return Some(Self::take_stop(&mut self.rising, key, pos));
+        }
+
+        // Sell stops and buy if-touched orders trigger once their trigger price is at or
+        // below the stop, highest stop first
+        let triggered = self
+            .falling
+            .iter()
+            .take_while(|(&key, _)| OrderBook::key_to_price(key, true) >= lowest)
+            .find_map(|(&key, orders)| {
+                let stop_price = OrderBook::key_to_price(key, true);
+                let pos = orders.iter().position(|o| {
+                    prices
+                        .get(o.options.stop_trigger)
+                        .is_some_and(|price| price <= stop_price)
+                })?;
+                Some((key, pos))
+            });
+        triggered.map(|(key, pos)| Self::take_stop(&mut self.falling, key, pos))
+    }
+
+    // Position of the oldest conditional order whose condition holds
+    fn released_conditional(&self, holds: impl Fn(&BookCondition) -> bool) -> Option<usize> {
+        self.conditional
+            .iter()
+            .position(|o| holds(o.options.condition.as_ref().unwrap()))
+    }
+
+    fn take_conditional(&mut self, pos: usize) -> Order {
+        self.conditional.remove(pos)
+    }
+
+    // Remove the stop order at `pos` under `key`, dropping the key once it is empty
+    fn take_stop(stops: &mut BTreeMap<i64, Vec<Order>>, key: i64, pos: usize) -> Order {
+        let orders = stops.get_mut(&key).unwrap();
+        let order = orders.remove(pos);
+        if orders.is_empty() {
+            stops.remove(&key);
+        }
+        order
+    }
+
+    fn clear(&mut self) {
+        self.rising.clear();
+        self.falling.clear();
+        self.conditional.clear();
+    }
+}
+
+/// Lifecycle transition recorded in an order's audit trail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditAction {
+    Accepted,
+    Amended,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Expired,
+    Rejected,
+    /// One of the order's executions was busted
+    TradeBusted,
+}
+
+/// One entry of an order's audit trail, see `OrderBook::audit_trail`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Book-wide sequence number, increasing across the trails of all orders
+    pub sequence: u64,
+    pub timestamp: u64,
+    pub action: AuditAction,
+    pub filled_quantity: f64,
+    pub remaining_quantity: f64,
+    /// The order's amendment revision when the entry was recorded
+    #[serde(default)]
+    pub version: u64,
+}
+
+/// Append-only lifecycle history per order id, kept while enabled
+#[derive(Debug, Clone, Default)]
+struct AuditTrail {
+    enabled: bool,
+    entries: HashMap<u64, Vec<AuditEntry>>,
+    next_sequence: u64,
+}
+
+impl AuditTrail {
+    fn record(&mut self, order: &Order, action: AuditAction, timestamp: u64) {
+        if !self.enabled {
+            return;
+        }
+        self.next_sequence += 1;
+        self.entries.entry(order.id).or_default().push(AuditEntry {
+            sequence: self.next_sequence,
+            timestamp,
+            action,
+            filled_quantity: order.filled_quantity,
+            remaining_quantity: order.remaining_quantity,
+            version: order.version,
+        });
+    }
+
+    // Record an order leaving the book. Fills are recorded as they trade, so only
+    // cancellations and rejections add an entry here.
+    fn record_exit(&mut self, order: &Order, timestamp: u64) {
+        let action = match order.status {
+            OrderStatus::Cancelled => AuditAction::Cancelled,
+            OrderStatus::Rejected => AuditAction::Rejected,
+            _ => return,
+        };
+        self.record(order, action, timestamp);
+    }
+
+    // Record a busted execution for an order that is no longer resting, keeping the
+    // quantities of its latest entry
+    fn record_bust(&mut self, order_id: u64, timestamp: u64) {
+        if !self.enabled {
+            return;
+        }
+        let Some(entries) = self.entries.get_mut(&order_id) else {
+            return;
+        };
+        let Some(last) = entries.last().cloned() else {
+            return;
+        };
+        self.next_sequence += 1;
+        entries.push(AuditEntry {
+            sequence: self.next_sequence,
+            timestamp,
+            action: AuditAction::TradeBusted,
+            ..last
+        });
+    }
+
+    // Turn the cancellation just recorded for an order into an expiry
+    fn mark_expired(&mut self, order_id: u64) {
+        if let Some(entry) = self.entries.get_mut(&order_id).and_then(|e| e.last_mut()) {
+            if entry.action == AuditAction::Cancelled {
+                entry.action = AuditAction::Expired;
+            }
+        }
+    }
+
+    fn get(&self, order_id: u64) -> &[AuditEntry] {
+        self.entries.get(&order_id).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Cache of orders that have left the book, evicted oldest first once it holds
+/// `capacity` orders (never if `capacity` is `None`)
+#[derive(Debug, Clone)]
+pub struct FinishedOrders {
+    orders: HashMap<u64, OrderView>,
+    insertion_order: VecDeque<u64>,
+    capacity: Option<usize>,
+}
+
+impl FinishedOrders {
+    pub fn new() -> Self {
+        FinishedOrders {
+            orders: HashMap::with_capacity(FINISHED_ORDER_CACHE_SIZE),
+            insertion_order: VecDeque::with_capacity(FINISHED_ORDER_CACHE_SIZE),
+            capacity: Some(FINISHED_ORDER_CACHE_SIZE),
+        }
+    }
+
+    pub fn record(&mut self, order: &Order) {
+        if self
+            .orders
+            .insert(order.id, OrderView::from(order))
+            .is_none()
+        {
+            self.insertion_order.push_back(order.id);
+            self.evict();
+        }
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// Change how many finished orders are kept, evicting the oldest ones beyond it
+    pub fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        let Some(capacity) = self.capacity else {
+            return;
+        };
+        while self.insertion_order.len() > capacity {
+            if let Some(oldest) = self.insertion_order.pop_front() {
+                self.orders.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn get(&self, order_id: u64) -> Option<&OrderView> {
+        self.orders.get(&order_id)
+    }
+
+    pub fn clear(&mut self) {
+        self.orders.clear();
+        self.insertion_order.clear();
+    }
+}
+
+impl Default for FinishedOrders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Batch of orders to process efficiently
+#[derive(Debug, Default, Clone)]
+pub struct OrderBatch {
+    pub buy_market_orders: Vec<Order>,
+    pub sell_market_orders: Vec<Order>,
+    pub buy_limit_orders: Vec<Order>,
+    pub sell_limit_orders: Vec<Order>,
+}
+
+impl OrderBatch {
+    pub fn new() -> Self {
+        OrderBatch {
+            buy_market_orders: Vec::with_capacity(16),
+            sell_market_orders: Vec::with_capacity(16),
+            buy_limit_orders: Vec::with_capacity(32),
+            sell_limit_orders: Vec::with_capacity(32),
+        }
+    }
+
+    pub fn add_order(&mut self, order: Order) {
+        match (order.side, order.order_type) {
+            (OrderSide::Buy, OrderType::Market) => self.buy_market_orders.push(order),
+            (OrderSide::Sell, OrderType::Market) => self.sell_market_orders.push(order),
+            (OrderSide::Buy, OrderType::Limit) => self.buy_limit_orders.push(order),
+            (OrderSide::Sell, OrderType::Limit) => self.sell_limit_orders.push(order),
+        }
+    }
+
+    pub fn sort(&mut self) {
+        // Sort market orders by timestamp (FIFO)
+        self.buy_market_orders.sort_by_key(|o| o.timestamp);
+        self.sell_market_orders.sort_by_key(|o| o.timestamp);
+
+        // Sort limit orders by price (best price first) then timestamp
+        self.buy_limit_orders.sort_by(|a, b| {
+            let a_price = a.price.unwrap_or(0.0);
+            let b_price = b.price.unwrap_or(0.0);
+            b_price
+                .partial_cmp(&a_price)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.timestamp.cmp(&b.timestamp))
+        });
+
+        self.sell_limit_orders.sort_by(|a, b| {
+            let a_price = a.price.unwrap_or(f64::MAX);
+            let b_price = b.price.unwrap_or(f64::MAX);
+            a_price
+                .partial_cmp(&b_price)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.timestamp.cmp(&b.timestamp))
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buy_market_orders.is_empty()
+            && self.sell_market_orders.is_empty()
+            && self.buy_limit_orders.is_empty()
+            && self.sell_limit_orders.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buy_market_orders.len()
+            + self.sell_market_orders.len()
+            + self.buy_limit_orders.len()
+            + self.sell_limit_orders.len()
+    }
+}
+
+/// OrderBook struct with optimized implementation
+#[derive(Debug)]
+pub struct OrderBook {
+    // Price levels for improved locality and reduced cloning
+    buy_price_levels: BTreeMap<i64, PriceLevel>, // Negated fixed-point price as key, best first
+    sell_price_levels: BTreeMap<i64, PriceLevel>, // Fixed-point price as key
+
+    // Fast lookups
+    orders_by_id: HashMap<u64, (OrderSide, i64)>, // Map order ID to side and price key
+    orders_by_owner: OwnerIndex,
+
+    // Stop, if-touched and conditional orders waiting to trigger
+    triggers: TriggerBook,
+    last_trade_price: Option<f64>,
+    // Most recent trade price other than `last_trade_price`, for the short sale tick test
+    last_different_price: Option<f64>,
+
+    // Auction-only orders kept out of continuous matching, in submission order, and the
+    // auctions that have already run
+    auction_orders: Vec<Order>,
+    completed_auctions: BTreeSet<AuctionSession>,
+    // Current trading phase, and the auction in its call phase if any
+    trading_state: TradingState,
+    auction_call: Option<AuctionSession>,
+
+    // Ids of resting pegged orders, pruned lazily as they leave the book, and the
+    // (bid, ask) reference they were last priced against
+    pegged_orders: BTreeSet<u64>,
+    peg_reference: Option<(Option<f64>, Option<f64>)>,
+
+    // Largest discretion of any order that has rested since the last clear, bounding how
+    // far past an incoming limit price matching looks for discretionary orders
+    max_discretion: f64,
+
+    // OCO pairs and the siblings waiting to be cancelled
+    order_links: OrderLinks,
+
+    // Order and trade IDs
+    next_order_id: u64,
+    next_trade_id: u64,
+
+    // Trades with pre-allocated capacity
+    trades: Vec<Trade>,
+    // Whether `trades` is in non-decreasing timestamp order. Trade timestamps come from
+    // caller-supplied order timestamps, so out-of-order submissions can break it.
+    trades_in_time_order: bool,
+    // Indices into `trades` of the first trades counted in `stats` (with positions and
+    // accrued fees) and in `session`, so a bust only reverses what was counted
+    stats_first_trade: usize,
+    session_first_trade: usize,
+    // Indices into `trades` of each order's executions, oldest first
+    fills_by_order: HashMap<u64, Vec<usize>>,
+
+    // Recently filled/cancelled orders for status queries
+    finished_orders: FinishedOrders,
+
+    // Allocation rule within a price level
+    matching_policy: MatchingPolicy,
+    hidden_priority: HiddenPriority,
+    iceberg_refill: IcebergRefill,
+    priority_share: f64,
+    lead_market_maker: Option<LeadMarketMaker>,
+
+    // Submission constraints
+    quantity_rules: QuantityRules,
+    quantity_decimals: Option<u32>,
+    tick_size: f64,
+    tick_table: TickTable,
+    off_tick_price: OffTickPrice,
+    integer_ticks: bool,
+    market_protection: Option<ProtectionBand>,
+    price_collar: Option<ProtectionBand>,
+    price_bands: Option<PriceBands>,
+    short_sale_rule: ShortSaleRule,
+
+    // Circuit breaker, the (timestamp, price) of trades inside its window, oldest first,
+    // and when a running volatility auction uncrosses
+    volatility_interruption: Option<VolatilityInterruption>,
+    recent_trade_prices: VecDeque<(u64, f64)>,
+    volatility_auction_end: Option<u64>,
+
+    // Fees
+    fee_schedule: FeeSchedule,
+    instrument: InstrumentMetadata,
+    accrued_maker_fees: f64,
+    accrued_taker_fees: f64,
+
+    // Net traded quantity per owner, positive when long
+    positions: HashMap<u64, f64>,
+    // Account group of each owner, for wash trade detection
+    account_groups: HashMap<u64, u64>,
+
+    // (bid id, ask id) of each participant's latest two-sided quotes
+    quotes: HashMap<u64, Vec<(u64, u64)>>,
+
+    // Engine id of the latest order submitted under each (owner, client order id)
+    orders_by_client_id: HashMap<(Option<u64>, String), u64>,
+
+    // Open client sessions whose orders are cancelled on disconnect
+    client_sessions: BTreeMap<u64, ClientSession>,
+    next_client_session_id: u64,
+
+    // (expire_at, order id) of every GTD order entered, earliest first. Orders that left
+    // the book before expiring are skipped when their entry comes up.
+    expiry_schedule: BinaryHeap<Reverse<(u64, u64)>>,
+    // Time at which `advance_time` cancels DAY orders
+    day_close: Option<u64>,
+
+    // Statistics
+    stats: OrderBookStats,
+    session: SessionStats,
+    session_history: Vec<SessionStats>,
+
+    // Event log for deterministic replay, only appended to while recording
+    recording: bool,
+    events: Vec<BookEvent>,
+
+    // Latest time the book has seen, from order timestamps and clock advances
+    clock: u64,
+    audit: AuditTrail,
+}
+
+/// Serializable flat representation of an order book's state.
+///
+/// Resting orders are stored per side in price-time priority so restoring them in
+/// sequence rebuilds identical queues. Trade history and the finished-order cache are
+/// not included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookSnapshot {
+    pub buy_orders: Vec<Order>,
+    pub sell_orders: Vec<Order>,
+    #[serde(default)]
+    pub stop_orders: Vec<Order>,
+    #[serde(default)]
+    pub conditional_orders: Vec<Order>,
+    #[serde(default)]
+    pub auction_orders: Vec<Order>,
+    #[serde(default)]
+    pub completed_auctions: BTreeSet<AuctionSession>,
+    #[serde(default)]
+    pub trading_state: TradingState,
+    #[serde(default)]
+    pub auction_call: Option<AuctionSession>,
+    #[serde(default)]
+    pub last_trade_price: Option<f64>,
+    #[serde(default)]
+    pub last_different_price: Option<f64>,
+    #[serde(default)]
+    pub order_links: OrderLinks,
+    pub next_order_id: u64,
+    pub next_trade_id: u64,
+    #[serde(default)]
+    pub matching_policy: MatchingPolicy,
+    #[serde(default)]
+    pub hidden_priority: HiddenPriority,
+    #[serde(default)]
+    pub iceberg_refill: IcebergRefill,
+    #[serde(default)]
+    pub priority_share: f64,
+    #[serde(default)]
+    pub lead_market_maker: Option<LeadMarketMaker>,
+    #[serde(default)]
+    pub quantity_rules: QuantityRules,
+    #[serde(default)]
+    pub quantity_decimals: Option<u32>,
+    #[serde(default = "default_tick_size")]
+    pub tick_size: f64,
+    #[serde(default)]
+    pub tick_table: TickTable,
+    #[serde(default)]
+    pub off_tick_price: OffTickPrice,
+    #[serde(default)]
+    pub integer_ticks: bool,
+    #[serde(default = "default_finished_order_retention")]
+    pub finished_order_retention: Option<usize>,
+    #[serde(default)]
+    pub market_protection: Option<ProtectionBand>,
+    #[serde(default)]
+    pub price_collar: Option<ProtectionBand>,
+    #[serde(default)]
+    pub price_bands: Option<PriceBands>,
+    #[serde(default)]
+    pub short_sale_rule: ShortSaleRule,
+    #[serde(default)]
+    pub volatility_interruption: Option<VolatilityInterruption>,
+    #[serde(default)]
+    pub recent_trade_prices: VecDeque<(u64, f64)>,
+    #[serde(default)]
+    pub volatility_auction_end: Option<u64>,
+    pub fee_schedule: FeeSchedule,
+    #[serde(default)]
+    pub instrument: InstrumentMetadata,
+    pub accrued_maker_fees: f64,
+    pub accrued_taker_fees: f64,
+    #[serde(default)]
+    pub positions: HashMap<u64, f64>,
+    #[serde(default)]
+    pub account_groups: HashMap<u64, u64>,
+    #[serde(default)]
+    pub quotes: HashMap<u64, Vec<(u64, u64)>>,
+    pub stats: OrderBookStats,
+    #[serde(default)]
+    pub session: SessionStats,
+    #[serde(default)]
+    pub session_history: Vec<SessionStats>,
+    #[serde(default)]
+    pub client_sessions: BTreeMap<u64, ClientSession>,
+    #[serde(default = "default_next_client_session_id")]
+    pub next_client_session_id: u64,
+    #[serde(default)]
+    pub day_close: Option<u64>,
+    #[serde(default)]
+    pub clock: u64,
+    #[serde(default)]
+    pub audit_enabled: bool,
+    #[serde(default)]
+    pub audit_trail: HashMap<u64, Vec<AuditEntry>>,
+    #[serde(default)]
+    pub next_audit_sequence: u64,
+}
+
+/// A single state-changing operation on an `OrderBook`, recorded when recording is enabled.
+///
+/// Replaying a recorded log against a fresh book with `OrderBook::replay` reproduces the
+/// same trades and resting state, since matching is fully deterministic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BookEvent {
+    AddLimit {
+        side: OrderSide,
+        price: f64,
+        quantity: f64,
+        timestamp: u64,
+        symbol: Option<String>,
+        options: OrderOptions,
+    },
+    AddMarket {
+        side: OrderSide,
+        quantity: f64,
+        timestamp: u64,
+        symbol: Option<String>,
+        options: OrderOptions,
+    },
+    AddMarketByNotional {
+        side: OrderSide,
+        notional: f64,
+        timestamp: u64,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    BatchAdd {
+        orders: Vec<BatchOrder>,
+        ordered: bool,
+    },
+    Cancel {
+        order_id: u64,
+    },
+    Modify {
+        order_id: u64,
+        new_price: Option<f64>,
+        new_quantity: Option<f64>,
+    },
+    ModifyBatch(Vec<(u64, Option<f64>, Option<f64>)>),
+    CancelReplace {
+        order_id: u64,
+        new_price: f64,
+        new_quantity: f64,
+        timestamp: u64,
+    },
+    CancelParticipant {
+        owner_id: u64,
+    },
+    CancelSymbol {
+        symbol: String,
+        owner_id: Option<u64>,
+    },
+    BustTrade {
+        trade_id: u64,
+        restore_orders: bool,
+    },
+    CancelAll {
+        side: Option<OrderSide>,
+        price_range: Option<(f64, f64)>,
+        owner_id: Option<u64>,
+    },
+    ExpireOrders {
+        now: u64,
+    },
+    AdvanceTime {
+        now: u64,
+    },
+    SetDayClose(Option<u64>),
+    Clear,
+    ResetIds,
+    ClearWith(ClearOptions),
+    EndSession,
+    OpenSession {
+        heartbeat_timeout: Option<u64>,
+        timestamp: u64,
+    },
+    Heartbeat {
+        session_id: u64,
+        timestamp: u64,
+    },
+    CloseSession {
+        session_id: u64,
+    },
+    ExpireSessions {
+        now: u64,
+    },
+    SetMatchingPolicy(MatchingPolicy),
+    SetHiddenPriority(HiddenPriority),
+    SetIcebergRefill(IcebergRefill),
+    SetPriorityShare(f64),
+    SetLeadMarketMaker(Option<LeadMarketMaker>),
+    SetQuantityRules(QuantityRules),
+    SetQuantityDecimals(Option<u32>),
+    SetFeeSchedule(FeeSchedule),
+    SetInstrument(InstrumentMetadata),
+    SetAccountGroup {
+        owner_id: u64,
+        group: Option<u64>,
+    },
+    SetTickSize(f64),
+    SetTickTable(TickTable, OffTickPrice),
+    SetIntegerTicks(bool),
+    SetFinishedOrderRetention(Option<usize>),
+    CompleteAuction(AuctionSession),
+    StartAuction(AuctionSession),
+    SetTradingState(TradingState),
+    RunAuction(AuctionSession),
+    SetMarketProtection(Option<ProtectionBand>),
+    SetPriceCollar(Option<ProtectionBand>),
+    SetPriceBands(Option<PriceBands>),
+    SetShortSaleRule(ShortSaleRule),
+    SetVolatilityInterruption(Option<VolatilityInterruption>),
+    AddOco {
+        first: Box<(OrderParams, OrderOptions)>,
+        second: Box<(OrderParams, OrderOptions)>,
+    },
+    Quote {
+        participant: u64,
+        quote: Quote,
+        timestamp: u64,
+        symbol: Option<String>,
+    },
+    MassQuote {
+        participant: u64,
+        quotes: Vec<Quote>,
+        mode: MassQuoteMode,
+        timestamp: u64,
+        symbol: Option<String>,
+    },
+}
+
+/// Aggregated (price, quantity) levels for the buy and sell sides
+pub type DepthSnapshot = (Vec<(f64, f64)>, Vec<(f64, f64)>);
+
+/// Aggregated (price, displayed quantity, total quantity) levels for the buy and sell sides
+pub type FullDepthSnapshot = (Vec<(f64, f64, f64)>, Vec<(f64, f64, f64)>);
+
+/// Side, type, price, quantity, timestamp and symbol of a new order
+pub type OrderParams = (OrderSide, OrderType, Option<f64>, f64, u64, Option<String>);
+
+/// Order parameters and time in force accepted by `OrderBook::batch_add_orders`
+pub type BatchOrder = (OrderParams, TimeInForce);
+
+/// Connection an order entry client holds with the book, see `OrderBook::open_session`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ClientSession {
+    /// Longest gap allowed between heartbeats before the session is dropped, if any
+    pub heartbeat_timeout: Option<u64>,
+    pub last_heartbeat: u64,
+}
+
+/// Where a resting order stands in the queue of its price level, see
+/// `OrderBook::queue_position`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QueuePosition {
+    pub price: f64,
+    /// Resting orders at the price that trade before this one
+    pub orders_ahead: usize,
+    /// Remaining quantity of those orders, hidden quantity included
+    pub quantity_ahead: f64,
+}
+
+/// Activity during one trading session, finalized by `OrderBook::end_session`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionStats {
+    /// Number of the session, counting from 0
+    pub session: u64,
+    pub orders_processed: u64,
+    pub trades_executed: u64,
+    pub total_trade_volume: f64,
+    pub open_price: Option<f64>,
+    pub high_price: Option<f64>,
+    pub low_price: Option<f64>,
+    pub close_price: Option<f64>,
+    /// DAY orders cancelled when the session ended
+    pub day_orders_cancelled: usize,
+    /// Closing auction price, or the last trade price if the closing auction did not
+    /// uncross. Set when the closing auction runs.
+    #[serde(default)]
+    pub official_close_price: Option<f64>,
+    /// Price the opening auction uncrossed at, if it did. Set when the opening auction
+    /// runs.
+    #[serde(default)]
+    pub opening_price: Option<f64>,
+}
+
+impl SessionStats {
+    fn record_trade(&mut self, price: f64, quantity: f64) {
+        self.trades_executed += 1;
+        self.total_trade_volume += quantity;
+        self.record_price(price);
+    }
+
+    fn record_price(&mut self, price: f64) {
+        self.open_price.get_or_insert(price);
+        self.high_price = Some(self.high_price.map_or(price, |high| high.max(price)));
+        self.low_price = Some(self.low_price.map_or(price, |low| low.min(price)));
+        self.close_price = Some(price);
+    }
+
+    // Take a busted trade out of the counters and recompute the prices from the
+    // session's trades still standing
+    fn bust_trade(&mut self, quantity: f64, session_trades: &[Trade]) {
+        self.trades_executed -= 1;
+        self.total_trade_volume -= quantity;
+        self.open_price = None;
+        self.high_price = None;
+        self.low_price = None;
+        self.close_price = None;
+        for trade in session_trades.iter().filter(|t| !t.busted) {
+            self.record_price(trade.price);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OrderBookStats {
+    pub orders_processed: u64,
+    pub trades_executed: u64,
+    #[serde(default)]
+    pub total_trade_volume: f64,
+    /// Sum of trade notionals
+    #[serde(default)]
+    pub total_notional: f64,
+    // Current book depth, filled in by `OrderBook::get_statistics`
+    #[serde(default)]
+    pub resting_order_count: usize,
+    #[serde(default)]
+    pub bid_levels: usize,
+    #[serde(default)]
+    pub ask_levels: usize,
+    /// Trades flagged `wash_trade` and their total quantity
+    #[serde(default)]
+    pub wash_trades: u64,
+    #[serde(default)]
+    pub wash_trade_volume: f64,
+    /// Trades whose aggressor got a better price than its limit, by aggressor side, and
+    /// the total improvement as price difference times quantity
+    #[serde(default)]
+    pub buy_price_improved_trades: u64,
+    #[serde(default)]
+    pub buy_price_improvement: f64,
+    #[serde(default)]
+    pub sell_price_improved_trades: u64,
+    #[serde(default)]
+    pub sell_price_improvement: f64,
+    // Prices and volume, filled in by `OrderBook::get_statistics` from the current session
+    #[serde(default)]
+    pub last_trade_price: Option<f64>,
+    #[serde(default)]
+    pub open_price: Option<f64>,
+    #[serde(default)]
+    pub high_price: Option<f64>,
+    #[serde(default)]
+    pub low_price: Option<f64>,
+    #[serde(default)]
+    pub close_price: Option<f64>,
+    #[serde(default)]
+    pub session_volume: f64,
+    #[serde(default)]
+    pub opening_price: Option<f64>,
+}
+
+impl OrderBook {
+    pub fn new() -> Self {
+        OrderBook {
+            buy_price_levels: BTreeMap::new(),
+            sell_price_levels: BTreeMap::new(),
+            orders_by_id: HashMap::with_capacity(1024),
+            orders_by_owner: OwnerIndex::default(),
+            triggers: TriggerBook::default(),
+            last_trade_price: None,
+            last_different_price: None,
+            auction_orders: Vec::new(),
+            completed_auctions: BTreeSet::new(),
+            trading_state: TradingState::default(),
+            auction_call: None,
+            pegged_orders: BTreeSet::new(),
+            peg_reference: None,
+            max_discretion: 0.0,
+            order_links: OrderLinks::default(),
+            next_order_id: 1,
+            next_trade_id: 1,
+            trades: Vec::with_capacity(1000),
+            trades_in_time_order: true,
+            stats_first_trade: 0,
+            session_first_trade: 0,
+            fills_by_order: HashMap::new(),
+            finished_orders: FinishedOrders::new(),
+            matching_policy: MatchingPolicy::default(),
+            hidden_priority: HiddenPriority::default(),
+            iceberg_refill: IcebergRefill::default(),
+            priority_share: 0.0,
+            lead_market_maker: None,
+            quantity_rules: QuantityRules::default(),
+            quantity_decimals: None,
+            tick_size: DEFAULT_TICK_SIZE,
+            tick_table: TickTable::default(),
+            off_tick_price: OffTickPrice::default(),
+            integer_ticks: false,
+            market_protection: None,
+            price_collar: None,
+            price_bands: None,
+            short_sale_rule: ShortSaleRule::default(),
+            volatility_interruption: None,
+            recent_trade_prices: VecDeque::new(),
+            volatility_auction_end: None,
+            fee_schedule: FeeSchedule::default(),
+            instrument: InstrumentMetadata::default(),
+            accrued_maker_fees: 0.0,
+            accrued_taker_fees: 0.0,
+            positions: HashMap::new(),
+            account_groups: HashMap::new(),
+            quotes: HashMap::new(),
+            orders_by_client_id: HashMap::new(),
+            client_sessions: BTreeMap::new(),
+            next_client_session_id: 1,
+            expiry_schedule: BinaryHeap::new(),
+            day_close: None,
+            stats: OrderBookStats::default(),
+            session: SessionStats::default(),
+            session_history: Vec::new(),
+            recording: false,
+            events: Vec::new(),
+            clock: 0,
+            audit: AuditTrail::default(),
+        }
+    }
+
+    // Fixed-point price key: the price as a whole number of PRICE_UNITS_PER_PRICE units,
+    // so prices that differ by float noise share a level and negative prices sort
+    // correctly
+    fn price_to_key(price: f64, is_buy: bool) -> i64 {
+        let units = price_units(price);
+        if is_buy {
+            // For buy orders, negate to get descending order
+            -units
+        } else {
+            units
+        }
+    }
+
+    // Canonical price of a key: the float nearest to its exact fixed-point value
+    fn key_to_price(key: i64, is_buy: bool) -> f64 {
+        units_price(if is_buy { -key } else { key })
+    }
+
+    // Get or create price level with caching
+    fn get_or_create_price_level(
+        &mut self,
+        is_buy: bool,
+        price_key: i64,
+        create_new: bool,
+    ) -> Option<&mut PriceLevel> {
+        let price_map = if is_buy {
+            &mut self.buy_price_levels
+        } else {
+            &mut self.sell_price_levels
+        };
+
+        if create_new {
+            Some(
+                price_map
+                    .entry(price_key)
+                    .or_insert_with(|| PriceLevel::new(Self::key_to_price(price_key, is_buy))),
+            )
+        } else {
+            price_map.get_mut(&price_key)
+        }
+    }
+
+    // Add a limit order's remainder to the book, or remember it as finished if fully filled
+    fn rest_or_finish(&mut self, mut order: Order) {
+        if self.quantity_rules.is_dust(order.remaining_quantity) {
+            order.status = OrderStatus::Cancelled;
+        }
+
+        if order.remaining_quantity <= 0.0 || order.status == OrderStatus::Cancelled {
+            self.finished_orders.record(&order);
+            self.audit.record_exit(&order, self.clock);
+            self.order_links.touch(&order);
+        } else {
+            self.insert_resting_order(order);
+        }
+    }
+
+    // Append an order to the back of its price level without matching
+    fn insert_resting_order(&mut self, mut order: Order) {
+        if order.display_remaining <= 0.0 {
+            order.refresh_display();
+        }
+        if order.options.peg.is_some() {
+            self.pegged_orders.insert(order.id);
+        }
+        if let Some(discretion) = order.options.discretion {
+            self.max_discretion = self.max_discretion.max(discretion);
+        }
+        let is_buy = order.side == OrderSide::Buy;
+        let price_key = Self::price_to_key(order.price.unwrap(), is_buy);
+        self.orders_by_id.insert(order.id, (order.side, price_key));
+        self.orders_by_owner.insert(&order);
+        self.get_or_create_price_level(is_buy, price_key, true)
+            .unwrap()
+            .add_order(order);
+    }
+
+    // Whether the order's owner already has a resting or pending order under its client
+    // order id
+    fn has_live_client_order_id(&self, order: &Order) -> bool {
+        let Some(client_order_id) = &order.options.client_order_id else {
+            return false;
+        };
+        self.order_id_by_client_id(order.options.owner_id, client_order_id)
+            .is_some_and(|order_id| {
+                self.orders_by_id.contains_key(&order_id)
+                    || self.find_pending_order(order_id).is_some()
+            })
+    }
+
+    fn index_client_order_id(&mut self, order: &Order) {
+        if let Some(client_order_id) = &order.options.client_order_id {
+            self.orders_by_client_id
+                .insert((order.options.owner_id, client_order_id.clone()), order.id);
+        }
+    }
+
+    fn schedule_expiry(&mut self, order: &Order) {
+        if let TimeInForce::Gtd { expire_at } = order.options.time_in_force {
+            self.expiry_schedule.push(Reverse((expire_at, order.id)));
+        }
+    }
+
+    /// Engine id of the latest order submitted by `owner_id` under `client_order_id`
+    pub fn order_id_by_client_id(
+        &self,
+        owner_id: Option<u64>,
+        client_order_id: &str,
+    ) -> Option<u64> {
+        self.orders_by_client_id
+            .get(&(owner_id, client_order_id.to_string()))
+            .copied()
+    }
+
+    /// `get_order` by client order id
+    pub fn get_order_by_client_id(
+        &self,
+        owner_id: Option<u64>,
+        client_order_id: &str,
+    ) -> Option<OrderView> {
+        self.get_order(self.order_id_by_client_id(owner_id, client_order_id)?)
+    }
+
+    /// `cancel_order` by client order id. Returns false if the id is unknown.
+    pub fn cancel_order_by_client_id(
+        &mut self,
+        owner_id: Option<u64>,
+        client_order_id: &str,
+    ) -> bool {
+        match self.order_id_by_client_id(owner_id, client_order_id) {
+            Some(order_id) => self.cancel_order(order_id),
+            None => false,
+        }
+    }
+
+    /// `modify_order` by client order id. Returns `Ok(false)` if the id is unknown.
+    pub fn modify_order_by_client_id(
+        &mut self,
+        owner_id: Option<u64>,
+        client_order_id: &str,
+        new_price: Option<f64>,
+        new_quantity: Option<f64>,
+    ) -> Result<bool, OrderError> {
+        match self.order_id_by_client_id(owner_id, client_order_id) {
+            Some(order_id) => self.modify_order(order_id, new_price, new_quantity),
+            None => Ok(false),
+        }
+    }
+
+    // Find a stop, conditional or auction-only order that has not entered the book yet
+    fn find_pending_order(&self, order_id: u64) -> Option<&Order> {
+        self.triggers
+            .orders()
+            .chain(&self.auction_orders)
+            .find(|o| o.id == order_id)
+    }
+
+    // Whether an order with this id is live or still in the finished order cache
+    fn is_known_order_id(&self, order_id: u64) -> bool {
+        self.orders_by_id.contains_key(&order_id)
+            || self.find_pending_order(order_id).is_some()
+            || self.finished_orders.get(order_id).is_some()
+    }
+
+    /// Look up an order's current state by id.
+    ///
+    /// Resting orders are read from their price level. Orders that have left the book
+    /// (filled, cancelled, or market orders that could not rest) are served from a
+    /// cache of the most recent finished orders, `FINISHED_ORDER_CACHE_SIZE` of them
+    /// unless `set_finished_order_retention` says otherwise; older ones return `None`.
+    pub fn get_order(&self, order_id: u64) -> Option<OrderView> {
+        if let Some(&(side, price_key)) = self.orders_by_id.get(&order_id) {
+            let price_levels = match side {
+                OrderSide::Buy => &self.buy_price_levels,
+                OrderSide::Sell => &self.sell_price_levels,
+            };
+            return price_levels
+                .get(&price_key)
+                .and_then(|level| level.orders.iter().find(|o| o.id == order_id))
+                .map(OrderView::from);
+        }
+        if let Some(order) = self.find_pending_order(order_id) {
+            return Some(OrderView::from(order));
+        }
+        self.finished_orders.get(order_id).cloned()
+    }
+
+    /// Position of a resting order within its price level, or `None` if it is not
+    /// resting.
+    ///
+    /// Orders ahead are those that trade first under price-time priority, honouring the
+    /// hidden priority setting. Pro-rata matching and priority shares can fill an order
+    /// out of turn, so there the position is only an estimate.
+    pub fn queue_position(&self, order_id: u64) -> Option<QueuePosition> {
+        let &(side, price_key) = self.orders_by_id.get(&order_id)?;
+        let price_levels = match side {
+            OrderSide::Buy => &self.buy_price_levels,
+            OrderSide::Sell => &self.sell_price_levels,
+        };
+        let level = price_levels.get(&price_key)?;
+        let pos = level.orders.iter().position(|o| o.id == order_id)?;
+        let hidden = level.orders[pos].options.hidden;
+        let behind_displayed = self.hidden_priority == HiddenPriority::BehindDisplayed;
+        let ahead = level.orders.iter().enumerate().filter(|&(i, other)| {
+            if behind_displayed && other.options.hidden != hidden {
+                !other.options.hidden
+            } else {
+                i < pos
+            }
+        });
+        let (orders_ahead, quantity_ahead) = ahead.fold((0, 0.0), |(count, quantity), (_, o)| {
+            (count + 1, quantity + o.remaining_quantity)
+        });
+        Some(QueuePosition {
+            price: level.price,
+            orders_ahead,
+            quantity_ahead,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_order(
+        &mut self,
+        side: OrderSide,
+        order_type: OrderType,
+        price: Option<f64>,
+        quantity: f64,
+        timestamp: u64,
+        symbol: Option<String>,
+        time_in_force: TimeInForce,
+    ) -> Result<u64, OrderError> {
+        self.add_order_with_options(
+            side,
+            order_type,
+            price,
+            quantity,
+            timestamp,
+            symbol,
+            OrderOptions {
+                time_in_force,
+                ..OrderOptions::default()
+            },
+        )
+    }
+
+    /// Market order for a cash amount rather than a quantity ("buy $X worth").
+    ///
+    /// Walks the opposite side to work out the quantity whose cost reaches `notional`,
+    /// taking a fractional quantity at the final level, then executes it. If the book
+    /// cannot absorb the full notional the order ends `PartiallyFilled` after taking all
+    /// available liquidity, or `Rejected` if the opposite side is empty. Like any other
+    /// order it is rejected while the book is halted or closed, and held during auctions.
+    ///
+    /// The sized quantity is capped at the book's maximum order quantity and must still
+    /// meet its minimum, else an error is returned and no order is created.
+    pub fn add_market_order_by_notional(
+        &mut self,
+        side: OrderSide,
+        notional: f64,
+        timestamp: u64,
+        symbol: Option<String>,
+    ) -> Result<u64, OrderError> {
+        self.record_event(|| BookEvent::AddMarketByNotional {
+            side,
+            notional,
+            timestamp,
+            symbol: symbol.clone(),
+        });
+
+        // Size the order from the opposite side's depth
+        let opposite_levels = match side {
+            OrderSide::Buy => &self.sell_price_levels,
+            OrderSide::Sell => &self.buy_price_levels,
+        };
+        let mut quantity = 0.0;
+        let mut notional_left = notional;
+        for level in opposite_levels.values() {
+            if notional_left <= 0.0 {
+                break;
+            }
+            let level_quantity = level.current_quantity();
+            let unit_notional = level.price * self.instrument.contract_multiplier;
+            let level_notional = unit_notional * level_quantity;
+            if level_notional >= notional_left {
+                quantity += notional_left / unit_notional;
+                notional_left = 0.0;
+            } else {
+                quantity += level_quantity;
+                notional_left -= level_notional;
+            }
+        }
+
+        // Notional orders are sized from the book, so round down to a whole lot and to the
+        // book's quantity decimals rather than rejecting them for an odd quantity, and cap
+        // them at the maximum order quantity
+        if self.quantity_rules.max_quantity > 0.0 {
+            quantity = quantity.min(self.quantity_rules.max_quantity);
+        }
+        quantity = round_down_to_lot(quantity, self.quantity_rules.lot_size);
+        if let Some(decimals) = self.quantity_decimals {
+            let units = quantity * 10f64.powi(decimals as i32) + 1e-9;
+            quantity = units_quantity(units.floor() as i64, decimals);
+        }
+        // An empty opposite side is rejected as an order below
+        if quantity > 0.0 {
+            self.validate_order(side, quantity, timestamp, &OrderOptions::default())?;
+        }
+
+        let mut order =
+            self.new_order((side, OrderType::Market, None, quantity, timestamp, symbol));
+        let order_id = order.id;
+        if let Some(reason) = self.closed_reason() {
+            self.reject_order(order, reason);
+            return Ok(order_id);
+        }
+        self.audit.record(&order, AuditAction::Accepted, timestamp);
+        // Outside continuous trading the sized order is held for the auction like any
+        // other market order
+        if quantity > 0.0 && self.trading_state != TradingState::ContinuousTrading {
+            self.execute_order(order);
+            return Ok(order_id);
+        }
+        if quantity > 0.0 {
+            self.match_order(&mut order, None);
+        }
+
+        order.status = if order.filled_quantity <= 0.0 {
+            order.reject_reason = Some(RejectReason::NoLiquidity);
+            OrderStatus::Rejected
+        } else if notional_left > 0.0 || order.remaining_quantity > 0.0 {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::Filled
+        };
+        self.finished_orders.record(&order);
+        self.audit.record_exit(&order, self.clock);
+        self.order_links.touch(&order);
+        self.settle();
+
+        Ok(order_id)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_order_with_options(
+        &mut self,
+        side: OrderSide,
+        order_type: OrderType,
+        price: Option<f64>,
+        quantity: f64,
+        timestamp: u64,
+        symbol: Option<String>,
+        options: OrderOptions,
+    ) -> Result<u64, OrderError> {
+        // Checked before recording, since a limit event cannot be recorded without a price
+        validate_limit_price(order_type, price)?;
+        self.record_event(|| match order_type {
+            OrderType::Limit => BookEvent::AddLimit {
+                side,
+                price: price.unwrap(),
+                quantity,
+                timestamp,
+                symbol: symbol.clone(),
+                options: options.clone(),
+            },
+            OrderType::Market => BookEvent::AddMarket {
+                side,
+                quantity,
+                timestamp,
+                symbol: symbol.clone(),
+                options: options.clone(),
+            },
+        });
+
+        let price = price
+            .map(|price| self.tick_price(side, price))
+            .transpose()?;
+        self.validate_order(side, quantity, timestamp, &options)?;
+
+        // Create the order
+        let order = self.new_order_with_options(
+            (side, order_type, price, quantity, timestamp, symbol),
+            options,
+        );
+        let order_id = order.id;
+
+        // Process the order
+        self.process_order(order);
+
+        // Return the order ID
+        Ok(order_id)
+    }
+
+    // Allocate the next order id for a new order
+    fn new_order(&mut self, params: OrderParams) -> Order {
+        self.create_order(self.next_order_id, params)
+    }
+
+    // Create a new order under the id its options ask for, if any, else the next one
+    fn new_order_with_options(&mut self, params: OrderParams, mut options: OrderOptions) -> Order {
+        let order_id = options.order_id.take().unwrap_or(self.next_order_id);
+        let mut order = self.create_order(order_id, params);
+        order.options = options;
+        order
+    }
+
+    fn create_order(&mut self, order_id: u64, params: OrderParams) -> Order {
+        let (side, order_type, price, quantity, timestamp, symbol) = params;
+        self.next_order_id = self.next_order_id.max(order_id + 1);
+        self.stats.orders_processed += 1;
+        self.session.orders_processed += 1;
+        self.clock = self.clock.max(timestamp);
+        Order::new(
+            order_id, side, order_type, price, quantity, timestamp, symbol,
+        )
+    }
+
+    // The limit price an order enters with: unchanged if on tick, else rounded or refused
+    // depending on the book's off-tick setting
+    fn tick_price(&self, side: OrderSide, price: f64) -> Result<f64, OrderError> {
+        if self.integer_ticks {
+            return self.integer_tick_price(side, price);
+        }
+        if self.tick_table.is_on_tick(price) {
+            return Ok(price);
+        }
+        match self.off_tick_price {
+            OffTickPrice::Reject => Err(OrderError::PriceOffTick {
+                price,
+                tick_size: self.tick_table.tick_size_at(price).unwrap(),
+            }),
+            OffTickPrice::Round => Ok(self.tick_table.round_passive(side, price)),
+        }
+    }
+
+    // `tick_price` on whole price units; without a tick table every unit is a tick
+    fn integer_tick_price(&self, side: OrderSide, price: f64) -> Result<f64, OrderError> {
+        let units = price_units(price);
+        let tick_size = self.tick_table.tick_size_at(price);
+        let tick_units = tick_size.map_or(1, price_units).max(1);
+        let offset = units.rem_euclid(tick_units);
+        if offset == 0 {
+            return Ok(units_price(units));
+        }
+        match self.off_tick_price {
+            OffTickPrice::Reject => Err(OrderError::PriceOffTick {
+                price,
+                tick_size: tick_size.unwrap(),
+            }),
+            OffTickPrice::Round => {
+                let floor = units - offset;
+                Ok(units_price(match side {
+                    OrderSide::Buy => floor,
+                    OrderSide::Sell => floor + tick_units,
+                }))
+            }
+        }
+    }
+
+    // Submission checks shared by every single-order entry point
+    fn validate_order(
+        &self,
+        side: OrderSide,
+        quantity: f64,
+        timestamp: u64,
+        options: &OrderOptions,
+    ) -> Result<(), OrderError> {
+        self.quantity_rules.validate(quantity)?;
+        validate_quantity_decimals(quantity, self.quantity_decimals)?;
+        if let Some(order_id) = options.order_id {
+            if self.is_known_order_id(order_id) {
+                return Err(OrderError::DuplicateOrderId { order_id });
+            }
+        }
+        if let Some(Bracket {
+            take_profit,
+            stop_loss,
+        }) = options.bracket
+        {
+            let ordered = match side {
+                OrderSide::Buy => stop_loss < take_profit,
+                OrderSide::Sell => stop_loss > take_profit,
+            };
+            if !ordered {
+                return Err(OrderError::InvalidBracket {
+                    take_profit,
+                    stop_loss,
+                });
+            }
+        }
+        if let Some(display_quantity) = options.display_quantity {
+            if display_quantity <= 0.0 || display_quantity > quantity {
+                return Err(OrderError::InvalidDisplayQuantity {
+                    display_quantity,
+                    quantity,
+                });
+            }
+        }
+        if options.reduce_only && options.owner_id.is_none() {
+            return Err(OrderError::ReduceOnlyWithoutOwner);
+        }
+        if let Some(min_execution_quantity) = options.min_execution_quantity {
+            if min_execution_quantity <= 0.0 || min_execution_quantity > quantity {
+                return Err(OrderError::InvalidMinExecutionQuantity {
+                    min_execution_quantity,
+                    quantity,
+                });
+            }
+        }
+        if let TimeInForce::Gtd { expire_at } = options.time_in_force {
+            // A GTD order that is already expired on arrival would only linger until the
+            // next sweep, so refuse it outright
+            if expire_at <= timestamp {
+                return Err(OrderError::AlreadyExpired {
+                    expire_at,
+                    timestamp,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Submit two linked orders, e.g. a take-profit limit and a stop-loss, where any fill
+    /// or cancellation of one cancels the other. Both orders are validated before either
+    /// is submitted. If the first order trades on arrival the second is cancelled without
+    /// ever entering the book. Returns the two order ids.
+    pub fn add_oco_orders(
+        &mut self,
+        mut first: (OrderParams, OrderOptions),
+        mut second: (OrderParams, OrderOptions),
+    ) -> Result<(u64, u64), OrderError> {
+        self.record_event(|| BookEvent::AddOco {
+            first: Box::new(first.clone()),
+            second: Box::new(second.clone()),
+        });
+
+        for ((side, order_type, price, quantity, timestamp, _), options) in
+            [&mut first, &mut second]
+        {
+            validate_limit_price(*order_type, *price)?;
+            *price = price
+                .map(|price| self.tick_price(*side, price))
+                .transpose()?;
+            self.validate_order(*side, *quantity, *timestamp, options)?;
+        }
+        // The first order is created first, so only the second can collide with it
+        let first_id = first.1.order_id.unwrap_or(self.next_order_id);
+        if second.1.order_id == Some(first_id) {
+            return Err(OrderError::DuplicateOrderId { order_id: first_id });
+        }
+
+        let [first, second] =
+            [first, second].map(|(params, options)| self.new_order_with_options(params, options));
+        let order_ids = (first.id, second.id);
+        self.enter_oco(first, second);
+        self.settle();
+
+        Ok(order_ids)
+    }
+
+    // Link two new orders as an OCO pair and enter them. If the first one trades on
+    // arrival the second is cancelled without entering the book.
+    fn enter_oco(&mut self, first: Order, mut second: Order) {
+        let first_id = first.id;
+        self.order_links.link_oco(first_id, second.id);
+
+        self.enter_order(first);
+        if self.order_links.oco.contains_key(&first_id) {
+            self.enter_order(second);
+        } else {
+            second.status = OrderStatus::Cancelled;
+            self.finished_orders.record(&second);
+            self.audit.record_exit(&second, self.clock);
+        }
+    }
+
+    /// Exit orders `(take_profit_id, stop_loss_id)` spawned for a bracket entry order, once
+    /// it has finished with a fill
+    pub fn bracket_children(&self, parent_id: u64) -> Option<(u64, u64)> {
+        self.order_links.bracket_children.get(&parent_id).copied()
+    }
+
+    /// Replace a participant's two-sided quote with a new bid and ask in one step and
+    /// return their ids. Whatever is left of the participant's previous quotes is
+    /// cancelled first; nothing changes if the new quote fails validation. Quote orders
+    /// are plain limit orders owned by `participant` and may trade on entry.
+    #[allow(clippy::too_many_arguments)]
+    pub fn quote(
+        &mut self,
+        participant: u64,
+        bid_price: f64,
+        bid_quantity: f64,
+        ask_price: f64,
+        ask_quantity: f64,
+        timestamp: u64,
+        symbol: Option<String>,
+    ) -> Result<(u64, u64), OrderError> {
+        let quote = Quote {
+            bid_price,
+            bid_quantity,
+            ask_price,
+            ask_quantity,
+        };
+        self.record_event(|| BookEvent::Quote {
+            participant,
+            quote,
+            timestamp,
+            symbol: symbol.clone(),
+        });
+
+        self.replace_quotes(
+            participant,
+            &[quote],
+            MassQuoteMode::AllOrNothing,
+            timestamp,
+            symbol,
+        )
+        .remove(0)
+    }
+
+    /// Replace all of a participant's quotes with a new set of two-sided quotes, e.g. one
+    /// per price point, and return a result per quote in input order: the (bid, ask) ids
+    /// of an accepted quote or the reason it was rejected.
+    ///
+    /// With `AllOrNothing` a single invalid quote rejects the whole set and leaves the
+    /// previous quotes in place. With `BestEffort` the previous quotes are always
+    /// cancelled and the valid quotes are entered.
+    pub fn mass_quote(
+        &mut self,
+        participant: u64,
+        quotes: Vec<Quote>,
+        mode: MassQuoteMode,
+        timestamp: u64,
+        symbol: Option<String>,
+    ) -> Vec<Result<(u64, u64), OrderError>> {
+        self.record_event(|| BookEvent::MassQuote {
+            participant,
+            quotes: quotes.clone(),
+            mode,
+            timestamp,
+            symbol: symbol.clone(),
+        });
+
+        self.replace_quotes(participant, &quotes, mode, timestamp, symbol)
+    }
+
+    fn replace_quotes(
+        &mut self,
+        participant: u64,
+        quotes: &[Quote],
+        mode: MassQuoteMode,
+        timestamp: u64,
+        symbol: Option<String>,
+    ) -> Vec<Result<(u64, u64), OrderError>> {
+        let options = OrderOptions {
+            owner_id: Some(participant),
+            ..OrderOptions::default()
+        };
+        let checks: Vec<Result<Quote, OrderError>> = quotes
+            .iter()
+            .map(|quote| {
+                let quote = Quote {
+                    bid_price: self.tick_price(OrderSide::Buy, quote.bid_price)?,
+                    ask_price: self.tick_price(OrderSide::Sell, quote.ask_price)?,
+                    ..*quote
+                };
+                if quote.bid_price >= quote.ask_price {
+                    return Err(OrderError::CrossedQuote {
+                        bid_price: quote.bid_price,
+                        ask_price: quote.ask_price,
+                    });
+                }
+                self.validate_order(OrderSide::Buy, quote.bid_quantity, timestamp, &options)?;
+                self.validate_order(OrderSide::Sell, quote.ask_quantity, timestamp, &options)?;
+                Ok(quote)
+            })
+            .collect();
+        if mode == MassQuoteMode::AllOrNothing && checks.iter().any(Result::is_err) {
+            return checks
+                .into_iter()
+                .map(|check| Err(check.err().unwrap_or(OrderError::QuoteSetRejected)))
+                .collect();
+        }
+
+        for (bid_id, ask_id) in self.quotes.remove(&participant).unwrap_or_default() {
+            self.remove_and_cancel(bid_id);
+            self.remove_and_cancel(ask_id);
+        }
+
+        let mut results = Vec::with_capacity(quotes.len());
+        let mut entered = Vec::new();
+        for check in checks {
+            let quote = match check {
+                Ok(quote) => quote,
+                Err(err) => {
+                    results.push(Err(err));
+                    continue;
+                }
+            };
+            let [bid, ask] = [
+                (OrderSide::Buy, quote.bid_price, quote.bid_quantity),
+                (OrderSide::Sell, quote.ask_price, quote.ask_quantity),
+            ]
+            .map(|(side, price, quantity)| {
+                let mut order = self.new_order((
+                    side,
+                    OrderType::Limit,
+                    Some(price),
+                    quantity,
+                    timestamp,
+                    symbol.clone(),
+                ));
+                order.options = options.clone();
+                order
+            });
+            let order_ids = (bid.id, ask.id);
+            self.enter_order(bid);
+            self.enter_order(ask);
+            entered.push(order_ids);
+            results.push(Ok(order_ids));
+        }
+        if !entered.is_empty() {
+            self.quotes.insert(participant, entered);
+        }
+        self.settle();
+
+        results
+    }
+
+    /// Submit many orders at once. The whole batch is validated up front and rejected
+    /// without side effects if any order fails validation.
+    pub fn batch_add_orders(&mut self, orders: Vec<BatchOrder>) -> Result<Vec<u64>, OrderError> {
+        if orders.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.record_event(|| BookEvent::BatchAdd {
+            orders: orders.clone(),
+            ordered: false,
+        });
+        let orders = self.validate_batch(orders)?;
+
+        let mut order_ids = Vec::with_capacity(orders.len());
+        let mut batch = OrderBatch::new();
+
+        // Create all orders first
+        for (params, time_in_force) in orders {
+            let mut order = self.new_order(params);
+            order.options.time_in_force = time_in_force;
+            order_ids.push(order.id);
+            batch.add_order(order);
+        }
+
+        // Process orders in optimized batches
+        self.process_batch(batch);
+
+        Ok(order_ids)
+    }
+
+    // Validate every order of a batch, returning the orders with on-tick prices
+    fn validate_batch(&self, orders: Vec<BatchOrder>) -> Result<Vec<BatchOrder>, OrderError> {
+        orders
+            .into_iter()
+            .map(
+                |((side, order_type, price, quantity, timestamp, symbol), time_in_force)| {
+                    validate_limit_price(order_type, price)?;
+                    let price = price
+                        .map(|price| self.tick_price(side, price))
+                        .transpose()?;
+                    let options = OrderOptions {
+                        time_in_force,
+                        ..OrderOptions::default()
+                    };
+                    self.validate_order(side, quantity, timestamp, &options)?;
+                    Ok((
+                        (side, order_type, price, quantity, timestamp, symbol),
+                        time_in_force,
+                    ))
+                },
+            )
+            .collect()
+    }
+
+    /// Batch submission that processes orders strictly in ascending timestamp order across
+    /// sides and order types, rather than grouping market orders ahead of limit orders.
+    ///
+    /// Orders with equal timestamps keep their submission order, so for time-ordered input
+    /// the result is identical to calling `add_order` for each order in turn.
+    pub fn batch_add_orders_ordered(
+        &mut self,
+        orders: Vec<BatchOrder>,
+    ) -> Result<Vec<u64>, OrderError> {
+        self.record_event(|| BookEvent::BatchAdd {
+            orders: orders.clone(),
+            ordered: true,
+        });
+        let orders = self.validate_batch(orders)?;
+
+        let mut order_ids = Vec::with_capacity(orders.len());
+        let mut pending = Vec::with_capacity(orders.len());
+
+        for (params, time_in_force) in orders {
+            let mut order = self.new_order(params);
+            order.options.time_in_force = time_in_force;
+            order_ids.push(order.id);
+            pending.push(order);
+        }
+
+        // Stable sort keeps submission order among equal timestamps
+        pending.sort_by_key(|o| o.timestamp);
+        for order in pending {
+            self.process_order(order);
+        }
+
+        Ok(order_ids)
+    }
+
+    fn process_batch(&mut self, mut batch: OrderBatch) {
+        // Sort orders within each category for optimal processing
+        batch.sort();
+
+        // Process market orders first, then limit orders
+        let orders = batch
+            .buy_market_orders
+            .into_iter()
+            .chain(batch.sell_market_orders)
+            .chain(batch.buy_limit_orders)
+            .chain(batch.sell_limit_orders);
+        for order in orders {
+            if let Some(reason) = self.closed_reason() {
+                self.reject_order(order, reason);
+                continue;
+            }
+            self.schedule_expiry(&order);
+            self.audit
+                .record(&order, AuditAction::Accepted, order.timestamp);
+            self.execute_order(order);
+            self.settle();
+        }
+    }
+
+    fn process_order(&mut self, order: Order) {
+        self.enter_order(order);
+        self.settle();
+    }
+
+    // Park or execute a new order without settling the rest of the book
+    fn enter_order(&mut self, order: Order) {
+        if let Some(reason) = self.closed_reason() {
+            self.reject_order(order, reason);
+            return;
+        }
+        if self.has_live_client_order_id(&order) {
+            self.reject_order(order, RejectReason::DuplicateClientOrderId);
+            return;
+        }
+        if let Some(session_id) = order.options.session_id {
+            if !self.client_sessions.contains_key(&session_id) {
+                self.reject_order(order, RejectReason::SessionClosed);
+                return;
+            }
+        }
+        self.index_client_order_id(&order);
+        self.schedule_expiry(&order);
+        if order.options.bracket.is_some() {
+            self.order_links.bracket_parents.insert(order.id);
+        }
+        if let Some(session) = order.options.auction_target {
+            if self.completed_auctions.contains(&session) {
+                self.reject_order(order, RejectReason::AuctionClosed);
+                return;
+            }
+        }
+        self.audit
+            .record(&order, AuditAction::Accepted, order.timestamp);
+        if order.options.auction_only || order.options.auction_target.is_some() {
+            self.auction_orders.push(order);
+        } else if order.options.condition.is_some() {
+            self.triggers.insert_conditional(order);
+        } else {
+            self.release_order(order);
+        }
+    }
+
+    // Why new orders are turned away in the current trading state, if they are
+    fn closed_reason(&self) -> Option<RejectReason> {
+        match self.trading_state {
+            TradingState::Halted => Some(RejectReason::TradingHalted),
+            TradingState::Closed => Some(RejectReason::MarketClosed),
+            _ => None,
+        }
+    }
+
+    // Park a stop order or execute any other order whose condition (if any) has been met
+    fn release_order(&mut self, order: Order) {
+        if order.options.stop_price.is_some() {
+            self.insert_stop_order(order);
+        } else {
+            self.execute_order(order);
+        }
+    }
+
+    // Park a stop order until its trigger price reaches its stop price
+    fn insert_stop_order(&mut self, order: Order) {
+        self.triggers.insert_stop(order);
+    }
+
+    // Bring derived orders up to date after the book changed: fire triggered orders and
+    // reprice pegged orders, repeating while any of them moves the book
+    fn settle(&mut self) {
+        loop {
+            self.spawn_bracket_exits();
+            self.cancel_oco_siblings();
+            let fired = self.fire_triggers();
+            let repriced = self.reprice_pegged_orders();
+            if !fired && !repriced && self.order_links.is_settled() {
+                break;
+            }
+        }
+        #[cfg(feature = "integrity-checks")]
+        if let Err(err) = self.verify_integrity() {
+            panic!("order book integrity violated: {err}");
+        }
+    }
+
+    // Release every order in the trigger book whose trigger has been reached. Each release
+    // can trade and move the trigger prices or the book, so stops are re-checked after
+    // every execution and conditional orders after every stop cascade, until nothing else
+    // fires. Returns whether anything fired.
+    fn fire_triggers(&mut self) -> bool {
+        let mut any_fired = false;
+        loop {
+            while let Some(order) = self.triggers.next_triggered_stop(self.trigger_prices()) {
+                self.execute_order(order);
+                // A triggered order that traded must take its OCO sibling with it before
+                // anything else can trigger
+                self.cancel_oco_siblings();
+                any_fired = true;
+            }
+            let Some(pos) = self
+                .triggers
+                .released_conditional(|condition| self.condition_holds(condition))
+            else {
+                return any_fired;
+            };
+            let order = self.triggers.take_conditional(pos);
+            self.release_order(order);
+            self.cancel_oco_siblings();
+            any_fired = true;
+        }
+    }
+
+    fn trigger_prices(&self) -> TriggerPrices {
+        TriggerPrices {
+            last_trade: self.last_trade_price,
+            best_bid: self.best_displayed_price(true),
+            best_ask: self.best_displayed_price(false),
+        }
+    }
+
+    fn condition_holds(&self, condition: &BookCondition) -> bool {
+        let price_levels = |side: OrderSide| match side {
+            OrderSide::Buy => &self.buy_price_levels,
+            OrderSide::Sell => &self.sell_price_levels,
+        };
+        match condition {
+            BookCondition::DepthAtLeast {
+                side,
+                price,
+                quantity,
+            } => price_levels(*side)
+                .get(&Self::price_to_key(*price, *side == OrderSide::Buy))
+                .is_some_and(|level| level.current_quantity() >= *quantity),
+            BookCondition::BestDepthAtLeast { side, quantity } => price_levels(*side)
+                .values()
+                .find(|level| level.has_displayed_orders())
+                .is_some_and(|level| level.current_quantity() >= *quantity),
+            BookCondition::SpreadAtMost(spread) => {
+                match (
+                    self.best_displayed_price(true),
+                    self.best_displayed_price(false),
+                ) {
+                    (Some(bid), Some(ask)) => ask - bid <= *spread,
+                    _ => false,
+                }
+            }
+            BookCondition::All(conditions) => conditions.iter().all(|c| self.condition_holds(c)),
+            BookCondition::Any(conditions) => conditions.iter().any(|c| self.condition_holds(c)),
+        }
+    }
+
+    // Enter the take-profit and stop-loss exits of bracket parents that finished with a fill
+    fn spawn_bracket_exits(&mut self) {
+        for parent in std::mem::take(&mut self.order_links.pending_brackets) {
+            let bracket = parent.options.bracket.unwrap();
+            let side = match parent.side {
+                OrderSide::Buy => OrderSide::Sell,
+                OrderSide::Sell => OrderSide::Buy,
+            };
+            let quantity = parent.filled_quantity;
+
+            let take_profit = self.new_order((
+                side,
+                OrderType::Limit,
+                Some(bracket.take_profit),
+                quantity,
+                parent.timestamp,
+                parent.symbol.clone(),
+            ));
+            let mut stop_loss = self.new_order((
+                side,
+                OrderType::Market,
+                None,
+                quantity,
+                parent.timestamp,
+                parent.symbol,
+            ));
+            stop_loss.options.stop_price = Some(bracket.stop_loss);
+
+            self.order_links
+                .bracket_children
+                .insert(parent.id, (take_profit.id, stop_loss.id));
+            self.enter_oco(take_profit, stop_loss);
+        }
+    }
+
+    // Cancel the OCO siblings of orders that traded or left the book
+    fn cancel_oco_siblings(&mut self) {
+        for order_id in std::mem::take(&mut self.order_links.pending_cancels) {
+            self.remove_and_cancel(order_id);
+        }
+    }
+
+    // Best displayed price on one side ignoring pegged orders, so pegs never chase each
+    // other
+    fn peg_reference_price(&self, is_buy: bool) -> Option<f64> {
+        let price_levels = if is_buy {
+            &self.buy_price_levels
+        } else {
+            &self.sell_price_levels
+        };
+        price_levels
+            .values()
+            .find(|level| {
+                level
+                    .orders
+                    .iter()
+                    .any(|o| o.options.peg.is_none() && !o.options.hidden)
+            })
+            .map(|level| level.price)
+    }
+
+    // Working price of a pegged order against a (bid, ask) reference, capped at its limit
+    fn peg_price(&self, order: &Order, (bid, ask): (Option<f64>, Option<f64>)) -> Option<f64> {
+        let (own_best, opposite_best) = match order.side {
+            OrderSide::Buy => (bid, ask),
+            OrderSide::Sell => (ask, bid),
+        };
+        let target = match order.options.peg? {
+            Peg::Midpoint => (bid? + ask?) / 2.0,
+            Peg::Primary { offset_ticks } => own_best? + offset_ticks as f64 * self.tick_size,
+            Peg::Market { offset_ticks } => opposite_best? + offset_ticks as f64 * self.tick_size,
+        };
+        Some(match (order.side, order.peg_limit) {
+            (OrderSide::Buy, Some(limit)) => target.min(limit),
+            (OrderSide::Sell, Some(limit)) => target.max(limit),
+            (_, None) => target,
+        })
+    }
+
+    // Move resting pegged orders whose working price changed since the reference last
+    // moved. All of them are lifted before any is re-entered, so the new prices apply
+    // together; re-entered orders lose time priority and may match. Returns whether any
+    // order was repriced.
+    fn reprice_pegged_orders(&mut self) -> bool {
+        if self.pegged_orders.is_empty() {
+            return false;
+        }
+        let reference = (
+            self.peg_reference_price(true),
+            self.peg_reference_price(false),
+        );
+        if self.peg_reference == Some(reference) {
+            return false;
+        }
+        self.peg_reference = Some(reference);
+
+        let mut repriced = Vec::new();
+        for order_id in std::mem::take(&mut self.pegged_orders) {
+            let Some(&(side, price_key)) = self.orders_by_id.get(&order_id) else {
+                continue; // No longer resting
+            };
+            let price_levels = match side {
+                OrderSide::Buy => &self.buy_price_levels,
+                OrderSide::Sell => &self.sell_price_levels,
+            };
+            let new_price = price_levels
+                .get(&price_key)
+                .and_then(|level| level.orders.iter().find(|o| o.id == order_id))
+                .and_then(|o| self.peg_price(o, reference));
+            let price_levels = match side {
+                OrderSide::Buy => &mut self.buy_price_levels,
+                OrderSide::Sell => &mut self.sell_price_levels,
+            };
+            let Some(level) = price_levels.get_mut(&price_key) else {
+                continue;
+            };
+            match new_price {
+                Some(price) if Self::price_to_key(price, side == OrderSide::Buy) != price_key => {
+                    let mut order = level.remove_order(order_id).unwrap();
+                    if level.is_empty() {
+                        price_levels.remove(&price_key);
+                    }
+                    self.orders_by_id.remove(&order_id);
+                    self.orders_by_owner.remove(&order);
+                    order.price = Some(price);
+                    repriced.push(order);
+                }
+                _ => {
+                    self.pegged_orders.insert(order_id);
+                }
+            }
+        }
+
+        let any_repriced = !repriced.is_empty();
+        for order in repriced {
+            self.process_limit_order(order);
+        }
+        any_repriced
+    }
+
+    // Best price on one side with an order visible in depth
+    fn best_displayed_price(&self, is_buy: bool) -> Option<f64> {
+        let price_levels = if is_buy {
+            &self.buy_price_levels
+        } else {
+            &self.sell_price_levels
+        };
+        price_levels
+            .values()
+            .find(|level| level.has_displayed_orders())
+            .map(|level| level.price)
+    }
+
+    fn execute_order(&mut self, mut order: Order) {
+        // Nothing trades outside continuous trading: hold the order until matching resumes
+        if self.trading_state != TradingState::ContinuousTrading {
+            self.auction_orders.push(order);
+            return;
+        }
+
+        self.apply_peg(&mut order);
+        if let Some(reason) = self.price_rejection(&order) {
+            self.reject_order(order, reason);
+            return;
+        }
+
+        // Reduce-only orders are trimmed to the owner's position, or rejected if there is
+        // nothing on their side to reduce
+        if order.options.reduce_only {
+            let reducible = self.reducible_quantity(&order);
+            if reducible <= 0.0 {
+                self.reject_order(order, RejectReason::NothingToReduce);
+                return;
+            }
+            if order.remaining_quantity > reducible {
+                order.quantity -= order.remaining_quantity - reducible;
+                order.remaining_quantity = reducible;
+            }
+        }
+
+        // FOK orders are probed up front so a partial fill never touches the book
+        if order.options.time_in_force == TimeInForce::Fok
+            && self.fillable_quantity(&order) < order.quantity
+        {
+            self.reject_order(order, RejectReason::FillOrKillUnfilled);
+            return;
+        }
+
+        // Orders with a minimum execution quantity (all of it, for AON orders) must trade
+        // at least that much on entry if they trade at all; orders that cannot rest must
+        // trade it regardless
+        if let Some(min_quantity) = order.min_execution_quantity() {
+            let achievable = self.fillable_quantity(&order);
+            let must_trade = order.order_type == OrderType::Market
+                || order.options.time_in_force == TimeInForce::Ioc
+                || achievable > 0.0;
+            if must_trade && achievable < min_quantity {
+                self.reject_order(order, RejectReason::MinimumQuantityUnmet);
+                return;
+            }
+        }
+
+        match order.order_type {
+            OrderType::Market => self.process_market_order(order),
+            OrderType::Limit => self.process_limit_order(order),
+        }
+    }
+
+    fn process_limit_order(&mut self, mut order: Order) {
+        // Amended and repriced orders re-enter through here
+        if self.trading_state != TradingState::ContinuousTrading {
+            self.auction_orders.push(order);
+            return;
+        }
+
+        // Post-only orders must add liquidity, so reprice or reject them instead of taking
+        if order.options.post_only && self.would_cross(&order) {
+            let is_buy = order.side == OrderSide::Buy;
+            match self.next_level_key(is_buy, None) {
+                Some(best_key) if order.options.post_only_reprice => {
+                    order.price = Some(Self::key_to_price(best_key, is_buy));
+                }
+                _ => {
+                    self.reject_order(order, RejectReason::PostOnlyWouldCross);
+                    return;
+                }
+            }
+        }
+
+        // Try to match the order first
+        self.match_limit_order(&mut order);
+
+        // A volatility interruption cut matching short: hold the rest for the auction
+        if self.trading_state != TradingState::ContinuousTrading && order.remaining_quantity > 0.0 {
+            self.auction_orders.push(order);
+            return;
+        }
+
+        // IOC orders never rest: whatever did not match immediately is cancelled
+        if order.options.time_in_force == TimeInForce::Ioc && order.remaining_quantity > 0.0 {
+            order.status = OrderStatus::Cancelled;
+        }
+
+        // If order is not completely filled, add it to the order book
+        self.rest_or_finish(order);
+    }
+
+    // Price a pegged order from the current reference prices, keeping its submitted price
+    // as its peg limit
+    fn apply_peg(&self, order: &mut Order) {
+        if order.options.peg.is_some() {
+            order.peg_limit = order.price;
+            let reference = (
+                self.peg_reference_price(true),
+                self.peg_reference_price(false),
+            );
+            if let Some(price) = self.peg_price(order, reference) {
+                order.price = Some(price);
+            }
+        }
+    }
+
+    // Why a limit order's price keeps it out of the book, if it does
+    fn price_rejection(&self, order: &Order) -> Option<RejectReason> {
+        if order.order_type != OrderType::Limit {
+            return None;
+        }
+        if self.breaches_price_collar(order) {
+            Some(RejectReason::PriceCollar)
+        } else if self.breaches_price_bands(order.price.unwrap()) {
+            Some(RejectReason::PriceBand)
+        } else {
+            None
+        }
+    }
+
+    // Quantity available to an incoming order on the opposite side up to where matching
+    // would stop (its limit price, or a market order's slippage, worst price and protection
+    // limits), stopping early once the order's full quantity is covered
+    fn fillable_quantity(&self, order: &Order) -> f64 {
+        let opposite_levels = match order.side {
+            OrderSide::Buy => &self.sell_price_levels,
+            OrderSide::Sell => &self.buy_price_levels,
+        };
+
+        let limit_price = match order.order_type {
+            OrderType::Market => self.market_limit_price(order),
+            OrderType::Limit => order.price,
+        };
+        let mut available = 0.0;
+        for level in opposite_levels.values() {
+            let within_limit = match (limit_price, order.side) {
+                (None, _) => true,
+                (Some(limit), OrderSide::Buy) => level.price <= limit,
+                (Some(limit), OrderSide::Sell) => level.price >= limit,
+            };
+            if !within_limit || available >= order.quantity {
+                break;
+            }
+            // Matching stops at the first price that breaches a band or the short sale rule
+            if self.breaches_volatility_band(level.price)
+                || self.breaches_price_bands(level.price)
+                || order.is_short_sale() && !self.short_sale_allowed(level.price)
+            {
+                break;
+            }
+            for resting_order in &level.orders {
+                // Resting orders whose minimum execution quantity the rest of the order
+                // cannot meet will be passed over
+                let fill = resting_order
+                    .remaining_quantity
+                    .min(order.quantity - available);
+                let meets_minimum =
+                    resting_order
+                        .min_execution_quantity()
+                        .is_none_or(|min_quantity| {
+                            fill >= min_quantity.min(resting_order.remaining_quantity)
+                        });
+                if meets_minimum {
+                    available += fill;
+                }
+            }
+        }
+        available
+    }
+
+    // Whether a limit order is priced further through the best opposite price than the
+    // price collar allows
+    fn breaches_price_collar(&self, order: &Order) -> bool {
+        let Some(collar) = self.price_collar else {
+            return false;
+        };
+        let price = order.price.unwrap();
+        let resting_is_buy = order.side == OrderSide::Sell;
+        let Some(best_key) = self.next_level_key(resting_is_buy, None) else {
+            return false;
+        };
+        let best_price = Self::key_to_price(best_key, resting_is_buy);
+        let width = collar.width(best_price, self.tick_size);
+        match order.side {
+            OrderSide::Buy => price > best_price + width,
+            OrderSide::Sell => price < best_price - width,
+        }
+    }
+
+    // Whether trading at `price` would move further than the volatility band from a trade
+    // inside the window
+    fn breaches_volatility_band(&self, price: f64) -> bool {
+        let Some(interruption) = self.volatility_interruption else {
+            return false;
+        };
+        let since = self.clock.saturating_sub(interruption.window);
+        self.recent_trade_prices
+            .iter()
+            .filter(|&&(timestamp, _)| timestamp >= since)
+            .any(|&(_, reference)| {
+                (price - reference).abs() > interruption.band.width(reference, self.tick_size)
+            })
+    }
+
+    // Whether `price` lies outside the static or the dynamic price band
+    fn breaches_price_bands(&self, price: f64) -> bool {
+        let Some(bands) = self.price_bands else {
+            return false;
+        };
+        let outside = |(reference, band): (f64, ProtectionBand)| {
+            (price - reference).abs() > band.width(reference, self.tick_size)
+        };
+        bands
+            .static_reference
+            .zip(bands.static_band)
+            .is_some_and(outside)
+            || self
+                .reference_price()
+                .zip(bands.dynamic_band)
+                .is_some_and(outside)
+    }
+
+    // Whether a short sale may execute at `price` under the short sale rule
+    fn short_sale_allowed(&self, price: f64) -> bool {
+        let ShortSaleRule::TickTest = self.short_sale_rule else {
+            return true;
+        };
+        let Some(last) = self.last_trade_price else {
+            return true;
+        };
+        let (price, last) = (price_units(price), price_units(last));
+        price > last
+            || price == last
+                && self
+                    .last_different_price
+                    .is_none_or(|different| last > price_units(different))
+    }
+
+    fn start_volatility_auction(&mut self) {
+        if let Some(interruption) = self.volatility_interruption {
+            self.trading_state = TradingState::Auction;
+            self.auction_call = Some(AuctionSession::Volatility);
+            self.volatility_auction_end = Some(self.clock + interruption.auction_duration);
+        }
+    }
+
+    // Finish an order as rejected on entry
+    fn reject_order(&mut self, mut order: Order, reason: RejectReason) {
+        order.status = OrderStatus::Rejected;
+        order.reject_reason = Some(reason);
+        self.finished_orders.record(&order);
+        self.audit.record_exit(&order, self.clock);
+        self.order_links.touch(&order);
+    }
+
+    // Whether a limit order would immediately match against the opposite best price
+    fn would_cross(&self, order: &Order) -> bool {
+        let price = order.price.unwrap();
+        let resting_is_buy = order.side == OrderSide::Sell;
+        match self.next_level_key(resting_is_buy, None) {
+            Some(best_key) => {
+                let best_price = Self::key_to_price(best_key, resting_is_buy);
+                match order.side {
+                    OrderSide::Buy => price >= best_price,
+                    OrderSide::Sell => price <= best_price,
+                }
+            }
+            None => false,
+        }
+    }
+
+    // Price a market order must not trade through: the tightest of its slippage limit,
+    // its worst price and the book's market protection band, if any is set
+    fn market_limit_price(&self, order: &Order) -> Option<f64> {
+        let resting_is_buy = order.side == OrderSide::Sell;
+        let best_price = self
+            .next_level_key(resting_is_buy, None)
+            .map(|best_key| Self::key_to_price(best_key, resting_is_buy));
+        let slippage_price =
+            order
+                .options
+                .max_slippage_bps
+                .zip(best_price)
+                .map(|(bps, best_price)| match order.side {
+                    OrderSide::Buy => best_price * (1.0 + bps / 10_000.0),
+                    OrderSide::Sell => best_price * (1.0 - bps / 10_000.0),
+                });
+        let protection_price = self.market_protection.and_then(|band| {
+            let reference_price = self.last_trade_price.or(best_price)?;
+            let width = band.width(reference_price, self.tick_size);
+            Some(match order.side {
+                OrderSide::Buy => reference_price + width,
+                OrderSide::Sell => reference_price - width,
+            })
+        });
+        [slippage_price, order.options.worst_price, protection_price]
+            .into_iter()
+            .flatten()
+            .reduce(|a, b| match order.side {
+                OrderSide::Buy => a.min(b),
+                OrderSide::Sell => a.max(b),
+            })
+    }
+
+    fn process_market_order(&mut self, mut order: Order) {
+        let resting_is_buy = order.side == OrderSide::Sell;
+        let band_price = self.market_limit_price(&order);
+        self.match_order(&mut order, band_price);
+        if self.trading_state != TradingState::ContinuousTrading && order.remaining_quantity > 0.0 {
+            self.auction_orders.push(order);
+            return;
+        }
+
+        // Update order status
+        let stopped_by_band = band_price.is_some()
+            && order.remaining_quantity > 0.0
+            && self.next_level_key(resting_is_buy, None).is_some();
+        if order.remaining_quantity <= 0.0 {
+            order.status = OrderStatus::Filled;
+        } else if stopped_by_band && order.filled_quantity <= 0.0 {
+            // Liquidity exists but only outside the band: cancel rather than reject
+            order.status = OrderStatus::Cancelled;
+        } else if order.filled_quantity > 0.0 {
+            order.status = OrderStatus::PartiallyFilled;
+            if order.options.market_remainder == MarketRemainder::ConvertToLimit {
+                // The order was the last to trade, so the last trade price is its own
+                order.order_type = OrderType::Limit;
+                order.price = self.last_trade_price;
+                self.rest_or_finish(order);
+                return;
+            }
+        } else {
+            // Market orders that can't be filled are rejected
+            order.status = OrderStatus::Rejected;
+            order.reject_reason = Some(RejectReason::NoLiquidity);
+        }
+        self.finished_orders.record(&order);
+        self.audit.record_exit(&order, self.clock);
+        self.order_links.touch(&order);
+    }
+
+    fn match_limit_order(&mut self, order: &mut Order) {
+        let price = order.price.unwrap(); // Safe unwrap since we know it's a limit order
+
+        // Discretion lets the order take liquidity beyond its displayed price, unless it
+        // must only add liquidity
+        let limit = match order.discretion_price() {
+            Some(discretion_price) if !order.options.post_only => discretion_price,
+            _ => price,
+        };
+        self.match_order(order, Some(limit));
+
+        // Update order status
+        if order.remaining_quantity <= 0.0 {
+            order.status = OrderStatus::Filled;
+        } else if order.filled_quantity > 0.0 {
+            order.status = OrderStatus::PartiallyFilled;
+        }
+    }
+
+    // Key of the best opposite-side level after `after`, or the best level if `after` is None
+    fn next_level_key(&self, is_buy: bool, after: Option<i64>) -> Option<i64> {
+        let price_levels = if is_buy {
+            &self.buy_price_levels
+        } else {
+            &self.sell_price_levels
+        };
+        match after {
+            Some(key) => price_levels
+                .range((Bound::Excluded(key), Bound::Unbounded))
+                .next()
+                .map(|(&k, _)| k),
+            None => price_levels.keys().next().copied(),
+        }
+    }
+
+    // Match an incoming order against the opposite side in price-time priority,
+    // never trading through `limit_price` when one is given. Resting discretionary orders
+    // beyond the limit price of an incoming limit order still trade with it, at that limit
+    // price, if their discretion reaches it.
+    fn match_order(&mut self, order: &mut Order, limit_price: Option<f64>) {
+        let resting_is_buy = order.side == OrderSide::Sell;
+        let mut cursor = None;
+
+        while order.remaining_quantity > 0.0 {
+            let Some(price_key) = self.next_level_key(resting_is_buy, cursor) else {
+                break;
+            };
+
+            // Stop once the opposite side no longer crosses the limit price, or no resting
+            // discretion could reach it
+            let level_price = Self::key_to_price(price_key, resting_is_buy);
+            let mut trade_price = level_price;
+            let mut discretion_only = false;
+            if let Some(limit) = limit_price {
+                // Keys of either side grow as prices get worse for the incoming order
+                let reaches = |price: f64| {
+                    if self.integer_ticks {
+                        price_key <= Self::price_to_key(price, resting_is_buy)
+                    } else {
+                        match order.side {
+                            OrderSide::Buy => level_price <= price,
+                            OrderSide::Sell => level_price >= price,
+                        }
+                    }
+                };
+                if !reaches(limit) {
+                    let within_discretion = order.order_type == OrderType::Limit
+                        && reaches(match order.side {
+                            OrderSide::Buy => limit + self.max_discretion,
+                            OrderSide::Sell => limit - self.max_discretion,
+                        });
+                    if !within_discretion {
+                        break;
+                    }
+                    trade_price = limit;
+                    discretion_only = true;
+                }
+            }
+
+            if self.breaches_volatility_band(trade_price) {
+                self.start_volatility_auction();
+                break;
+            }
+            if self.breaches_price_bands(trade_price) {
+                if self.price_bands.unwrap().on_breach == BandBreach::Halt {
+                    self.trading_state = TradingState::Halted;
+                }
+                break;
+            }
+            if order.is_short_sale() && !self.short_sale_allowed(trade_price) {
+                break;
+            }
+
+            // Extract orders to process from the level
+            let resting_orders = {
+                let level = self
+                    .get_or_create_price_level(resting_is_buy, price_key, false)
+                    .unwrap();
+                std::mem::take(&mut level.orders)
+            };
+
+            let filled_before = order.filled_quantity;
+            let at_best = cursor.is_none();
+            let orders_to_keep =
+                self.match_level(order, resting_orders, trade_price, discretion_only, at_best);
+
+            // Update the level with remaining orders, dropping it if it became empty
+            let level = self
+                .get_or_create_price_level(resting_is_buy, price_key, false)
+                .unwrap();
+            level.orders = orders_to_keep;
+            level.is_dirty = true;
+            if level.is_empty() {
+                if resting_is_buy {
+                    self.buy_price_levels.remove(&price_key);
+                } else {
+                    self.sell_price_levels.remove(&price_key);
+                }
+            } else if order.filled_quantity > filled_before {
+                // Refreshed iceberg tranches can still trade at this price
+                continue;
+            }
+            cursor = Some(price_key);
+        }
+    }
+
+    // Fill an incoming order against the resting orders of a single price level according to
+    // the matching policy, returning the orders that should stay on the level. With
+    // `discretion_only` just the orders whose discretion reaches `price` may trade.
+    // `at_best` marks the first level the incoming order trades at.
+    fn match_level(
+        &mut self,
+        order: &mut Order,
+        mut resting_orders: Vec<Order>,
+        price: f64,
+        discretion_only: bool,
+        at_best: bool,
+    ) -> Vec<Order> {
+        // OCO siblings of orders that already traded in this sweep must not fill
+        if !self.order_links.pending_cancels.is_empty() {
+            resting_orders.retain_mut(|resting_order| {
+                if !self.order_links.pending_cancels.contains(&resting_order.id) {
+                    return true;
+                }
+                resting_order.status = OrderStatus::Cancelled;
+                self.orders_by_id.remove(&resting_order.id);
+                self.orders_by_owner.remove(resting_order);
+                self.finished_orders.record(resting_order);
+                self.audit.record_exit(resting_order, self.clock);
+                false
+            });
+        }
+        let discretion_limit = discretion_only.then_some(price);
+        let short_sale_allowed = self.short_sale_allowed(price);
+        let allocations = self.allocate_level(
+            order.remaining_quantity,
+            &resting_orders,
+            discretion_limit,
+            short_sale_allowed,
+            at_best,
+        );
+        let mut orders_to_keep = Vec::with_capacity(resting_orders.len());
+        let mut refreshed = Vec::new();
+
+        for (mut resting_order, trade_qty) in resting_orders.into_iter().zip(allocations) {
+            if trade_qty > 0.0 {
+                let flags = if discretion_only {
+                    TradeFlags::DISCRETION
+                } else {
+                    TradeFlags::NONE
+                };
+                self.execute_trade(order, &mut resting_order, price, trade_qty, flags);
+            }
+
+            if self
+                .quantity_rules
+                .is_dust(resting_order.remaining_quantity)
+            {
+                resting_order.status = OrderStatus::Cancelled;
+            }
+
+            if matches!(
+                resting_order.status,
+                OrderStatus::Filled | OrderStatus::Cancelled
+            ) {
+                // Remove filled orders and cancelled dust from the lookup map
+                self.orders_by_id.remove(&resting_order.id);
+                self.orders_by_owner.remove(&resting_order);
+                self.finished_orders.record(&resting_order);
+                self.audit.record_exit(&resting_order, self.clock);
+                self.order_links.touch(&resting_order);
+            } else if resting_order.matchable_quantity() <= 0.0 {
+                // Iceberg tranche exhausted: show the next one, queued per the refill policy
+                resting_order.refresh_display();
+                match self.iceberg_refill {
+                    IcebergRefill::BackOfLevel => refreshed.push(resting_order),
+                    IcebergRefill::KeepPriority => orders_to_keep.push(resting_order),
+                }
+            } else {
+                orders_to_keep.push(resting_order);
+            }
+        }
+
+        orders_to_keep.extend(refreshed);
+        orders_to_keep
+    }
+
+    // Quantity to fill against each resting order of a level. Orders whose share would
+    // fall short of their minimum execution quantity (including AON orders that would not
+    // fill completely) are passed over and their share goes to the orders behind them,
+    // which keep their relative priority.
+    fn allocate_level(
+        &self,
+        incoming_quantity: f64,
+        resting_orders: &[Order],
+        discretion_limit: Option<f64>,
+        short_sale_allowed: bool,
+        at_best: bool,
+    ) -> Vec<f64> {
+        let mut available: Vec<f64> = resting_orders
+            .iter()
+            .map(|o| {
+                let reaches_limit = discretion_limit.is_none_or(|limit| {
+                    o.discretion_price().is_some_and(|price| match o.side {
+                        OrderSide::Buy => price >= limit,
+                        OrderSide::Sell => price <= limit,
+                    })
+                });
+                if !reaches_limit || o.is_short_sale() && !short_sale_allowed {
+                    0.0
+                } else if o.options.reduce_only {
+                    o.matchable_quantity().min(self.reducible_quantity(o))
+                } else {
+                    o.matchable_quantity()
+                }
+            })
+            .collect();
+
+        loop {
+            let allocations =
+                self.allocate_by_priority(incoming_quantity, resting_orders, &available, at_best);
+
+            let mut passed_over = false;
+            for (i, resting_order) in resting_orders.iter().enumerate() {
+                if let Some(min_quantity) = resting_order.min_execution_quantity() {
+                    let allocation = allocations[i];
+                    if allocation > 0.0 && allocation < min_quantity.min(available[i]) {
+                        available[i] = 0.0;
+                        passed_over = true;
+                    }
+                }
+            }
+            if !passed_over {
+                return allocations;
+            }
+        }
+    }
+
+    // Serve the lead market maker (at the best price) and then priority orders their
+    // shares of the incoming quantity in time priority, then allocate the rest among all
+    // orders as usual
+    fn allocate_by_priority(
+        &self,
+        incoming_quantity: f64,
+        resting_orders: &[Order],
+        available: &[f64],
+        at_best: bool,
+    ) -> Vec<f64> {
+        let mut priority_allocations = vec![0.0; resting_orders.len()];
+        let mut serve = |share: f64, eligible: &dyn Fn(&Order) -> bool| {
+            let mut priority_quantity = incoming_quantity * share;
+            for (i, resting_order) in resting_orders.iter().enumerate() {
+                if priority_quantity <= 0.0 {
+                    break;
+                }
+                if eligible(resting_order) {
+                    let allocation =
+                        (available[i] - priority_allocations[i]).min(priority_quantity);
+                    priority_allocations[i] += allocation;
+                    priority_quantity -= allocation;
+                }
+            }
+        };
+        if let Some(lmm) = self.lead_market_maker.filter(|_| at_best) {
+            serve(lmm.share, &|order| {
+                order.options.owner_id == Some(lmm.owner_id)
+            });
+        }
+        serve(self.priority_share, &|order| order.options.priority);
+        let priority_total: f64 = priority_allocations.iter().sum();
+        if priority_total <= 0.0 {
+            return self.allocate_by_visibility(incoming_quantity, resting_orders, available);
+        }
+
+        let remaining: Vec<f64> = available
+            .iter()
+            .zip(&priority_allocations)
+            .map(|(quantity, allocation)| quantity - allocation)
+            .collect();
+        self.allocate_by_visibility(
+            incoming_quantity - priority_total,
+            resting_orders,
+            &remaining,
+        )
+        .into_iter()
+        .zip(priority_allocations)
+        .map(|(allocation, priority_allocation)| allocation + priority_allocation)
+        .collect()
+    }
+
+    // Apply the matching policy to a level, serving hidden orders after displayed ones
+    // when the hidden priority asks for it
+    fn allocate_by_visibility(
+        &self,
+        incoming_quantity: f64,
+        resting_orders: &[Order],
+        available: &[f64],
+    ) -> Vec<f64> {
+        match self.hidden_priority {
+            HiddenPriority::BehindDisplayed if resting_orders.iter().any(|o| o.options.hidden) => {
+                let mut allocations = vec![0.0; resting_orders.len()];
+                let mut incoming_quantity = incoming_quantity;
+                for hidden in [false, true] {
+                    let group: Vec<usize> = (0..resting_orders.len())
+                        .filter(|&i| resting_orders[i].options.hidden == hidden)
+                        .collect();
+                    let group_available: Vec<f64> = group.iter().map(|&i| available[i]).collect();
+                    let group_allocations =
+                        self.allocate_by_policy(incoming_quantity, &group_available);
+                    for (&i, allocation) in group.iter().zip(group_allocations) {
+                        allocations[i] = allocation;
+                        incoming_quantity -= allocation;
+                    }
+                }
+                allocations
+            }
+            _ => self.allocate_by_policy(incoming_quantity, available),
+        }
+    }
+
+    // Pro-rata shares are rounded to the book's lot size, or whole units without one
+    fn allocate_by_policy(&self, incoming_quantity: f64, available: &[f64]) -> Vec<f64> {
+        let lot_size = match self.quantity_rules.lot_size {
+            lot_size if lot_size > 0.0 => lot_size,
+            _ => 1.0,
+        };
+        self.matching_policy
+            .allocate_in_lots(incoming_quantity, available, lot_size)
+    }
+
+    // Execute a trade between the incoming (taker) order and a resting (maker) order
+    fn execute_trade(
+        &mut self,
+        taker: &mut Order,
+        maker: &mut Order,
+        price: f64,
+        quantity: f64,
+        mut flags: TradeFlags,
+    ) {
+        let quantity = match self.quantity_decimals {
+            Some(decimals) => units_quantity(quantity_units(quantity, decimals), decimals),
+            None => quantity,
+        };
+        taker.fill(quantity, self.quantity_decimals);
+        maker.fill(quantity, self.quantity_decimals);
+        self.order_links.touch(taker);
+        self.order_links.touch(maker);
+
+        let notional = price * quantity * self.instrument.contract_multiplier;
+        let maker_fee = notional * self.fee_schedule.maker_bps / 10_000.0;
+        let taker_fee = notional * self.fee_schedule.taker_bps / 10_000.0;
+        self.accrued_maker_fees += maker_fee;
+        self.accrued_taker_fees += taker_fee;
+
+        let (buy_order, sell_order) = match taker.side {
+            OrderSide::Buy => (&*taker, &*maker),
+            OrderSide::Sell => (&*maker, &*taker),
+        };
+        if let Some(owner) = buy_order.options.owner_id {
+            *self.positions.entry(owner).or_default() += quantity;
+        }
+        if let Some(owner) = sell_order.options.owner_id {
+            *self.positions.entry(owner).or_default() -= quantity;
+        }
+        let owners = [buy_order.options.owner_id, sell_order.options.owner_id];
+        let wash_trade = self.is_wash_trade(owners[0], owners[1]);
+        if wash_trade {
+            flags |= TradeFlags::WASH_TRADE;
+        }
+        if owners.contains(&Some(IMPLIED_OWNER_ID)) {
+            flags |= TradeFlags::IMPLIED;
+        }
+        let trade = Trade {
+            id: self.next_trade_id,
+            buy_order_id: buy_order.id,
+            sell_order_id: sell_order.id,
+            price,
+            quantity,
+            timestamp: std::cmp::max(buy_order.timestamp, sell_order.timestamp),
+            symbol: buy_order
+                .symbol
+                .clone()
+                .or_else(|| sell_order.symbol.clone()),
+            maker_fee,
+            taker_fee,
+            buyer_owner_id: buy_order.options.owner_id,
+            seller_owner_id: sell_order.options.owner_id,
+            busted: false,
+            aggressor_side: taker.side,
+            maker_order_id: maker.id,
+            taker_order_id: taker.id,
+            wash_trade,
+            aggressor_limit_price: taker.price,
+            flags,
+            notional,
+        };
+        let timestamp = trade.timestamp;
+        let price_improvement = trade
+            .price_improvement()
+            .filter(|&improvement| improvement > 0.0);
+        self.next_trade_id += 1;
+        for order_id in [trade.buy_order_id, trade.sell_order_id] {
+            self.fills_by_order
+                .entry(order_id)
+                .or_default()
+                .push(self.trades.len());
+        }
+        if self
+            .trades
+            .last()
+            .is_some_and(|last| last.timestamp > timestamp)
+        {
+            self.trades_in_time_order = false;
+        }
+        self.trades.push(trade);
+        for order in [&*taker, &*maker] {
+            let action = match order.status {
+                OrderStatus::Filled => AuditAction::Filled,
+                _ => AuditAction::PartiallyFilled,
+            };
+            self.audit.record(order, action, timestamp);
+        }
+        self.stats.trades_executed += 1;
+        self.stats.total_trade_volume += quantity;
+        self.stats.total_notional += notional;
+        if wash_trade {
+            self.stats.wash_trades += 1;
+            self.stats.wash_trade_volume += quantity;
+        }
+        if let Some(improvement) = price_improvement {
+            match taker.side {
+                OrderSide::Buy => {
+                    self.stats.buy_price_improved_trades += 1;
+                    self.stats.buy_price_improvement += improvement * quantity;
+                }
+                OrderSide::Sell => {
+                    self.stats.sell_price_improved_trades += 1;
+                    self.stats.sell_price_improvement += improvement * quantity;
+                }
+            }
+        }
+        self.session.record_trade(price, quantity);
+        if self
+            .last_trade_price
+            .is_some_and(|last| price_units(last) != price_units(price))
+        {
+            self.last_different_price = self.last_trade_price;
+        }
+        self.last_trade_price = Some(price);
+        if let Some(interruption) = self.volatility_interruption {
+            let since = timestamp.saturating_sub(interruption.window);
+            while self
+                .recent_trade_prices
+                .front()
+                .is_some_and(|&(trade_timestamp, _)| trade_timestamp < since)
+            {
+                self.recent_trade_prices.pop_front();
+            }
+            self.recent_trade_prices.push_back((timestamp, price));
+        }
+    }
+
+    pub fn cancel_order(&mut self, order_id: u64) -> bool {
+        self.record_event(|| BookEvent::Cancel { order_id });
+
+        let cancelled = self.remove_and_cancel(order_id);
+        if cancelled {
+            self.settle();
+        }
+        cancelled
+    }
+
+    /// Amend the price and/or total quantity of a resting order.
+    ///
+    /// A quantity decrease at an unchanged price keeps the order's place in the queue.
+    /// A price change or quantity increase sends it to the back of the queue at its new
+    /// price, where it may match immediately like a new order. `new_quantity` is the new
+    /// total order quantity and must exceed what has already filled. Returns `Ok(false)`
+    /// if the order is not resting.
+    pub fn modify_order(
+        &mut self,
+        order_id: u64,
+        new_price: Option<f64>,
+        new_quantity: Option<f64>,
+    ) -> Result<bool, OrderError> {
+        self.record_event(|| BookEvent::Modify {
+            order_id,
+            new_price,
+            new_quantity,
+        });
+
+        let modified = self.amend_order(order_id, new_price, new_quantity);
+        self.settle();
+        modified
+    }
+
+    /// Apply many `(order_id, new_price, new_quantity)` amendments, each as
+    /// `modify_order` would, returning one result per amendment in order.
+    ///
+    /// Amendments are applied in turn, so a repriced order may trade with orders later in
+    /// the batch. Stop triggers, links and pegs are only settled once, after the last one.
+    pub fn modify_orders(
+        &mut self,
+        amendments: &[(u64, Option<f64>, Option<f64>)],
+    ) -> Vec<Result<bool, OrderError>> {
+        self.record_event(|| BookEvent::ModifyBatch(amendments.to_vec()));
+
+        let results = amendments
+            .iter()
+            .map(|&(order_id, new_price, new_quantity)| {
+                self.amend_order(order_id, new_price, new_quantity)
+            })
+            .collect();
+        self.settle();
+        results
+    }
+
+    // `modify_order` without recording an event or settling afterwards
+    fn amend_order(
+        &mut self,
+        order_id: u64,
+        new_price: Option<f64>,
+        new_quantity: Option<f64>,
+    ) -> Result<bool, OrderError> {
+        let Some(&(side, price_key)) = self.orders_by_id.get(&order_id) else {
+            return Ok(false);
+        };
+        let new_price = new_price
+            .map(|price| self.tick_price(side, price))
+            .transpose()?;
+        let price_levels = match side {
+            OrderSide::Buy => &mut self.buy_price_levels,
+            OrderSide::Sell => &mut self.sell_price_levels,
+        };
+        let level = price_levels.get_mut(&price_key).unwrap();
+        let pos = level.orders.iter().position(|o| o.id == order_id).unwrap();
+        let order = &level.orders[pos];
+        let quantity = new_quantity.unwrap_or(order.quantity);
+        if quantity <= order.filled_quantity {
+            return Err(OrderError::InvalidAmendQuantity {
+                quantity,
+                filled_quantity: order.filled_quantity,
+            });
+        }
+        self.quantity_rules.validate(quantity)?;
+        validate_quantity_decimals(quantity, self.quantity_decimals)?;
+
+        let price = new_price.unwrap_or(level.price);
+        let same_level = Self::price_to_key(price, side == OrderSide::Buy) == price_key;
+        if same_level && quantity <= order.quantity {
+            // Reduced in place: keeps priority
+            let order = &mut level.orders[pos];
+            order.remaining_quantity -= order.quantity - quantity;
+            order.quantity = quantity;
+            order.display_remaining = order.display_remaining.min(order.remaining_quantity);
+            order.version += 1;
+            level.is_dirty = true;
+            self.audit.record(order, AuditAction::Amended, self.clock);
+        } else {
+            let mut order = self.take_resting_order(order_id).unwrap();
+            order.remaining_quantity += quantity - order.quantity;
+            order.quantity = quantity;
+            order.price = Some(price);
+            order.refresh_display();
+            order.version += 1;
+            self.audit.record(&order, AuditAction::Amended, self.clock);
+            self.process_limit_order(order);
+        }
+        Ok(true)
+    }
+
+    /// Atomically cancel a resting order and enter a replacement at `new_price` with a
+    /// new total quantity of `new_quantity`, returning the replacement's id.
+    ///
+    /// The replacement keeps the original's side, symbol, options and links, and only
+    /// enters for what the original had not filled yet, so the same quantity can never
+    /// execute twice. It joins the back of the queue and may match immediately. Nothing
+    /// changes if the replacement is invalid, or if the book is halted or closed or would
+    /// reject the replacement's price (`OrderError::Refused`). Returns `Ok(None)` if the
+    /// order is not resting.
+    pub fn cancel_replace(
+        &mut self,
+        order_id: u64,
+        new_price: f64,
+        new_quantity: f64,
+        timestamp: u64,
+    ) -> Result<Option<u64>, OrderError> {
+        self.record_event(|| BookEvent::CancelReplace {
+            order_id,
+            new_price,
+            new_quantity,
+            timestamp,
+        });
+
+        let Some(&(side, price_key)) = self.orders_by_id.get(&order_id) else {
+            return Ok(None);
+        };
+        let price_levels = match side {
+            OrderSide::Buy => &self.buy_price_levels,
+            OrderSide::Sell => &self.sell_price_levels,
+        };
+        let original = price_levels
+            .get(&price_key)
+            .and_then(|level| level.orders.iter().find(|o| o.id == order_id))
+            .unwrap();
+        if new_quantity <= original.filled_quantity {
+            return Err(OrderError::InvalidAmendQuantity {
+                quantity: new_quantity,
+                filled_quantity: original.filled_quantity,
+            });
+        }
+        let quantity = new_quantity - original.filled_quantity;
+        let new_price = self.tick_price(side, new_price)?;
+        self.validate_order(side, quantity, timestamp, &original.options)?;
+        if let Some(reason) = self.closed_reason() {
+            return Err(OrderError::Refused { reason });
+        }
+        // Price checks are made on entry during continuous trading, so a replacement they
+        // would reject leaves the original resting instead
+        if self.trading_state == TradingState::ContinuousTrading {
+            let mut probe = original.clone();
+            probe.price = Some(new_price);
+            self.apply_peg(&mut probe);
+            if let Some(reason) = self.price_rejection(&probe) {
+                return Err(OrderError::Refused { reason });
+            }
+        }
+
+        let mut original = self.take_resting_order(order_id).unwrap();
+        let mut replacement = self.new_order((
+            side,
+            OrderType::Limit,
+            Some(new_price),
+            quantity,
+            timestamp,
+            original.symbol.clone(),
+        ));
+        replacement.options = original.options.clone();
+        original.status = OrderStatus::Cancelled;
+        self.finished_orders.record(&original);
+        self.audit.record_exit(&original, self.clock);
+        self.order_links.replace(order_id, replacement.id);
+        self.index_client_order_id(&replacement);
+        self.schedule_expiry(&replacement);
+
+        let replacement_id = replacement.id;
+        self.audit
+            .record(&replacement, AuditAction::Accepted, timestamp);
+        self.execute_order(replacement);
+        self.settle();
+        Ok(Some(replacement_id))
+    }
+
+    // Cancel a resting or pending stop order without recording an event
+    fn remove_and_cancel(&mut self, order_id: u64) -> bool {
+        if self.orders_by_id.contains_key(&order_id) {
+            let Some(mut order) = self.take_resting_order(order_id) else {
+                return false;
+            };
+            order.status = OrderStatus::Cancelled;
+            self.finished_orders.record(&order);
+            self.audit.record_exit(&order, self.clock);
+            self.order_links.touch(&order);
+            return true;
+        }
+        self.cancel_pending_order(order_id)
+    }
+
+    // Take a resting order out of its price level, dropping the level once it is empty
+    fn take_resting_order(&mut self, order_id: u64) -> Option<Order> {
+        let (side, price_key) = self.orders_by_id.remove(&order_id)?;
+        let price_levels = match side {
+            OrderSide::Buy => &mut self.buy_price_levels,
+            OrderSide::Sell => &mut self.sell_price_levels,
+        };
+        let level = price_levels.get_mut(&price_key)?;
+        let order = level.remove_order(order_id)?;
+        if level.is_empty() {
+            price_levels.remove(&price_key);
+        }
+        self.orders_by_owner.remove(&order);
+        Some(order)
+    }
+
+    // Remove an order still waiting in the trigger book or for an auction
+    fn cancel_pending_order(&mut self, order_id: u64) -> bool {
+        let found = self.triggers.remove(order_id).or_else(|| {
+            let pos = self.auction_orders.iter().position(|o| o.id == order_id)?;
+            Some(self.auction_orders.remove(pos))
+        });
+        let Some(mut order) = found else {
+            return false;
+        };
+        order.status = OrderStatus::Cancelled;
+        self.finished_orders.record(&order);
+        self.audit.record_exit(&order, self.clock);
+        self.order_links.touch(&order);
+        true
+    }
+
+    /// Cancel every resting order matching all of the given filters and return their ids.
+    ///
+    /// `None` filters match everything, so `cancel_all(None, None, None)` empties the book.
+    /// `price_range` is inclusive on both ends.
+    pub fn cancel_all(
+        &mut self,
+        side: Option<OrderSide>,
+        price_range: Option<(f64, f64)>,
+        owner_id: Option<u64>,
+    ) -> Vec<u64> {
+        self.record_event(|| BookEvent::CancelAll {
+            side,
+            price_range,
+            owner_id,
+        });
+
+        let mut cancelled = Vec::new();
+
+        for level_side in [OrderSide::Buy, OrderSide::Sell] {
+            if side.is_some_and(|s| s != level_side) {
+                continue;
+            }
+
+            let is_buy = level_side == OrderSide::Buy;
+            let key_range = Self::key_range(is_buy, price_range);
+            self.cancel_where(is_buy, key_range, &mut cancelled, |order| {
+                owner_id.is_none_or(|owner| order.options.owner_id == Some(owner))
+            });
+        }
+        self.settle();
+
+        cancelled
+    }
+
+    /// Cancel every order of one participant, resting or still waiting to enter the book,
+    /// e.g. to pull all of its quotes on a risk event. Returns the cancelled ids.
+    pub fn cancel_participant_orders(&mut self, owner_id: u64) -> Vec<u64> {
+        self.record_event(|| BookEvent::CancelParticipant { owner_id });
+
+        let mut cancelled: Vec<u64> = self.orders_by_owner.ids(owner_id).collect();
+        cancelled.extend(
+            self.triggers
+                .orders()
+                .chain(&self.auction_orders)
+                .filter(|o| o.options.owner_id == Some(owner_id))
+                .map(|o| o.id),
+        );
+        cancelled.retain(|&order_id| self.remove_and_cancel(order_id));
+        self.settle();
+
+        cancelled
+    }
+
+    /// Cancel every order for `symbol`, resting or still waiting to enter the book, e.g.
+    /// to simulate a halt or delisting. With `owner_id` only that participant's orders
+    /// are cancelled. Returns the cancelled ids.
+    pub fn cancel_symbol_orders(&mut self, symbol: &str, owner_id: Option<u64>) -> Vec<u64> {
+        self.record_event(|| BookEvent::CancelSymbol {
+            symbol: symbol.to_string(),
+            owner_id,
+        });
+
+        let mut cancelled: Vec<u64> = self
+            .buy_price_levels
+            .values()
+            .chain(self.sell_price_levels.values())
+            .flat_map(|level| level.orders.iter())
+            .chain(self.triggers.orders())
+            .chain(&self.auction_orders)
+            .filter(|o| {
+                o.symbol.as_deref() == Some(symbol)
+                    && owner_id.is_none_or(|owner| o.options.owner_id == Some(owner))
+            })
+            .map(|o| o.id)
+            .collect();
+        cancelled.retain(|&order_id| self.remove_and_cancel(order_id));
+        self.settle();
+
+        cancelled
+    }
+
+    /// Open a client session and return its id. Orders submitted with this id in
+    /// `OrderOptions::session_id` are cancelled when the session closes, which happens on
+    /// `close_session` or, given a `heartbeat_timeout`, once `expire_sessions` runs more
+    /// than that long after the last heartbeat (starting from `timestamp`).
+    pub fn open_session(&mut self, heartbeat_timeout: Option<u64>, timestamp: u64) -> u64 {
+        self.record_event(|| BookEvent::OpenSession {
+            heartbeat_timeout,
+            timestamp,
+        });
+
+        let session_id = self.next_client_session_id;
+        self.next_client_session_id += 1;
+        self.client_sessions.insert(
+            session_id,
+            ClientSession {
+                heartbeat_timeout,
+                last_heartbeat: timestamp,
+            },
+        );
+        session_id
+    }
+
+    /// Record a heartbeat from a client session. Returns false if it is not open.
+    pub fn heartbeat(&mut self, session_id: u64, timestamp: u64) -> bool {
+        self.record_event(|| BookEvent::Heartbeat {
+            session_id,
+            timestamp,
+        });
+
+        match self.client_sessions.get_mut(&session_id) {
+            Some(session) => {
+                session.last_heartbeat = session.last_heartbeat.max(timestamp);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Close a client session and cancel its orders, resting or still waiting to enter
+    /// the book. Returns the cancelled ids.
+    pub fn close_session(&mut self, session_id: u64) -> Vec<u64> {
+        self.record_event(|| BookEvent::CloseSession { session_id });
+
+        let cancelled = self.drop_client_session(session_id);
+        self.settle();
+        cancelled
+    }
+
+    /// Close every client session whose last heartbeat is more than its timeout before
+    /// `now`, as on a disconnect, and return the ids of the orders cancelled with them
+    pub fn expire_sessions(&mut self, now: u64) -> Vec<u64> {
+        self.record_event(|| BookEvent::ExpireSessions { now });
+
+        let timed_out: Vec<u64> = self
+            .client_sessions
+            .iter()
+            .filter(|(_, session)| {
+                session
+                    .heartbeat_timeout
+                    .is_some_and(|timeout| now.saturating_sub(session.last_heartbeat) > timeout)
+            })
+            .map(|(&session_id, _)| session_id)
+            .collect();
+        let mut cancelled = Vec::new();
+        for session_id in timed_out {
+            cancelled.extend(self.drop_client_session(session_id));
+        }
+        self.settle();
+        cancelled
+    }
+
+    pub fn client_sessions(&self) -> &BTreeMap<u64, ClientSession> {
+        &self.client_sessions
+    }
+
+    // Forget a client session and cancel its orders without recording an event
+    fn drop_client_session(&mut self, session_id: u64) -> Vec<u64> {
+        if self.client_sessions.remove(&session_id).is_none() {
+            return Vec::new();
+        }
+        let mut cancelled: Vec<u64> = self
+            .buy_price_levels
+            .values()
+            .chain(self.sell_price_levels.values())
+            .flat_map(|level| level.orders.iter())
+            .chain(self.triggers.orders())
+            .chain(&self.auction_orders)
+            .filter(|o| o.options.session_id == Some(session_id))
+            .map(|o| o.id)
+            .collect();
+        cancelled.retain(|&order_id| self.remove_and_cancel(order_id));
+        cancelled
+    }
+
+    /// Close the trading session: cancel every DAY order, resting or still waiting to
+    /// enter the book, and start the next session with fresh counters and no auctions
+    /// run. Returns the finished session's statistics, which are also kept in
+    /// `session_history`.
+    pub fn end_session(&mut self) -> SessionStats {
+        self.record_event(|| BookEvent::EndSession);
+
+        let is_day = |order: &Order| order.options.time_in_force == TimeInForce::Day;
+        let mut cancelled = Vec::new();
+        for is_buy in [true, false] {
+            self.cancel_where(
+                is_buy,
+                (Bound::Unbounded, Bound::Unbounded),
+                &mut cancelled,
+                is_day,
+            );
+        }
+        let pending: Vec<u64> = self
+            .triggers
+            .orders()
+            .chain(&self.auction_orders)
+            .filter(|o| is_day(o))
+            .map(|o| o.id)
+            .collect();
+        for order_id in pending {
+            if self.remove_and_cancel(order_id) {
+                cancelled.push(order_id);
+            }
+        }
+
+        let next = SessionStats {
+            session: self.session.session + 1,
+            ..SessionStats::default()
+        };
+        let mut finished = std::mem::replace(&mut self.session, next);
+        self.session_first_trade = self.trades.len();
+        finished.day_orders_cancelled = cancelled.len();
+        self.session_history.push(finished.clone());
+        self.completed_auctions.clear();
+        self.settle();
+
+        finished
+    }
+
+    /// Activity in the current trading session so far
+    pub fn session_stats(&self) -> &SessionStats {
+        &self.session
+    }
+
+    /// Statistics of every finished session, oldest first
+    pub fn session_history(&self) -> &[SessionStats] {
+        &self.session_history
+    }
+
+    /// Record that the session's opening or closing auction has run without uncrossing.
+    /// Held orders targeting it are cancelled and their ids returned; orders targeting it
+    /// later are rejected. Ends the auction's call phase like `run_auction`.
+    pub fn complete_auction(&mut self, session: AuctionSession) -> Vec<u64> {
+        self.record_event(|| BookEvent::CompleteAuction(session));
+
+        let expired_ids = self.finish_auction(session);
+        self.settle();
+        expired_ids
+    }
+
+    /// Enter the call phase of an auction. Until `run_auction` uncrosses it nothing trades:
+    /// new orders, triggered stops and amended or repriced orders are held for the auction
+    /// instead of matching, while resting orders stay where they are. Returns false if the
+    /// auction has already run this session.
+    pub fn start_auction(&mut self, session: AuctionSession) -> bool {
+        self.record_event(|| BookEvent::StartAuction(session));
+
+        if self.completed_auctions.contains(&session) {
+            return false;
+        }
+        self.trading_state = match session {
+            AuctionSession::Open => TradingState::PreOpen,
+            _ => TradingState::Auction,
+        };
+        self.auction_call = Some(session);
+        true
+    }
+
+    /// Move the book to another trading state.
+    ///
+    /// `PreOpen` starts the opening auction's call phase and `Auction` an unscheduled
+    /// one, uncrossed by `run_auction` with `AuctionSession::Volatility`, unless an
+    /// auction is already in its call phase. `ContinuousTrading` uncrosses the auction in
+    /// its call phase, if any, then releases the orders held while matching was
+    /// suspended. `Halted` and `Closed` reject new orders and hold anything that would
+    /// otherwise match, such as triggered stops, until trading resumes.
+    pub fn set_trading_state(&mut self, state: TradingState) {
+        self.record_event(|| BookEvent::SetTradingState(state));
+
+        match state {
+            TradingState::PreOpen => self.auction_call = Some(AuctionSession::Open),
+            TradingState::Auction => {
+                self.auction_call.get_or_insert(AuctionSession::Volatility);
+            }
+            TradingState::ContinuousTrading => {
+                if let Some(session) = self.auction_call {
+                    self.uncross_auction(session);
+                }
+            }
+            TradingState::Halted | TradingState::Closed => {}
+        }
+        self.trading_state = state;
+        if state == TradingState::ContinuousTrading {
+            self.release_held_orders();
+        }
+        self.settle();
+    }
+
+    pub fn trading_state(&self) -> TradingState {
+        self.trading_state
+    }
+
+    /// Auction currently in its call phase, if any
+    pub fn auction_call(&self) -> Option<AuctionSession> {
+        self.auction_call
+    }
+
+    /// Price, volume and imbalance the auction in its call phase would uncross at if it
+    /// ran now, as published on auction feeds. `None` outside a call or while nothing
+    /// crosses.
+    pub fn indicative_auction(&self) -> Option<AuctionResult> {
+        self.auction_equilibrium(self.auction_call?)
+    }
+
+    /// Uncross an auction at the single price that maximizes executed volume.
+    ///
+    /// For the closing auction, on-close orders are collected during continuous trading
+    /// and `start_auction` freezes the book at the close. Running it publishes the
+    /// session's `official_close_price`.
+    ///
+    /// Resting orders, orders held during the call and auction-only orders for this
+    /// auction all take part. Ties in volume go to the price leaving the smallest
+    /// imbalance, then to market pressure (the highest price if buyers are left over, the
+    /// lowest if sellers are), then to the price closest to the last trade, or before the
+    /// first trade to the static price band's reference (e.g. the previous close). The
+    /// opening auction's price is published as the session's `opening_price`. Orders trade
+    /// in price-time priority, resting orders ahead of held ones at the same price;
+    /// minimum execution quantities are not enforced.
+    ///
+    /// The auction then counts as run like after `complete_auction`: unfilled orders
+    /// targeting it are cancelled, other auction-only orders stay held, and what is left
+    /// of orders held during the call enters continuous trading as if just submitted.
+    /// Returns `None` if no orders crossed.
+    pub fn run_auction(&mut self, session: AuctionSession) -> Option<AuctionResult> {
+        self.record_event(|| BookEvent::RunAuction(session));
+
+        let result = self.uncross_auction(session);
+        self.settle();
+        result
+    }
+
+    // `run_auction` without recording an event or settling afterwards
+    fn uncross_auction(&mut self, session: AuctionSession) -> Option<AuctionResult> {
+        let result = self.auction_equilibrium(session);
+        if let Some(result) = result {
+            self.uncross(session, result.price);
+        }
+        if session == AuctionSession::Open {
+            self.session.opening_price = result.map(|result| result.price);
+        }
+        if session == AuctionSession::Close {
+            self.session.official_close_price =
+                result.map(|result| result.price).or(self.last_trade_price);
+        }
+        if session == AuctionSession::Volatility {
+            // Trading resumes from the auction price alone
+            self.volatility_auction_end = None;
+            self.recent_trade_prices.clear();
+            if let Some(result) = result {
+                self.recent_trade_prices
+                    .push_back((self.clock, result.price));
+            }
+        }
+        self.finish_auction(session);
+        result
+    }
+
+    // Whether a held order takes part in an auction
+    fn in_auction(order: &Order, session: AuctionSession) -> bool {
+        order
+            .options
+            .auction_target
+            .is_none_or(|target| target == session)
+    }
+
+    // Price, volume and imbalance an auction would uncross at right now
+    fn auction_equilibrium(&self, session: AuctionSession) -> Option<AuctionResult> {
+        let participants = self
+            .buy_price_levels
+            .values()
+            .chain(self.sell_price_levels.values())
+            .flat_map(|level| level.orders.iter())
+            .chain(
+                self.auction_orders
+                    .iter()
+                    .filter(|o| Self::in_auction(o, session)),
+            );
+
+        // (price, buy quantity, sell quantity) per limit price, ascending
+        let mut prices: Vec<(f64, f64, f64)> = Vec::new();
+        let (mut market_buys, mut market_sells) = (0.0, 0.0);
+        for order in participants {
+            let quantity = order.remaining_quantity;
+            match (order.price, order.side) {
+                (None, OrderSide::Buy) => market_buys += quantity,
+                (None, OrderSide::Sell) => market_sells += quantity,
+                (Some(price), OrderSide::Buy) => prices.push((price, quantity, 0.0)),
+                (Some(price), OrderSide::Sell) => prices.push((price, 0.0, quantity)),
+            }
+        }
+        prices.sort_by(|a, b| a.0.total_cmp(&b.0));
+        prices.dedup_by(|next, merged| {
+            if next.0 != merged.0 {
+                return false;
+            }
+            merged.1 += next.1;
+            merged.2 += next.2;
+            true
+        });
+
+        // Buyers at a price are those bidding at least that much, sellers those asking at
+        // most that much
+        let mut buys_at_or_above: f64 = market_buys + prices.iter().map(|p| p.1).sum::<f64>();
+        let mut sells_at_or_below = market_sells;
+        let mut candidates = Vec::with_capacity(prices.len());
+        for (price, buy_quantity, sell_quantity) in prices {
+            sells_at_or_below += sell_quantity;
+            candidates.push(AuctionResult {
+                price,
+                volume: buys_at_or_above.min(sells_at_or_below),
+                imbalance: buys_at_or_above - sells_at_or_below,
+            });
+            buys_at_or_above -= buy_quantity;
+        }
+
+        let max_volume = candidates.iter().map(|c| c.volume).fold(0.0, f64::max);
+        if max_volume <= 0.0 {
+            return None;
+        }
+        let min_imbalance = candidates
+            .iter()
+            .filter(|c| c.volume == max_volume)
+            .map(|c| c.imbalance.abs())
+            .fold(f64::INFINITY, f64::min);
+        let tied: Vec<AuctionResult> = candidates
+            .into_iter()
+            .filter(|c| c.volume == max_volume && c.imbalance.abs() == min_imbalance)
+            .collect();
+
+        if tied.iter().all(|c| c.imbalance > 0.0) {
+            return tied.last().copied();
+        }
+        if tied.iter().all(|c| c.imbalance < 0.0) {
+            return tied.first().copied();
+        }
+        let reference = self.last_trade_price.or_else(|| {
+            self.price_bands
+                .and_then(|price_bands| price_bands.static_reference)
+        });
+        match reference {
+            Some(reference) => tied.into_iter().min_by(|a, b| {
+                (a.price - reference)
+                    .abs()
+                    .total_cmp(&(b.price - reference).abs())
+            }),
+            None => tied.first().copied(),
+        }
+    }
+
+    // Trade every order of an auction that crosses `price` at that price. Resting orders
+    // that are left over go back to their levels in their original queue order.
+    fn uncross(&mut self, session: AuctionSession, price: f64) {
+        let crosses = |order: &Order| match (order.price, order.side) {
+            (None, _) => true,
+            (Some(limit), OrderSide::Buy) => limit >= price,
+            (Some(limit), OrderSide::Sell) => limit <= price,
+        };
+
+        // (order, whether it came from the book), best price first
+        let mut buys = self.take_crossing_levels(true, price);
+        let mut sells = self.take_crossing_levels(false, price);
+        let (held, rest): (Vec<Order>, Vec<Order>) = std::mem::take(&mut self.auction_orders)
+            .into_iter()
+            .partition(|o| Self::in_auction(o, session) && crosses(o));
+        self.auction_orders = rest;
+        for order in held {
+            match order.side {
+                OrderSide::Buy => buys.push((order, false)),
+                OrderSide::Sell => sells.push((order, false)),
+            }
+        }
+        // Stable sorts keep resting orders ahead of held ones at the same price
+        buys.sort_by(|(a, _), (b, _)| {
+            let key = |o: &Order| o.price.unwrap_or(f64::INFINITY);
+            key(b).total_cmp(&key(a))
+        });
+        sells.sort_by(|(a, _), (b, _)| {
+            let key = |o: &Order| o.price.unwrap_or(f64::NEG_INFINITY);
+            key(a).total_cmp(&key(b))
+        });
+
+        let (mut i, mut j) = (0, 0);
+        while i < buys.len() && j < sells.len() {
+            let buy = &mut buys[i].0;
+            let sell = &mut sells[j].0;
+            let quantity = buy.remaining_quantity.min(sell.remaining_quantity);
+            // The order that arrived later counts as the taker
+            if buy.timestamp >= sell.timestamp {
+                self.execute_trade(buy, sell, price, quantity, TradeFlags::AUCTION);
+            } else {
+                self.execute_trade(sell, buy, price, quantity, TradeFlags::AUCTION);
+            }
+            if buy.remaining_quantity <= 0.0 {
+                i += 1;
+            }
+            if sell.remaining_quantity <= 0.0 {
+                j += 1;
+            }
+        }
+
+        for (order, from_book) in buys.into_iter().chain(sells) {
+            if order.status == OrderStatus::Filled {
+                self.finished_orders.record(&order);
+                self.order_links.touch(&order);
+            } else if from_book {
+                self.insert_resting_order(order);
+            } else {
+                self.auction_orders.push(order);
+            }
+        }
+    }
+
+    // Take every level on one side that crosses `price` out of the book, best first
+    fn take_crossing_levels(&mut self, is_buy: bool, price: f64) -> Vec<(Order, bool)> {
+        let price_levels = if is_buy {
+            &mut self.buy_price_levels
+        } else {
+            &mut self.sell_price_levels
+        };
+        let keys: Vec<i64> = price_levels
+            .iter()
+            .take_while(|(_, level)| {
+                if is_buy {
+                    level.price >= price
+                } else {
+                    level.price <= price
+                }
+            })
+            .map(|(&key, _)| key)
+            .collect();
+        let orders: Vec<Order> = keys
+            .into_iter()
+            .flat_map(|key| price_levels.remove(&key).unwrap().orders)
+            .collect();
+        for order in &orders {
+            self.orders_by_id.remove(&order.id);
+            self.orders_by_owner.remove(order);
+        }
+        orders.into_iter().map(|order| (order, true)).collect()
+    }
+
+    // Mark an auction as run: end its call phase, cancel the orders still targeting it
+    // and release orders held during the call into continuous trading. Returns the
+    // cancelled ids.
+    fn finish_auction(&mut self, session: AuctionSession) -> Vec<u64> {
+        if session != AuctionSession::Volatility {
+            self.completed_auctions.insert(session);
+        }
+        if self.auction_call == Some(session) {
+            self.auction_call = None;
+            self.trading_state = match session {
+                AuctionSession::Close => TradingState::Closed,
+                _ => TradingState::ContinuousTrading,
+            };
+        }
+        let (expired, held): (Vec<Order>, Vec<Order>) = std::mem::take(&mut self.auction_orders)
+            .into_iter()
+            .partition(|o| o.options.auction_target == Some(session));
+        self.auction_orders = held;
+
+        let mut expired_ids = Vec::with_capacity(expired.len());
+        for mut order in expired {
+            order.status = OrderStatus::Cancelled;
+            self.finished_orders.record(&order);
+            self.audit.record_exit(&order, self.clock);
+            self.order_links.touch(&order);
+            expired_ids.push(order.id);
+        }
+        self.release_held_orders();
+        expired_ids
+    }
+
+    // Execute the orders held while matching was suspended, other than auction-only
+    // ones, in arrival order. They are held again if matching is still suspended.
+    fn release_held_orders(&mut self) {
+        let (held, released): (Vec<Order>, Vec<Order>) = std::mem::take(&mut self.auction_orders)
+            .into_iter()
+            .partition(|o| o.options.auction_only || o.options.auction_target.is_some());
+        self.auction_orders = held;
+        for order in released {
+            self.execute_order(order);
+        }
+    }
+
+    /// Cancel every resting GTD order whose `expire_at <= now` and return their ids.
+    ///
+    /// Partially filled orders keep their fills; only the unfilled remainder expires and
+    /// the order ends `Cancelled`.
+    pub fn expire_orders(&mut self, now: u64) -> Vec<u64> {
+        self.record_event(|| BookEvent::ExpireOrders { now });
+
+        self.clock = self.clock.max(now);
+        let mut expired = Vec::new();
+        for is_buy in [true, false] {
+            self.cancel_where(
+                is_buy,
+                (Bound::Unbounded, Bound::Unbounded),
+                &mut expired,
+                |order| matches!(order.options.time_in_force, TimeInForce::Gtd { expire_at } if expire_at <= now),
+            );
+        }
+        for &order_id in &expired {
+            self.audit.mark_expired(order_id);
+        }
+        self.settle();
+        expired
+    }
+
+    /// Move the book's clock to `now`: cancel every GTD order, resting or waiting to enter
+    /// the book, whose `expire_at <= now`, and every DAY order once `now` reaches the day
+    /// close time. GTD orders come off a schedule ordered by expiry, so only orders that
+    /// actually expire are visited. Returns the cancelled orders in expiry order.
+    pub fn advance_time(&mut self, now: u64) -> Vec<OrderView> {
+        self.record_event(|| BookEvent::AdvanceTime { now });
+
+        self.clock = self.clock.max(now);
+        let mut expired = Vec::new();
+        while let Some(&Reverse((expire_at, order_id))) = self.expiry_schedule.peek() {
+            if expire_at > now {
+                break;
+            }
+            self.expiry_schedule.pop();
+            self.expire_order(order_id, &mut expired);
+        }
+
+        if self.trading_state == TradingState::Auction
+            && self.volatility_auction_end.is_some_and(|end| end <= now)
+        {
+            self.uncross_auction(AuctionSession::Volatility);
+        }
+
+        if self.day_close.is_some_and(|day_close| day_close <= now) {
+            self.day_close = None;
+            let day_orders: Vec<u64> = self
+                .buy_price_levels
+                .values()
+                .chain(self.sell_price_levels.values())
+                .flat_map(|level| level.orders.iter())
+                .chain(self.triggers.orders())
+                .chain(&self.auction_orders)
+                .filter(|o| o.options.time_in_force == TimeInForce::Day)
+                .map(|o| o.id)
+                .collect();
+            for order_id in day_orders {
+                self.expire_order(order_id, &mut expired);
+            }
+        }
+        self.settle();
+        expired
+    }
+
+    // Cancel a live order and append its final state to `expired`
+    fn expire_order(&mut self, order_id: u64, expired: &mut Vec<OrderView>) {
+        let Some(mut view) = self.get_order(order_id) else {
+            return;
+        };
+        if self.remove_and_cancel(order_id) {
+            self.audit.mark_expired(order_id);
+            view.status = OrderStatus::Cancelled;
+            expired.push(view);
+        }
+    }
+
+    /// Time at which `advance_time` cancels all DAY orders, once. `None` leaves DAY orders
+    /// to `end_session`.
+    pub fn set_day_close(&mut self, day_close: Option<u64>) {
+        self.record_event(|| BookEvent::SetDayClose(day_close));
+        self.day_close = day_close;
+    }
+
+    pub fn day_close(&self) -> Option<u64> {
+        self.day_close
+    }
+
+    // Cancel the resting orders on one side within `key_range` that satisfy `predicate`,
+    // appending their ids to `cancelled` in priority order and dropping emptied levels
+    fn cancel_where<F>(
+        &mut self,
+        is_buy: bool,
+        key_range: (Bound<i64>, Bound<i64>),
+        cancelled: &mut Vec<u64>,
+        mut predicate: F,
+    ) where
+        F: FnMut(&Order) -> bool,
+    {
+        let price_levels = if is_buy {
+            &mut self.buy_price_levels
+        } else {
+            &mut self.sell_price_levels
+        };
+
+        let mut emptied_levels = Vec::new();
+        for (&price_key, level) in price_levels.range_mut(key_range) {
+            level.orders.retain_mut(|order| {
+                let matches = predicate(order);
+                if matches {
+                    order.status = OrderStatus::Cancelled;
+                    self.orders_by_id.remove(&order.id);
+                    self.orders_by_owner.remove(order);
+                    self.finished_orders.record(order);
+                    self.audit.record_exit(order, self.clock);
+                    self.order_links.touch(order);
+                    cancelled.push(order.id);
+                }
+                !matches
+            });
+            level.is_dirty = true;
+
+            if level.is_empty() {
+                emptied_levels.push(price_key);
+            }
+        }
+
+        for price_key in emptied_levels {
+            price_levels.remove(&price_key);
+        }
+    }
+
+    // Price level keys covering an inclusive price range on one side, or all of them
+    fn key_range(is_buy: bool, price_range: Option<(f64, f64)>) -> (Bound<i64>, Bound<i64>) {
+        match price_range {
+            Some((low, high)) => {
+                let low_key = Self::price_to_key(low.min(high), is_buy);
+                let high_key = Self::price_to_key(low.max(high), is_buy);
+                (
+                    Bound::Included(low_key.min(high_key)),
+                    Bound::Included(low_key.max(high_key)),
+                )
+            }
+            None => (Bound::Unbounded, Bound::Unbounded),
+        }
+    }
+
+    /// Resting orders matching all of the given filters, hidden ones included, bids then
+    /// asks in price-time priority. `None` filters match everything and `price_range` is
+    /// inclusive on both ends, as for `cancel_all`.
+    pub fn open_orders(
+        &self,
+        side: Option<OrderSide>,
+        price_range: Option<(f64, f64)>,
+        owner_id: Option<u64>,
+    ) -> Vec<OrderView> {
+        let mut views = Vec::new();
+        for level_side in [OrderSide::Buy, OrderSide::Sell] {
+            if side.is_some_and(|s| s != level_side) {
+                continue;
+            }
+
+            let is_buy = level_side == OrderSide::Buy;
+            let price_levels = if is_buy {
+                &self.buy_price_levels
+            } else {
+                &self.sell_price_levels
+            };
+            views.extend(
+                price_levels
+                    .range(Self::key_range(is_buy, price_range))
+                    .flat_map(|(_, level)| level.orders.iter())
+                    .filter(|order| {
+                        owner_id.is_none_or(|owner| order.options.owner_id == Some(owner))
+                    })
+                    .map(OrderView::from),
+            );
+        }
+        views
+    }
+
+    /// Market-by-order snapshot: every resting order individually, bids then asks, each
+    /// side in price-time priority (best price first, then queue order within a level)
+    pub fn l3_snapshot(&self) -> (Vec<OrderView>, Vec<OrderView>) {
+        let collect_views = |price_levels: &BTreeMap<i64, PriceLevel>| -> Vec<OrderView> {
+            price_levels
+                .values()
+                .flat_map(|level| level.orders.iter())
+                .filter(|order| !order.options.hidden)
+                .map(OrderView::from)
+                .collect()
+        };
+
+        (
+            collect_views(&self.buy_price_levels),
+            collect_views(&self.sell_price_levels),
+        )
+    }
+
+    /// Order book imbalance `(bid_qty - ask_qty) / (bid_qty + ask_qty)` over the top `levels`
+    /// price levels of each side, or `None` if either side is empty. Returns 0.0 if the
+    /// summed quantity is zero.
+    pub fn imbalance(&mut self, levels: usize) -> Option<f64> {
+        if self.buy_price_levels.is_empty() || self.sell_price_levels.is_empty() {
+            return None;
+        }
+
+        let bid_qty: f64 = self
+            .buy_price_levels
+            .values_mut()
+            .map(|level| level.total_quantity())
+            .filter(|&quantity| quantity > 0.0)
+            .take(levels)
+            .sum();
+        let ask_qty: f64 = self
+            .sell_price_levels
+            .values_mut()
+            .map(|level| level.total_quantity())
+            .filter(|&quantity| quantity > 0.0)
+            .take(levels)
+            .sum();
+
+        let total_qty = bid_qty + ask_qty;
+        if total_qty <= 0.0 {
+            return Some(0.0);
+        }
+        Some((bid_qty - ask_qty) / total_qty)
+    }
+
+    /// Size-weighted microprice of the best bid and ask, or `None` if either side is empty.
+    /// Falls back to the plain midpoint if both top levels have zero quantity.
+    pub fn microprice(&self) -> Option<f64> {
+        // Levels holding only hidden orders are not part of the displayed book
+        let best_bid = self
+            .buy_price_levels
+            .values()
+            .find(|level| level.has_displayed_orders())?;
+        let best_ask = self
+            .sell_price_levels
+            .values()
+            .find(|level| level.has_displayed_orders())?;
+        let bid_qty = best_bid.current_quantity();
+        let ask_qty = best_ask.current_quantity();
+
+        let total_qty = bid_qty + ask_qty;
+        if total_qty <= 0.0 {
+            return Some((best_bid.price + best_ask.price) / 2.0);
+        }
+        Some((best_bid.price * ask_qty + best_ask.price * bid_qty) / total_qty)
+    }
+
+    pub fn get_order_book_snapshot(&mut self) -> DepthSnapshot {
+        // Get buy side: price level and total quantity
+        let mut buy_snapshot = Vec::with_capacity(self.buy_price_levels.len());
+        for (&price_key, level) in &mut self.buy_price_levels {
+            // Use mutable ref to update cache
+            let price = Self::key_to_price(price_key, true);
+            let quantity = level.total_quantity(); // Use cached quantity
+            if quantity > 0.0 {
+                buy_snapshot.push((price, quantity)); // Levels of only hidden orders are skipped
+            }
+        }
+
+        // Get sell side: price level and total quantity
+        let mut sell_snapshot = Vec::with_capacity(self.sell_price_levels.len());
+        for (&price_key, level) in &mut self.sell_price_levels {
+            // Use mutable ref to update cache
+            let price = Self::key_to_price(price_key, false);
+            let quantity = level.total_quantity(); // Use cached quantity
+            if quantity > 0.0 {
+                sell_snapshot.push((price, quantity)); // Levels of only hidden orders are skipped
+            }
+        }
+
+        // Sort by price (unnecessary but consistent with original)
+        buy_snapshot.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+        sell_snapshot.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+        (buy_snapshot, sell_snapshot)
+    }
+
+    /// Depth with both the displayed and the total quantity of every level, best price
+    /// first. Unlike `get_order_book_snapshot`, levels holding only hidden orders are
+    /// included with a displayed quantity of zero.
+    pub fn get_full_order_book_snapshot(&mut self) -> FullDepthSnapshot {
+        let levels = |price_levels: &mut BTreeMap<i64, PriceLevel>| -> Vec<(f64, f64, f64)> {
+            price_levels
+                .values_mut()
+                .map(|level| (level.price, level.total_quantity(), level.full_quantity()))
+                .collect()
+        };
+        (
+            levels(&mut self.buy_price_levels),
+            levels(&mut self.sell_price_levels),
+        )
+    }
+
+    fn get_trades(&self, limit: Option<usize>) -> PyResult<Vec<PyTrade>> {
+        let trades = if let Some(l) = limit {
+            // Take the last 'l' trades
+            let start_index = self.trades.len().saturating_sub(l);
+            &self.trades[start_index..]
+        } else {
+            // Return all trades (slice of the whole vector)
+            &self.trades[..]
+        };
+
+        let py_trades = trades
+            .iter() // Iterate over the slice, not cloning the Vec
+            .map(PyTrade::from)
+            .collect();
+
+        Ok(py_trades)
+    }
+
+    /// Trades matching `symbol` (if given) with timestamps in `[start_ts, end_ts]`.
+    ///
+    /// While trades have been appended in non-decreasing timestamp order the time bounds
+    /// are located by binary search and only the slice in between is scanned. Once an
+    /// out-of-order submission breaks that ordering the whole log is scanned instead.
+    pub fn trades_filtered(
+        &self,
+        symbol: Option<&str>,
+        start_ts: Option<u64>,
+        end_ts: Option<u64>,
+    ) -> Vec<&Trade> {
+        let matches_symbol = |t: &&Trade| symbol.is_none_or(|s| t.symbol.as_deref() == Some(s));
+        if !self.trades_in_time_order {
+            return self
+                .trades
+                .iter()
+                .filter(|t| start_ts.is_none_or(|ts| t.timestamp >= ts))
+                .filter(|t| end_ts.is_none_or(|ts| t.timestamp <= ts))
+                .filter(matches_symbol)
+                .collect();
+        }
+
+        let start = start_ts.map_or(0, |ts| self.trades.partition_point(|t| t.timestamp < ts));
+        let end = end_ts.map_or(self.trades.len(), |ts| {
+            self.trades.partition_point(|t| t.timestamp <= ts)
+        });
+        if start >= end {
+            return Vec::new();
+        }
+
+        self.trades[start..end]
+            .iter()
+            .filter(matches_symbol)
+            .collect()
+    }
+
+    /// Executions of one order, oldest first, as recorded in the trade log
+    pub fn fills(&self, order_id: u64) -> Vec<&Trade> {
+        self.fills_by_order
+            .get(&order_id)
+            .map_or_else(Vec::new, |indices| {
+                indices.iter().map(|&i| &self.trades[i]).collect()
+            })
+    }
+
+    /// Bust (cancel) an executed trade, e.g. to apply an error-trade policy. The trade
+    /// stays in the log marked `busted`, its effect on positions, accrued fees and the book
+    /// and session statistics is reversed, the last trade price and the session's prices
+    /// fall back to the trades still standing and both orders' audit trails note the bust.
+    ///
+    /// With `restore_orders` each order that is still resting gets the busted quantity
+    /// back in place, keeping its queue priority. Orders that have left the book stay
+    /// finished. Returns `false` if the trade is unknown or already busted, in which case
+    /// no correction event is recorded.
+    pub fn bust_trade(&mut self, trade_id: u64, restore_orders: bool) -> bool {
+        // Ids repeat after a clear that keeps trades, so prefer the latest trade
+        let Some(index) = self
+            .trades
+            .iter()
+            .rposition(|t| t.id == trade_id && !t.busted)
+        else {
+            return false;
+        };
+        self.record_event(|| BookEvent::BustTrade {
+            trade_id,
+            restore_orders,
+        });
+        let trade = &mut self.trades[index];
+        trade.busted = true;
+        trade.flags |= TradeFlags::BUSTED;
+        let trade = trade.clone();
+
+        if index >= self.stats_first_trade {
+            self.reverse_trade_statistics(&trade);
+        }
+        if index >= self.session_first_trade {
+            self.session
+                .bust_trade(trade.quantity, &self.trades[self.session_first_trade..]);
+        }
+        if let Some(pos) = self
+            .recent_trade_prices
+            .iter()
+            .rposition(|&entry| entry == (trade.timestamp, trade.price))
+        {
+            self.recent_trade_prices.remove(pos);
+        }
+        let mut prices = self
+            .trades
+            .iter()
+            .rev()
+            .filter(|t| !t.busted)
+            .map(|t| t.price);
+        self.last_trade_price = prices.next();
+        self.last_different_price = self
+            .last_trade_price
+            .and_then(|last| prices.find(|&price| price_units(price) != price_units(last)));
+
+        for order_id in [trade.buy_order_id, trade.sell_order_id] {
+            if !(restore_orders && self.restore_fill(order_id, trade.quantity)) {
+                self.audit.record_bust(order_id, self.clock);
+            }
+        }
+        true
+    }
+
+    // Undo a busted trade's effect on positions, accrued fees and the book's statistics
+    fn reverse_trade_statistics(&mut self, trade: &Trade) {
+        if let Some(owner) = trade.buyer_owner_id {
+            *self.positions.entry(owner).or_default() -= trade.quantity;
+        }
+        if let Some(owner) = trade.seller_owner_id {
+            *self.positions.entry(owner).or_default() += trade.quantity;
+        }
+        self.accrued_maker_fees -= trade.maker_fee;
+        self.accrued_taker_fees -= trade.taker_fee;
+        self.stats.trades_executed -= 1;
+        self.stats.total_trade_volume -= trade.quantity;
+        self.stats.total_notional -= trade.notional;
+        if trade.wash_trade {
+            self.stats.wash_trades -= 1;
+            self.stats.wash_trade_volume -= trade.quantity;
+        }
+        if let Some(improvement) = trade
+            .price_improvement()
+            .filter(|&improvement| improvement > 0.0)
+        {
+            match trade.aggressor_side {
+                OrderSide::Buy => {
+                    self.stats.buy_price_improved_trades -= 1;
+                    self.stats.buy_price_improvement -= improvement * trade.quantity;
+                }
+                OrderSide::Sell => {
+                    self.stats.sell_price_improved_trades -= 1;
+                    self.stats.sell_price_improvement -= improvement * trade.quantity;
+                }
+            }
+        }
+    }
+
+    // Give a resting order back the quantity of a busted execution
+    fn restore_fill(&mut self, order_id: u64, quantity: f64) -> bool {
+        let Some(&(side, price_key)) = self.orders_by_id.get(&order_id) else {
+            return false;
+        };
+        let price_levels = match side {
+            OrderSide::Buy => &mut self.buy_price_levels,
+            OrderSide::Sell => &mut self.sell_price_levels,
+        };
+        let level = price_levels.get_mut(&price_key).unwrap();
+        let order = level.orders.iter_mut().find(|o| o.id == order_id).unwrap();
+        order.filled_quantity =
+            add_quantities(order.filled_quantity, -quantity, self.quantity_decimals);
+        order.remaining_quantity =
+            add_quantities(order.remaining_quantity, quantity, self.quantity_decimals);
+        order.status = if order.filled_quantity > 0.0 {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::New
+        };
+        self.audit
+            .record(order, AuditAction::TradeBusted, self.clock);
+        level.is_dirty = true;
+        true
+    }
+
+    /// Check the book's invariants: outside auctions and halts the best bid is below the
+    /// best ask (among orders that may trade unconditionally), no price level is empty,
+    /// the order id index matches the price levels, every resting order's filled and
+    /// remaining quantity add up to its quantity, and no order has traded more than it
+    /// has filled. Returns the first violation found.
+    ///
+    /// Building with the `integrity-checks` feature runs this after every operation that
+    /// settles the book and panics on a violation.
+    pub fn verify_integrity(&self) -> Result<(), IntegrityError> {
+        let mut seen = BTreeSet::new();
+        for (side, price_levels) in [
+            (OrderSide::Buy, &self.buy_price_levels),
+            (OrderSide::Sell, &self.sell_price_levels),
+        ] {
+            for (&price_key, level) in price_levels {
+                if level.orders.is_empty() {
+                    return Err(IntegrityError::EmptyPriceLevel {
+                        side,
+                        price: level.price,
+                    });
+                }
+                for order in &level.orders {
+                    let indexed = self.orders_by_id.get(&order.id) == Some(&(side, price_key));
+                    if !indexed || order.side != side || !seen.insert(order.id) {
+                        return Err(IntegrityError::IndexMismatch { order_id: order.id });
+                    }
+                    let tolerance = 1e-9 * order.quantity.max(1.0);
+                    let total = order.filled_quantity + order.remaining_quantity;
+                    if (total - order.quantity).abs() > tolerance || order.remaining_quantity <= 0.0
+                    {
+                        return Err(IntegrityError::QuantityNotConserved {
+                            order_id: order.id,
+                            quantity: order.quantity,
+                            filled_quantity: order.filled_quantity,
+                            remaining_quantity: order.remaining_quantity,
+                        });
+                    }
+                    let traded_quantity: f64 = self
+                        .fills(order.id)
+                        .iter()
+                        .filter(|trade| !trade.busted)
+                        .map(|trade| trade.quantity)
+                        .sum();
+                    if traded_quantity > order.filled_quantity + tolerance {
+                        return Err(IntegrityError::OverFilled {
+                            order_id: order.id,
+                            filled_quantity: order.filled_quantity,
+                            traded_quantity,
+                        });
+                    }
+                }
+            }
+        }
+        if let Some(&order_id) = self
+            .orders_by_id
+            .keys()
+            .find(|order_id| !seen.contains(order_id))
+        {
+            return Err(IntegrityError::IndexMismatch { order_id });
+        }
+
+        // Orders that only trade under conditions, like a minimum execution quantity or
+        // the short sale rule, can rest against the other side without trading
+        let short_sales_restricted = self.short_sale_rule != ShortSaleRule::Off;
+        let unconditional = |level: &&PriceLevel| {
+            level.orders.iter().any(|o| {
+                o.min_execution_quantity().is_none()
+                    && !o.options.reduce_only
+                    && !(short_sales_restricted && o.is_short_sale())
+            })
+        };
+        let best_bid = self.buy_price_levels.values().find(unconditional);
+        let best_ask = self.sell_price_levels.values().find(unconditional);
+        if let (Some(bid), Some(ask)) = (best_bid, best_ask) {
+            if self.trading_state == TradingState::ContinuousTrading && bid.price >= ask.price {
+                return Err(IntegrityError::CrossedBook {
+                    best_bid: bid.price,
+                    best_ask: ask.price,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_statistics(&self) -> OrderBookStats {
+        // Depth counts come straight from the book's collections, which track their
+        // lengths as orders and levels are added and removed
+        OrderBookStats {
+            resting_order_count: self.orders_by_id.len(),
+            bid_levels: self.buy_price_levels.len(),
+            ask_levels: self.sell_price_levels.len(),
+            last_trade_price: self.last_trade_price,
+            open_price: self.session.open_price,
+            high_price: self.session.high_price,
+            low_price: self.session.low_price,
+            close_price: self.session.close_price,
+            session_volume: self.session.total_trade_volume,
+            opening_price: self.session.opening_price,
+            ..self.stats.clone()
+        }
+    }
+
+    /// Remove all orders, trades and statistics while keeping allocated capacity and
+    /// configuration (matching policy, fees). Order and trade ids keep counting up.
+    pub fn clear(&mut self) {
+        self.record_event(|| BookEvent::Clear);
+        self.clear_state(ClearOptions::default());
+    }
+
+    /// Like `clear`, but also restarts order and trade ids at 1 for deterministic replay
+    pub fn reset_ids(&mut self) {
+        self.record_event(|| BookEvent::ResetIds);
+        self.clear_state(ClearOptions {
+            reset_ids: true,
+            ..ClearOptions::default()
+        });
+    }
+
+    /// Remove all orders, choosing whether ids restart and whether trades and statistics
+    /// survive, so one book can be reused across simulation scenarios
+    pub fn clear_with(&mut self, options: ClearOptions) {
+        self.record_event(|| BookEvent::ClearWith(options));
+        self.clear_state(options);
+    }
+
+    fn clear_state(&mut self, options: ClearOptions) {
+        self.buy_price_levels.clear();
+        self.sell_price_levels.clear();
+        self.orders_by_id.clear();
+        self.orders_by_owner.clear();
+        self.triggers.clear();
+        self.auction_orders.clear();
+        self.completed_auctions.clear();
+        self.trading_state = TradingState::default();
+        self.auction_call = None;
+        self.volatility_auction_end = None;
+        self.pegged_orders.clear();
+        self.max_discretion = 0.0;
+        self.peg_reference = None;
+        self.order_links = OrderLinks::default();
+        self.finished_orders.clear();
+        self.quotes.clear();
+        self.orders_by_client_id.clear();
+        self.expiry_schedule.clear();
+        self.audit.entries.clear();
+        // Fills are indexed by order id, so kept trades must not be credited to the new
+        // orders that reuse their ids
+        if options.reset_ids || !options.keep_trades {
+            self.fills_by_order.clear();
+        }
+        if options.reset_ids {
+            self.next_order_id = 1;
+            self.next_trade_id = 1;
+        }
+        if !options.keep_trades {
+            self.trades.clear();
+            self.trades_in_time_order = true;
+            self.stats_first_trade = 0;
+            self.session_first_trade = 0;
+            self.last_trade_price = None;
+            self.last_different_price = None;
+            self.recent_trade_prices.clear();
+        }
+        if !options.keep_statistics {
+            self.accrued_maker_fees = 0.0;
+            self.accrued_taker_fees = 0.0;
+            self.positions.clear();
+            self.stats = OrderBookStats::default();
+            self.session = SessionStats::default();
+            self.session_history.clear();
+            self.stats_first_trade = self.trades.len();
+            self.session_first_trade = self.trades.len();
+        }
+    }
+
+    /// Start appending every state-changing operation to the event log
+    pub fn enable_recording(&mut self) {
+        self.recording = true;
+    }
+
+    pub fn disable_recording(&mut self) {
+        self.recording = false;
+    }
+
+    pub fn recorded_events(&self) -> &[BookEvent] {
+        &self.events
+    }
+
+    /// Start recording every order's lifecycle transitions for `audit_trail`
+    pub fn enable_audit_trail(&mut self) {
+        self.audit.enabled = true;
+    }
+
+    pub fn disable_audit_trail(&mut self) {
+        self.audit.enabled = false;
+    }
+
+    /// Lifecycle transitions of an order in the order they happened, recorded while the
+    /// audit trail was enabled. Timestamps of fills are the trade's; other transitions
+    /// carry the latest time the book has seen.
+    pub fn audit_trail(&self, order_id: u64) -> &[AuditEntry] {
+        self.audit.get(order_id)
+    }
+
+    fn record_event<F: FnOnce() -> BookEvent>(&mut self, event: F) {
+        if self.recording {
+            self.events.push(event());
+        }
+    }
+
+    /// Rebuild a book by applying `events` in order to a fresh `OrderBook`
+    pub fn replay(events: &[BookEvent]) -> OrderBook {
+        let mut book = OrderBook::new();
+        for event in events {
+            book.apply_event(event.clone());
+        }
+        book
+    }
+
+    fn apply_event(&mut self, event: BookEvent) {
+        match event {
+            BookEvent::AddLimit {
+                side,
+                price,
+                quantity,
+                timestamp,
+                symbol,
+                options,
+            } => {
+                // Rejections replay identically, so the result can be ignored
+                let _ = self.add_order_with_options(
+                    side,
+                    OrderType::Limit,
+                    Some(price),
+                    quantity,
+                    timestamp,
+                    symbol,
+                    options,
+                );
+            }
+            BookEvent::AddMarket {
+                side,
+                quantity,
+                timestamp,
+                symbol,
+                options,
+            } => {
+                let _ = self.add_order_with_options(
+                    side,
+                    OrderType::Market,
+                    None,
+                    quantity,
+                    timestamp,
+                    symbol,
+                    options,
+                );
+            }
+            BookEvent::AddMarketByNotional {
+                side,
+                notional,
+                timestamp,
+                symbol,
+            } => {
+                let _ = self.add_market_order_by_notional(side, notional, timestamp, symbol);
+            }
+            BookEvent::BatchAdd { orders, ordered } => {
+                let _ = if ordered {
+                    self.batch_add_orders_ordered(orders)
+                } else {
+                    self.batch_add_orders(orders)
+                };
+            }
+            BookEvent::Cancel { order_id } => {
+                self.cancel_order(order_id);
+            }
+            BookEvent::Modify {
+                order_id,
+                new_price,
+                new_quantity,
+            } => {
+                let _ = self.modify_order(order_id, new_price, new_quantity);
+            }
+            BookEvent::CancelReplace {
+                order_id,
+                new_price,
+                new_quantity,
+                timestamp,
+            } => {
+                let _ = self.cancel_replace(order_id, new_price, new_quantity, timestamp);
+            }
+            BookEvent::ModifyBatch(amendments) => {
+                self.modify_orders(&amendments);
+            }
+            BookEvent::CancelParticipant { owner_id } => {
+                self.cancel_participant_orders(owner_id);
+            }
+            BookEvent::CancelSymbol { symbol, owner_id } => {
+                self.cancel_symbol_orders(&symbol, owner_id);
+            }
+            BookEvent::BustTrade {
+                trade_id,
+                restore_orders,
+            } => {
+                self.bust_trade(trade_id, restore_orders);
+            }
+            BookEvent::CancelAll {
+                side,
+                price_range,
+                owner_id,
+            } => {
+                self.cancel_all(side, price_range, owner_id);
+            }
+            BookEvent::ExpireOrders { now } => {
+                self.expire_orders(now);
+            }
+            BookEvent::AdvanceTime { now } => {
+                self.advance_time(now);
+            }
+            BookEvent::SetDayClose(day_close) => self.set_day_close(day_close),
+            BookEvent::Clear => self.clear(),
+            BookEvent::ResetIds => self.reset_ids(),
+            BookEvent::ClearWith(options) => self.clear_with(options),
+            BookEvent::EndSession => {
+                self.end_session();
+            }
+            BookEvent::OpenSession {
+                heartbeat_timeout,
+                timestamp,
+            } => {
+                self.open_session(heartbeat_timeout, timestamp);
+            }
+            BookEvent::Heartbeat {
+                session_id,
+                timestamp,
+            } => {
+                self.heartbeat(session_id, timestamp);
+            }
+            BookEvent::CloseSession { session_id } => {
+                self.close_session(session_id);
+            }
+            BookEvent::ExpireSessions { now } => {
+                self.expire_sessions(now);
+            }
+            BookEvent::SetMatchingPolicy(policy) => self.set_matching_policy(policy),
+            BookEvent::SetHiddenPriority(priority) => self.set_hidden_priority(priority),
+            BookEvent::SetIcebergRefill(refill) => self.set_iceberg_refill(refill),
+            BookEvent::SetPriorityShare(share) => self.set_priority_share(share),
+            BookEvent::SetLeadMarketMaker(lmm) => self.set_lead_market_maker(lmm),
+            BookEvent::SetQuantityRules(rules) => self.set_quantity_rules(rules),
+            BookEvent::SetQuantityDecimals(decimals) => self.set_quantity_decimals(decimals),
+            BookEvent::SetFeeSchedule(fee_schedule) => self.set_fee_schedule(fee_schedule),
+            BookEvent::SetInstrument(instrument) => self.set_instrument(instrument),
+            BookEvent::SetAccountGroup { owner_id, group } => {
+                self.set_account_group(owner_id, group)
+            }
+            BookEvent::SetTickSize(tick_size) => self.set_tick_size(tick_size),
+            BookEvent::SetTickTable(tick_table, off_tick_price) => {
+                self.set_tick_table(tick_table, off_tick_price)
+            }
+            BookEvent::SetIntegerTicks(enabled) => self.set_integer_ticks(enabled),
+            BookEvent::SetFinishedOrderRetention(retention) => {
+                self.set_finished_order_retention(retention)
+            }
+            BookEvent::CompleteAuction(session) => {
+                self.complete_auction(session);
+            }
+            BookEvent::StartAuction(session) => {
+                self.start_auction(session);
+            }
+            BookEvent::SetTradingState(state) => self.set_trading_state(state),
+            BookEvent::RunAuction(session) => {
+                self.run_auction(session);
+            }
+            BookEvent::SetMarketProtection(band) => self.set_market_protection(band),
+            BookEvent::SetPriceCollar(collar) => self.set_price_collar(collar),
+            BookEvent::SetPriceBands(bands) => self.set_price_bands(bands),
+            BookEvent::SetShortSaleRule(rule) => self.set_short_sale_rule(rule),
+            BookEvent::SetVolatilityInterruption(interruption) => {
+                self.set_volatility_interruption(interruption)
+            }
+            BookEvent::AddOco { first, second } => {
+                let _ = self.add_oco_orders(*first, *second);
+            }
+            BookEvent::Quote {
+                participant,
+                quote,
+                timestamp,
+                symbol,
+            } => {
+                let _ = self.quote(
+                    participant,
+                    quote.bid_price,
+                    quote.bid_quantity,
+                    quote.ask_price,
+                    quote.ask_quantity,
+                    timestamp,
+                    symbol,
+                );
+            }
+            BookEvent::MassQuote {
+                participant,
+                quotes,
+                mode,
+                timestamp,
+                symbol,
+            } => {
+                self.mass_quote(participant, quotes, mode, timestamp, symbol);
+            }
+        }
+    }
+
+    pub fn to_snapshot(&self) -> BookSnapshot {
+        let collect_orders = |price_levels: &BTreeMap<i64, PriceLevel>| -> Vec<Order> {
+            price_levels
+                .values()
+                .flat_map(|level| level.orders.iter().cloned())
+                .collect()
+        };
+
+        BookSnapshot {
+            buy_orders: collect_orders(&self.buy_price_levels),
+            sell_orders: collect_orders(&self.sell_price_levels),
+            stop_orders: self.triggers.stop_orders().cloned().collect(),
+            conditional_orders: self.triggers.conditional.clone(),
+            auction_orders: self.auction_orders.clone(),
+            completed_auctions: self.completed_auctions.clone(),
+            trading_state: self.trading_state,
+            auction_call: self.auction_call,
+            last_trade_price: self.last_trade_price,
+            last_different_price: self.last_different_price,
+            order_links: self.order_links.clone(),
+            next_order_id: self.next_order_id,
+            next_trade_id: self.next_trade_id,
+            matching_policy: self.matching_policy,
+            hidden_priority: self.hidden_priority,
+            iceberg_refill: self.iceberg_refill,
+            priority_share: self.priority_share,
+            lead_market_maker: self.lead_market_maker,
+            quantity_rules: self.quantity_rules,
+            quantity_decimals: self.quantity_decimals,
+            tick_size: self.tick_size,
+            tick_table: self.tick_table.clone(),
+            off_tick_price: self.off_tick_price,
+            integer_ticks: self.integer_ticks,
+            market_protection: self.market_protection,
+            price_collar: self.price_collar,
+            price_bands: self.price_bands,
+            short_sale_rule: self.short_sale_rule,
+            volatility_interruption: self.volatility_interruption,
+            recent_trade_prices: self.recent_trade_prices.clone(),
+            volatility_auction_end: self.volatility_auction_end,
+            fee_schedule: self.fee_schedule,
+            instrument: self.instrument.clone(),
+            accrued_maker_fees: self.accrued_maker_fees,
+            accrued_taker_fees: self.accrued_taker_fees,
+            positions: self.positions.clone(),
+            account_groups: self.account_groups.clone(),
+            quotes: self.quotes.clone(),
+            stats: self.stats.clone(),
+            session: self.session.clone(),
+            session_history: self.session_history.clone(),
+            finished_order_retention: self.finished_orders.capacity(),
+            client_sessions: self.client_sessions.clone(),
+            next_client_session_id: self.next_client_session_id,
+            day_close: self.day_close,
+            clock: self.clock,
+            audit_enabled: self.audit.enabled,
+            audit_trail: self.audit.entries.clone(),
+            next_audit_sequence: self.audit.next_sequence,
+        }
+    }
+
+    pub fn from_snapshot(snapshot: BookSnapshot) -> Self {
+        let mut book = OrderBook::new();
+        for order in snapshot.buy_orders.into_iter().chain(snapshot.sell_orders) {
+            book.insert_resting_order(order);
+        }
+        for order in snapshot.stop_orders {
+            book.insert_stop_order(order);
+        }
+        book.triggers.conditional = snapshot.conditional_orders;
+        book.auction_orders = snapshot.auction_orders;
+        book.completed_auctions = snapshot.completed_auctions;
+        book.trading_state = snapshot.trading_state;
+        book.auction_call = snapshot.auction_call;
+        book.last_trade_price = snapshot.last_trade_price;
+        book.last_different_price = snapshot.last_different_price;
+        book.order_links = snapshot.order_links;
+
+        book.next_order_id = snapshot.next_order_id;
+        book.next_trade_id = snapshot.next_trade_id;
+        book.matching_policy = snapshot.matching_policy;
+        book.hidden_priority = snapshot.hidden_priority;
+        book.iceberg_refill = snapshot.iceberg_refill;
+        book.priority_share = snapshot.priority_share;
+        book.lead_market_maker = snapshot.lead_market_maker;
+        book.quantity_rules = snapshot.quantity_rules;
+        book.quantity_decimals = snapshot.quantity_decimals;
+        book.tick_size = snapshot.tick_size;
+        book.tick_table = snapshot.tick_table;
+        book.off_tick_price = snapshot.off_tick_price;
+        book.integer_ticks = snapshot.integer_ticks;
+        book.finished_orders
+            .set_capacity(snapshot.finished_order_retention);
+        book.market_protection = snapshot.market_protection;
+        book.price_collar = snapshot.price_collar;
+        book.price_bands = snapshot.price_bands;
+        book.short_sale_rule = snapshot.short_sale_rule;
+        book.volatility_interruption = snapshot.volatility_interruption;
+        book.recent_trade_prices = snapshot.recent_trade_prices;
+        book.volatility_auction_end = snapshot.volatility_auction_end;
+        book.fee_schedule = snapshot.fee_schedule;
+        book.instrument = snapshot.instrument;
+        book.accrued_maker_fees = snapshot.accrued_maker_fees;
+        book.accrued_taker_fees = snapshot.accrued_taker_fees;
+        book.positions = snapshot.positions;
+        book.account_groups = snapshot.account_groups;
+        book.quotes = snapshot.quotes;
+        book.stats = snapshot.stats;
+        book.session = snapshot.session;
+        book.session_history = snapshot.session_history;
+        book.client_sessions = snapshot.client_sessions;
+        book.next_client_session_id = snapshot.next_client_session_id;
+        book.day_close = snapshot.day_close;
+        book.clock = snapshot.clock;
+        book.audit = AuditTrail {
+            enabled: snapshot.audit_enabled,
+            entries: snapshot.audit_trail,
+            next_sequence: snapshot.next_audit_sequence,
+        };
+
+        // Client order ids and expiry times are indexed again from the live orders
+        let live_orders: Vec<Order> = book
+            .buy_price_levels
+            .values()
+            .chain(book.sell_price_levels.values())
+            .flat_map(|level| level.orders.iter())
+            .chain(book.triggers.orders())
+            .chain(&book.auction_orders)
+            .cloned()
+            .collect();
+        for order in &live_orders {
+            book.index_client_order_id(order);
+            book.schedule_expiry(order);
+        }
+        book
+    }
+
+    pub fn set_matching_policy(&mut self, matching_policy: MatchingPolicy) {
+        self.record_event(|| BookEvent::SetMatchingPolicy(matching_policy));
+        self.matching_policy = matching_policy;
+    }
+
+    pub fn matching_policy(&self) -> MatchingPolicy {
+        self.matching_policy
+    }
+
+    pub fn set_hidden_priority(&mut self, hidden_priority: HiddenPriority) {
+        self.record_event(|| BookEvent::SetHiddenPriority(hidden_priority));
+        self.hidden_priority = hidden_priority;
+    }
+
+    pub fn hidden_priority(&self) -> HiddenPriority {
+        self.hidden_priority
+    }
+
+    pub fn set_iceberg_refill(&mut self, iceberg_refill: IcebergRefill) {
+        self.record_event(|| BookEvent::SetIcebergRefill(iceberg_refill));
+        self.iceberg_refill = iceberg_refill;
+    }
+
+    pub fn iceberg_refill(&self) -> IcebergRefill {
+        self.iceberg_refill
+    }
+
+    /// Fraction (clamped to 0..=1) of each incoming order that priority orders at a price
+    /// level are filled from before the matching policy allocates the rest. 0 disables
+    /// priority allocation.
+    pub fn set_priority_share(&mut self, share: f64) {
+        self.record_event(|| BookEvent::SetPriorityShare(share));
+        self.priority_share = share.clamp(0.0, 1.0);
+    }
+
+    pub fn priority_share(&self) -> f64 {
+        self.priority_share
+    }
+
+    /// Designate a lead market maker, or remove it with `None`. Its share is served only
+    /// at the first price level an incoming order trades at, ahead of priority orders.
+    pub fn set_lead_market_maker(&mut self, lead_market_maker: Option<LeadMarketMaker>) {
+        self.record_event(|| BookEvent::SetLeadMarketMaker(lead_market_maker));
+        self.lead_market_maker = lead_market_maker.map(|lmm| LeadMarketMaker {
+            share: lmm.share.clamp(0.0, 1.0),
+            ..lmm
+        });
+    }
+
+    pub fn lead_market_maker(&self) -> Option<LeadMarketMaker> {
+        self.lead_market_maker
+    }
+
+    pub fn set_quantity_rules(&mut self, quantity_rules: QuantityRules) {
+        self.record_event(|| BookEvent::SetQuantityRules(quantity_rules));
+        self.quantity_rules = quantity_rules;
+    }
+
+    pub fn quantity_rules(&self) -> QuantityRules {
+        self.quantity_rules
+    }
+
+    /// Keep order and trade quantities to `decimals` decimal places, doing fill arithmetic
+    /// in whole units of the last place so fills leave no floating-point residue such as
+    /// a 1e-16 remainder. New orders and amendments must then be quantity multiples of
+    /// one unit. `None` uses plain floating-point quantities.
+    pub fn set_quantity_decimals(&mut self, decimals: Option<u32>) {
+        self.record_event(|| BookEvent::SetQuantityDecimals(decimals));
+        self.quantity_decimals = decimals;
+    }
+
+    pub fn quantity_decimals(&self) -> Option<u32> {
+        self.quantity_decimals
+    }
+
+    pub fn set_fee_schedule(&mut self, fee_schedule: FeeSchedule) {
+        self.record_event(|| BookEvent::SetFeeSchedule(fee_schedule));
+        self.fee_schedule = fee_schedule;
+    }
+
+    pub fn fee_schedule(&self) -> FeeSchedule {
+        self.fee_schedule
+    }
+
+    /// Describe the instrument the book trades. A new contract multiplier applies to
+    /// trades from now on.
+    pub fn set_instrument(&mut self, instrument: InstrumentMetadata) {
+        self.record_event(|| BookEvent::SetInstrument(instrument.clone()));
+        self.instrument = instrument;
+    }
+
+    pub fn instrument(&self) -> &InstrumentMetadata {
+        &self.instrument
+    }
+
+    /// Set the price increment peg offsets are measured in. Pegged orders are repriced
+    /// against the new tick right away.
+    pub fn set_tick_size(&mut self, tick_size: f64) {
+        self.record_event(|| BookEvent::SetTickSize(tick_size));
+        self.tick_size = tick_size;
+        self.peg_reference = None;
+        self.settle();
+    }
+
+    pub fn tick_size(&self) -> f64 {
+        self.tick_size
+    }
+
+    /// Restrict limit prices to the ticks of `tick_table`, handling off-tick prices of new
+    /// and amended orders as `off_tick_price` says. Resting orders are left alone. An
+    /// empty table lifts the restriction. Peg offsets and protection bands keep using
+    /// `tick_size`.
+    pub fn set_tick_table(&mut self, tick_table: TickTable, off_tick_price: OffTickPrice) {
+        self.record_event(|| BookEvent::SetTickTable(tick_table.clone(), off_tick_price));
+        self.tick_table = tick_table;
+        self.off_tick_price = off_tick_price;
+    }
+
+    pub fn tick_table(&self) -> &TickTable {
+        &self.tick_table
+    }
+
+    pub fn off_tick_price(&self) -> OffTickPrice {
+        self.off_tick_price
+    }
+
+    /// Check and round limit prices against the tick table in whole price units instead
+    /// of floats, and decide whether an order crosses a level by comparing level keys.
+    /// Accepted prices are snapped to their canonical float, so trade prices are always
+    /// exact level prices.
+    pub fn set_integer_ticks(&mut self, enabled: bool) {
+        self.record_event(|| BookEvent::SetIntegerTicks(enabled));
+        self.integer_ticks = enabled;
+    }
+
+    pub fn integer_ticks(&self) -> bool {
+        self.integer_ticks
+    }
+
+    /// Keep the most recent `retention` finished orders available to `get_order`, or
+    /// all of them if `None`. Shrinking the retention evicts the oldest ones right away.
+    pub fn set_finished_order_retention(&mut self, retention: Option<usize>) {
+        self.record_event(|| BookEvent::SetFinishedOrderRetention(retention));
+        self.finished_orders.set_capacity(retention);
+    }
+
+    pub fn finished_order_retention(&self) -> Option<usize> {
+        self.finished_orders.capacity()
+    }
+
+    /// Stop every market order from filling further than `band` from the reference
+    /// price (the last trade price, or the best opposite price at entry before the first
+    /// trade) and cancel its remainder instead. `None` turns the protection off.
+    pub fn set_market_protection(&mut self, band: Option<ProtectionBand>) {
+        self.record_event(|| BookEvent::SetMarketProtection(band));
+        self.market_protection = band;
+    }
+
+    pub fn market_protection(&self) -> Option<ProtectionBand> {
+        self.market_protection
+    }
+
+    /// Reject limit orders priced more than `collar` through the best opposite price (a
+    /// buy above the best ask plus the collar, or a sell below the best bid minus it) with
+    /// `RejectReason::PriceCollar`. Orders are not collared while the opposite side is
+    /// empty. `None` turns the collar off.
+    pub fn set_price_collar(&mut self, collar: Option<ProtectionBand>) {
+        self.record_event(|| BookEvent::SetPriceCollar(collar));
+        self.price_collar = collar;
+    }
+
+    pub fn price_collar(&self) -> Option<ProtectionBand> {
+        self.price_collar
+    }
+
+    /// Reject limit orders priced outside the static or dynamic band with
+    /// `RejectReason::PriceBand`, and keep executions inside them as `on_breach` says.
+    /// `None` turns the bands off.
+    pub fn set_price_bands(&mut self, bands: Option<PriceBands>) {
+        self.record_event(|| BookEvent::SetPriceBands(bands));
+        self.price_bands = bands;
+    }
+
+    pub fn price_bands(&self) -> Option<PriceBands> {
+        self.price_bands
+    }
+
+    /// Restrict the prices short sales execute at. A short sale that may not trade at a
+    /// price stops matching there when incoming and is skipped when resting.
+    pub fn set_short_sale_rule(&mut self, rule: ShortSaleRule) {
+        self.record_event(|| BookEvent::SetShortSaleRule(rule));
+        self.short_sale_rule = rule;
+    }
+
+    pub fn short_sale_rule(&self) -> ShortSaleRule {
+        self.short_sale_rule
+    }
+
+    /// Price of the most recent trade that has not been busted
+    pub fn last_trade_price(&self) -> Option<f64> {
+        self.last_trade_price
+    }
+
+    /// Price the dynamic price band is centred on: the last trade price, or the midpoint
+    /// of the best displayed bid and ask before the first trade
+    pub fn reference_price(&self) -> Option<f64> {
+        self.last_trade_price.or_else(|| {
+            let best_bid = self.best_displayed_price(true)?;
+            let best_ask = self.best_displayed_price(false)?;
+            Some((best_bid + best_ask) / 2.0)
+        })
+    }
+
+    /// Halt continuous matching as soon as an order would trade further than the band
+    /// from any trade price within the window, holding the rest of it and everything
+    /// after it for a volatility auction. `advance_time` uncrosses the auction once its
+    /// duration has passed, after which trading resumes with the auction price as the
+    /// only reference. `None` turns the circuit breaker off.
+    pub fn set_volatility_interruption(&mut self, interruption: Option<VolatilityInterruption>) {
+        self.record_event(|| BookEvent::SetVolatilityInterruption(interruption));
+        self.volatility_interruption = interruption;
+    }
+
+    pub fn volatility_interruption(&self) -> Option<VolatilityInterruption> {
+        self.volatility_interruption
+    }
+
+    /// Cumulative (maker, taker) fees charged across all trades
+    pub fn accrued_fees(&self) -> (f64, f64) {
+        (self.accrued_maker_fees, self.accrued_taker_fees)
+    }
+
+    /// Net quantity an owner has bought minus sold across all trades
+    pub fn position(&self, owner_id: u64) -> f64 {
+        self.positions.get(&owner_id).copied().unwrap_or(0.0)
+    }
+
+    /// Put an owner in an account group, or take it out with `None`. Trades between
+    /// owners of the same group are flagged as wash trades, like trades of an owner with
+    /// itself.
+    pub fn set_account_group(&mut self, owner_id: u64, group: Option<u64>) {
+        self.record_event(|| BookEvent::SetAccountGroup { owner_id, group });
+        match group {
+            Some(group) => self.account_groups.insert(owner_id, group),
+            None => self.account_groups.remove(&owner_id),
+        };
+    }
+
+    pub fn account_group(&self, owner_id: u64) -> Option<u64> {
+        self.account_groups.get(&owner_id).copied()
+    }
+
+    // Whether a trade between these owners has the same beneficial owner on both sides
+    fn is_wash_trade(&self, buyer: Option<u64>, seller: Option<u64>) -> bool {
+        let (Some(buyer), Some(seller)) = (buyer, seller) else {
+            return false;
+        };
+        buyer == seller
+            || self
+                .account_group(buyer)
+                .is_some_and(|group| self.account_group(seller) == Some(group))
+    }
+
+    // How much of a reduce-only order's side the owner's position still allows
+    fn reducible_quantity(&self, order: &Order) -> f64 {
+        let position = order
+            .options
+            .owner_id
+            .map_or(0.0, |owner| self.position(owner));
+        match order.side {
+            OrderSide::Buy => (-position).max(0.0),
+            OrderSide::Sell => position.max(0.0),
+        }
+    }
+}
+
+impl Clone for OrderBook {
+    fn clone(&self) -> Self {
+        OrderBook {
+            buy_price_levels: self.buy_price_levels.clone(),
+            sell_price_levels: self.sell_price_levels.clone(),
+            orders_by_id: self.orders_by_id.clone(),
+            orders_by_owner: self.orders_by_owner.clone(),
+            triggers: self.triggers.clone(),
+            last_trade_price: self.last_trade_price,
+            last_different_price: self.last_different_price,
+            auction_orders: self.auction_orders.clone(),
+            completed_auctions: self.completed_auctions.clone(),
+            trading_state: self.trading_state,
+            auction_call: self.auction_call,
+            pegged_orders: self.pegged_orders.clone(),
+            max_discretion: self.max_discretion,
+            peg_reference: self.peg_reference,
+            order_links: self.order_links.clone(),
+            next_order_id: self.next_order_id,
+            next_trade_id: self.next_trade_id,
+            trades: self.trades.clone(),
+            trades_in_time_order: self.trades_in_time_order,
+            stats_first_trade: self.stats_first_trade,
+            session_first_trade: self.session_first_trade,
+            fills_by_order: self.fills_by_order.clone(),
+            finished_orders: self.finished_orders.clone(),
+            matching_policy: self.matching_policy,
+            hidden_priority: self.hidden_priority,
+            iceberg_refill: self.iceberg_refill,
+            priority_share: self.priority_share,
+            lead_market_maker: self.lead_market_maker,
+            quantity_rules: self.quantity_rules,
+            quantity_decimals: self.quantity_decimals,
+            tick_size: self.tick_size,
+            tick_table: self.tick_table.clone(),
+            off_tick_price: self.off_tick_price,
+            integer_ticks: self.integer_ticks,
+            market_protection: self.market_protection,
+            price_collar: self.price_collar,
+            price_bands: self.price_bands,
+            short_sale_rule: self.short_sale_rule,
+            volatility_interruption: self.volatility_interruption,
+            recent_trade_prices: self.recent_trade_prices.clone(),
+            volatility_auction_end: self.volatility_auction_end,
+            fee_schedule: self.fee_schedule,
+            instrument: self.instrument.clone(),
+            accrued_maker_fees: self.accrued_maker_fees,
+            accrued_taker_fees: self.accrued_taker_fees,
+            positions: self.positions.clone(),
+            account_groups: self.account_groups.clone(),
+            quotes: self.quotes.clone(),
+            stats: self.stats.clone(),
+            session: self.session.clone(),
+            session_history: self.session_history.clone(),
+            orders_by_client_id: self.orders_by_client_id.clone(),
+            client_sessions: self.client_sessions.clone(),
+            next_client_session_id: self.next_client_session_id,
+            expiry_schedule: self.expiry_schedule.clone(),
+            day_close: self.day_close,
+            recording: self.recording,
+            events: self.events.clone(),
+            clock: self.clock,
+            audit: self.audit.clone(),
+        }
+    }
+}
+
+impl Default for OrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Owner id that a `SpreadMarket`'s implied orders and their leg executions trade under
+pub const IMPLIED_OWNER_ID: u64 = u64::MAX;
+
+/// One of the three books of a `SpreadMarket`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SpreadLeg {
+    /// The nearer outright, bought by buying the spread
+    Front,
+    /// The later outright, sold by buying the spread
+    Back,
+    /// The calendar spread itself, priced as front minus back
+    Spread,
+}
+
+// The top level of one side of a book an implied price is derived from, and the sign
+// its price enters the implied price with
+type ImpliedSource = (SpreadLeg, OrderSide, f64);
+
+// Every implied order a spread market can derive: the book and side it rests on and the
+// two top levels behind it. Buying the spread buys the front and sells the back, so e.g.
+// a front bid and a back ask imply a spread bid, and a spread bid and a back bid imply a
+// front bid.
+const IMPLIED_ORDERS: [(SpreadLeg, OrderSide, [ImpliedSource; 2]); 6] = [
+    (
+        SpreadLeg::Spread,
+        OrderSide::Buy,
+        [
+            (SpreadLeg::Front, OrderSide::Buy, 1.0),
+            (SpreadLeg::Back, OrderSide::Sell, -1.0),
+        ],
+    ),
+    (
+        SpreadLeg::Spread,
+        OrderSide::Sell,
+        [
+            (SpreadLeg::Front, OrderSide::Sell, 1.0),
+            (SpreadLeg::Back, OrderSide::Buy, -1.0),
+        ],
+    ),
+    (
+        SpreadLeg::Front,
+        OrderSide::Buy,
+        [
+            (SpreadLeg::Spread, OrderSide::Buy, 1.0),
+            (SpreadLeg::Back, OrderSide::Buy, 1.0),
+        ],
+    ),
+    (
+        SpreadLeg::Front,
+        OrderSide::Sell,
+        [
+            (SpreadLeg::Spread, OrderSide::Sell, 1.0),
+            (SpreadLeg::Back, OrderSide::Sell, 1.0),
+        ],
+    ),
+    (
+        SpreadLeg::Back,
+        OrderSide::Buy,
+        [
+            (SpreadLeg::Front, OrderSide::Buy, 1.0),
+            (SpreadLeg::Spread, OrderSide::Sell, -1.0),
+        ],
+    ),
+    (
+        SpreadLeg::Back,
+        OrderSide::Sell,
+        [
+            (SpreadLeg::Front, OrderSide::Sell, 1.0),
+            (SpreadLeg::Spread, OrderSide::Buy, -1.0),
+        ],
+    ),
+];
+
+// An implied order resting in one book and the levels of the other two it lays off
+// against: (book, side to trade, price)
+#[derive(Debug, Clone)]
+struct ImpliedOrder {
+    leg: SpreadLeg,
+    order_id: u64,
+    legs: [(SpreadLeg, OrderSide, f64); 2],
+}
+
+/// A calendar spread (front minus back) traded in its own book next to the books of its
+/// two outrights, with implied orders in both directions: the outrights' best prices
+/// imply spread prices (implied out), and a spread price with one outright's best price
+/// implies a price in the other outright (implied in).
+///
+/// Implied orders are derived from real orders only, sized to the smaller of the two
+/// displayed top levels behind them, and rest in their book as post-only orders of
+/// `IMPLIED_OWNER_ID`. When one trades, its legs are executed straight away as IOC
+/// orders against those levels, leaving the implied owner flat. Implied orders are
+/// regenerated after every operation, so they always trail real orders in time priority.
+#[derive(Debug, Clone, Default)]
+pub struct SpreadMarket {
+    front: OrderBook,
+    back: OrderBook,
+    spread: OrderBook,
+    implied: Vec<ImpliedOrder>,
+    clock: u64,
+}
+
+impl SpreadMarket {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn book(&self, leg: SpreadLeg) -> &OrderBook {
+        match leg {
+            SpreadLeg::Front => &self.front,
+            SpreadLeg::Back => &self.back,
+            SpreadLeg::Spread => &self.spread,
+        }
+    }
+
+    fn book_mut(&mut self, leg: SpreadLeg) -> &mut OrderBook {
+        match leg {
+            SpreadLeg::Front => &mut self.front,
+            SpreadLeg::Back => &mut self.back,
+            SpreadLeg::Spread => &mut self.spread,
+        }
+    }
+
+    /// Run `f` against one of the books, then lay off whatever implied orders it traded
+    /// with and regenerate the implied orders. Use this for any operation beyond order
+    /// entry and cancellation, such as amendments or configuration.
+    pub fn with_book<R>(&mut self, leg: SpreadLeg, f: impl FnOnce(&mut OrderBook) -> R) -> R {
+        let result = f(self.book_mut(leg));
+        self.reconcile_implied();
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_order(
+        &mut self,
+        leg: SpreadLeg,
+        side: OrderSide,
+        order_type: OrderType,
+        price: Option<f64>,
+        quantity: f64,
+        timestamp: u64,
+        options: OrderOptions,
+    ) -> Result<u64, OrderError> {
+        self.clock = self.clock.max(timestamp);
+        self.with_book(leg, |book| {
+            book.add_order_with_options(side, order_type, price, quantity, timestamp, None, options)
+        })
+    }
+
+    pub fn cancel_order(&mut self, leg: SpreadLeg, order_id: u64) -> bool {
+        self.with_book(leg, |book| book.cancel_order(order_id))
+    }
+
+    /// Implied orders currently resting, as `(book, side, price, quantity)`
+    pub fn implied_orders(&self) -> Vec<(SpreadLeg, OrderSide, f64, f64)> {
+        self.implied
+            .iter()
+            .filter_map(|implied| {
+                let order = self.book(implied.leg).get_order(implied.order_id)?;
+                Some((
+                    implied.leg,
+                    order.side,
+                    order.price?,
+                    order.remaining_quantity,
+                ))
+            })
+            .collect()
+    }
+
+    // Withdraw every implied order, execute the legs of those that traded, then imply
+    // fresh orders from the real orders left. All implied orders are withdrawn before any
+    // leg executes so legs only ever trade with real orders.
+    fn reconcile_implied(&mut self) {
+        let mut fills = Vec::new();
+        for implied in std::mem::take(&mut self.implied) {
+            let book = self.book_mut(implied.leg);
+            let filled = book
+                .get_order(implied.order_id)
+                .map_or(0.0, |order| order.filled_quantity);
+            book.cancel_order(implied.order_id);
+            if filled > 0.0 {
+                fills.push((implied.legs, filled));
+            }
+        }
+        let options = OrderOptions {
+            owner_id: Some(IMPLIED_OWNER_ID),
+            time_in_force: TimeInForce::Ioc,
+            ..OrderOptions::default()
+        };
+        for (legs, quantity) in fills {
+            for (leg, side, price) in legs {
+                let clock = self.clock;
+                // A leg can only fail validation if the book's rules changed in between,
+                // in which case the implied owner keeps the position
+                let _ = self.book_mut(leg).add_order_with_options(
+                    side,
+                    OrderType::Limit,
+                    Some(price),
+                    quantity,
+                    clock,
+                    None,
+                    options.clone(),
+                );
+            }
+        }
+        self.imply_orders();
+    }
+
+    fn imply_orders(&mut self) {
+        // Best displayed (price, quantity) of each side of each book
+        let mut tops = Vec::with_capacity(6);
+        for leg in [SpreadLeg::Front, SpreadLeg::Back, SpreadLeg::Spread] {
+            let (bids, asks) = self.book_mut(leg).get_order_book_snapshot();
+            tops.push((leg, OrderSide::Buy, bids.first().copied()));
+            tops.push((leg, OrderSide::Sell, asks.first().copied()));
+        }
+        let top = |leg: SpreadLeg, side: OrderSide| {
+            tops.iter()
+                .find(|&&(l, s, _)| l == leg && s == side)
+                .and_then(|&(_, _, top)| top)
+        };
+        let options = OrderOptions {
+            owner_id: Some(IMPLIED_OWNER_ID),
+            post_only: true,
+            ..OrderOptions::default()
+        };
+        for (leg, side, sources) in IMPLIED_ORDERS {
+            let [Some(first), Some(second)] =
+                sources.map(|(source, source_side, _)| top(source, source_side))
+            else {
+                continue;
+            };
+            let price = sources[0].2 * first.0 + sources[1].2 * second.0;
+            let quantity = first.1.min(second.1);
+            // Lay off by trading against the source levels
+            let legs = [(sources[0], first.0), (sources[1], second.0)].map(
+                |((source, source_side, _), price)| {
+                    let side = match source_side {
+                        OrderSide::Buy => OrderSide::Sell,
+                        OrderSide::Sell => OrderSide::Buy,
+                    };
+                    (source, side, price)
+                },
+            );
+            let clock = self.clock;
+            let book = self.book_mut(leg);
+            let Ok(order_id) = book.add_order_with_options(
+                side,
+                OrderType::Limit,
+                Some(price),
+                quantity,
+                clock,
+                None,
+                options.clone(),
+            ) else {
+                continue;
+            };
+            // Post-only: an implied price crossing real orders is not entered
+            if book
+                .get_order(order_id)
+                .is_some_and(|order| order.status == OrderStatus::New)
+            {
+                self.implied.push(ImpliedOrder {
+                    leg,
+                    order_id,
+                    legs,
+                });
+            }
+        }
+    }
+}
+
+/// Error from an `Exchange` operation
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExchangeError {
+    UnknownSymbol {
+        symbol: String,
+    },
+    DuplicateSymbol {
+        symbol: String,
+    },
+    SymbolDelisted {
+        symbol: String,
+    },
+    /// An order sent to the exchange without a symbol
+    MissingSymbol,
+    /// The symbol's book refused the order
+    Order(OrderError),
+}
+
+impl std::fmt::Display for ExchangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExchangeError::UnknownSymbol { symbol } => write!(f, "unknown symbol {symbol}"),
+            ExchangeError::DuplicateSymbol { symbol } => {
+                write!(f, "symbol {symbol} is already listed")
+            }
+            ExchangeError::SymbolDelisted { symbol } => write!(f, "symbol {symbol} is delisted"),
+            ExchangeError::MissingSymbol => write!(f, "order has no symbol"),
+            ExchangeError::Order(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ExchangeError {}
+
+impl From<OrderError> for ExchangeError {
+    fn from(err: OrderError) -> Self {
+        ExchangeError::Order(err)
+    }
+}
+
+impl From<ExchangeError> for PyErr {
+    fn from(err: ExchangeError) -> Self {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+/// Trading parameters of one `Exchange` symbol, applied to its book when the symbol is
+/// registered or updated
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SymbolConfig {
+    /// Limit prices must be a multiple of this, and peg offsets and bands are measured
+    /// in it. 0 leaves prices unrestricted.
+    pub tick_size: f64,
+    /// Lot size and minimum and maximum order quantity
+    pub quantity_rules: QuantityRules,
+    pub price_bands: Option<PriceBands>,
+    pub matching_policy: MatchingPolicy,
+    /// `(timestamp, state)` trading state changes, made once the exchange's clock
+    /// reaches them
+    pub schedule: Vec<(u64, TradingState)>,
+    #[serde(default)]
+    pub instrument: InstrumentMetadata,
+}
+
+impl SymbolConfig {
+    fn apply(&self, book: &mut OrderBook) {
+        let off_tick_price = book.off_tick_price();
+        if self.tick_size > 0.0 {
+            book.set_tick_size(self.tick_size);
+            book.set_tick_table(TickTable::uniform(self.tick_size), off_tick_price);
+        } else {
+            book.set_tick_table(TickTable::default(), off_tick_price);
+        }
+        book.set_quantity_rules(self.quantity_rules);
+        book.set_price_bands(self.price_bands);
+        book.set_matching_policy(self.matching_policy);
+        book.set_instrument(self.instrument.clone());
+    }
+}
+
+/// Configuration of every symbol listed on an `Exchange`
+#[derive(Debug, Clone, Default)]
+pub struct SymbolRegistry {
+    symbols: BTreeMap<String, RegisteredSymbol>,
+}
+
+#[derive(Debug, Clone)]
+struct RegisteredSymbol {
+    config: SymbolConfig,
+    // Number of schedule entries already made
+    transitions_made: usize,
+    // Halted by `Exchange::suspend_symbol`, which holds off the schedule
+    suspended: bool,
+}
+
+impl SymbolRegistry {
+    pub fn config(&self, symbol: &str) -> Option<&SymbolConfig> {
+        self.symbols
+            .get(symbol)
+            .map(|registered| &registered.config)
+    }
+
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.symbols.contains_key(symbol)
+    }
+
+    pub fn is_suspended(&self, symbol: &str) -> bool {
+        self.symbols
+            .get(symbol)
+            .is_some_and(|registered| registered.suspended)
+    }
+
+    /// Registered symbols in alphabetical order
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.symbols.keys().map(String::as_str)
+    }
+}
+
+/// Order books of many symbols behind a single entry point.
+///
+/// Every symbol has its own book, which numbers its orders and trades independently:
+/// ids are only unique within a symbol, so orders are always addressed by symbol and
+/// id. Orders entered through the exchange carry their symbol, and so do their trades.
+///
+/// Each symbol is configured from its `SymbolConfig` in the registry. The exchange keeps
+/// a clock at the latest timestamp it has seen and makes a symbol's scheduled trading
+/// state changes as orders are routed to it, or for all symbols with `advance_time`.
+///
+/// Symbols can be listed, suspended and delisted at any time. Delisting drops the book
+/// and keeps only its trades.
+#[derive(Debug, Clone, Default)]
+pub struct Exchange {
+    books: BTreeMap<String, OrderBook>,
+    registry: SymbolRegistry,
+    clock: u64,
+    // Trades of delisted symbols
+    archived_trades: BTreeMap<String, Vec<Trade>>,
+    parallel_batches: bool,
+}
+
+impl Exchange {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// List a symbol with an empty book and the default configuration
+    pub fn add_symbol(&mut self, symbol: &str) -> Result<(), ExchangeError> {
+        self.register_symbol(symbol, SymbolConfig::default())
+    }
+
+    /// List a symbol with an empty book configured from `config`. Scheduled changes
+    /// that are already due are made straight away.
+    pub fn register_symbol(
+        &mut self,
+        symbol: &str,
+        mut config: SymbolConfig,
+    ) -> Result<(), ExchangeError> {
+        if self.books.contains_key(symbol) {
+            return Err(ExchangeError::DuplicateSymbol {
+                symbol: symbol.to_string(),
+            });
+        }
+        let mut book = OrderBook::new();
+        config.apply(&mut book);
+        config.schedule.sort_by_key(|&(timestamp, _)| timestamp);
+        self.books.insert(symbol.to_string(), book);
+        self.registry.symbols.insert(
+            symbol.to_string(),
+            RegisteredSymbol {
+                config,
+                transitions_made: 0,
+                suspended: false,
+            },
+        );
+        self.run_schedule(symbol);
+        Ok(())
+    }
+
+    /// Reconfigure a listed symbol's book. Resting orders are left alone, and so is the
+    /// trading state: scheduled changes of the new configuration that are already due
+    /// are skipped.
+    pub fn update_symbol(
+        &mut self,
+        symbol: &str,
+        mut config: SymbolConfig,
+    ) -> Result<(), ExchangeError> {
+        let book = self.listed_book_mut(symbol)?;
+        config.apply(book);
+        config.schedule.sort_by_key(|&(timestamp, _)| timestamp);
+        let transitions_made = config
+            .schedule
+            .partition_point(|&(timestamp, _)| timestamp <= self.clock);
+        let registered = self.registry.symbols.get_mut(symbol).unwrap();
+        registered.config = config;
+        registered.transitions_made = transitions_made;
+        Ok(())
+    }
+
+    /// Halt a symbol's matching until `resume_symbol`, optionally cancelling its resting
+    /// orders. Scheduled changes wait until the symbol resumes. Returns the cancelled ids.
+    pub fn suspend_symbol(
+        &mut self,
+        symbol: &str,
+        cancel_resting: bool,
+    ) -> Result<Vec<u64>, ExchangeError> {
+        let book = self.listed_book_mut(symbol)?;
+        book.set_trading_state(TradingState::Halted);
+        let cancelled = if cancel_resting {
+            book.cancel_all(None, None, None)
+        } else {
+            Vec::new()
+        };
+        self.registry.symbols.get_mut(symbol).unwrap().suspended = true;
+        Ok(cancelled)
+    }
+
+    /// Return a suspended symbol to continuous trading, then make the scheduled changes
+    /// that fell due while it was suspended
+    pub fn resume_symbol(&mut self, symbol: &str) -> Result<(), ExchangeError> {
+        self.listed_book_mut(symbol)?
+            .set_trading_state(TradingState::ContinuousTrading);
+        self.registry.symbols.get_mut(symbol).unwrap().suspended = false;
+        self.run_schedule(symbol);
+        Ok(())
+    }
+
+    /// Remove a symbol and free its book. Its trades stay available from
+    /// `archived_trades` and `trades`. The symbol can be listed again later.
+    pub fn delist_symbol(&mut self, symbol: &str) -> Result<(), ExchangeError> {
+        let book = self
+            .books
+            .remove(symbol)
+            .ok_or_else(|| self.unlisted(symbol))?;
+        self.registry.symbols.remove(symbol);
+        self.archived_trades
+            .entry(symbol.to_string())
+            .or_default()
+            .extend(book.trades);
+        Ok(())
+    }
+
+    /// Trades of a symbol from before it was last delisted
+    pub fn archived_trades(&self, symbol: &str) -> &[Trade] {
+        self.archived_trades.get(symbol).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn registry(&self) -> &SymbolRegistry {
+        &self.registry
+    }
+
+    /// Latest timestamp the exchange has seen
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// Move the clock forward to `timestamp` and make every symbol's scheduled changes
+    /// that are due by then
+    pub fn advance_time(&mut self, timestamp: u64) {
+        self.clock = self.clock.max(timestamp);
+        let symbols: Vec<String> = self.books.keys().cloned().collect();
+        for symbol in &symbols {
+            self.run_schedule(symbol);
+        }
+    }
+
+    // Make the symbol's scheduled trading state changes that are due by the clock
+    fn run_schedule(&mut self, symbol: &str) {
+        let (Some(book), Some(registered)) = (
+            self.books.get_mut(symbol),
+            self.registry.symbols.get_mut(symbol),
+        ) else {
+            return;
+        };
+        if registered.suspended {
+            return;
+        }
+        while let Some(&(timestamp, state)) =
+            registered.config.schedule.get(registered.transitions_made)
+        {
+            if timestamp > self.clock {
+                break;
+            }
+            book.set_trading_state(state);
+            registered.transitions_made += 1;
+        }
+    }
+
+    /// Listed symbols in alphabetical order
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.books.keys().map(String::as_str)
+    }
+
+    pub fn book(&self, symbol: &str) -> Option<&OrderBook> {
+        self.books.get(symbol)
+    }
+
+    pub fn book_mut(&mut self, symbol: &str) -> Option<&mut OrderBook> {
+        self.books.get_mut(symbol)
+    }
+
+    fn listed_book_mut(&mut self, symbol: &str) -> Result<&mut OrderBook, ExchangeError> {
+        if !self.books.contains_key(symbol) {
+            return Err(self.unlisted(symbol));
+        }
+        Ok(self.books.get_mut(symbol).unwrap())
+    }
+
+    // Error for a symbol without a book
+    fn unlisted(&self, symbol: &str) -> ExchangeError {
+        let symbol = symbol.to_string();
+        if self.archived_trades.contains_key(&symbol) {
+            ExchangeError::SymbolDelisted { symbol }
+        } else {
+            ExchangeError::UnknownSymbol { symbol }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_order(
+        &mut self,
+        symbol: &str,
+        side: OrderSide,
+        order_type: OrderType,
+        price: Option<f64>,
+        quantity: f64,
+        timestamp: u64,
+        options: OrderOptions,
+    ) -> Result<u64, ExchangeError> {
+        self.clock = self.clock.max(timestamp);
+        self.run_schedule(symbol);
+        let book = self.listed_book_mut(symbol)?;
+        Ok(book.add_order_with_options(
+            side,
+            order_type,
+            price,
+            quantity,
+            timestamp,
+            Some(symbol.to_string()),
+            options,
+        )?)
+    }
+
+    /// Submit orders for any number of symbols at once, routed by the symbol of their
+    /// parameters. Each symbol's orders go through `OrderBook::batch_add_orders` as one
+    /// batch, symbols in alphabetical order, or all at once on the rayon thread pool with
+    /// `set_parallel_batches`. Either way each book sees its orders in input order, so
+    /// the outcome is the same. Every order is validated up front and the whole batch is
+    /// rejected without side effects if any fails. Returns the order ids in input order.
+    pub fn batch_add_orders(&mut self, orders: Vec<BatchOrder>) -> Result<Vec<u64>, ExchangeError> {
+        // Orders of each symbol with their positions in the input
+        let mut batches: BTreeMap<String, (Vec<usize>, Vec<BatchOrder>)> = BTreeMap::new();
+        for (i, order) in orders.into_iter().enumerate() {
+            let symbol = order.0 .5.clone().ok_or(ExchangeError::MissingSymbol)?;
+            let (positions, batch) = batches.entry(symbol).or_default();
+            positions.push(i);
+            batch.push(order);
+        }
+        for (symbol, (_, batch)) in &batches {
+            let book = self
+                .books
+                .get(symbol)
+                .ok_or_else(|| self.unlisted(symbol))?;
+            book.validate_batch(batch.clone())?;
+        }
+
+        let latest = batches
+            .values()
+            .flat_map(|(_, batch)| batch.iter().map(|order| order.0 .4))
+            .max();
+        self.clock = self.clock.max(latest.unwrap_or(0));
+        let mut order_ids = vec![0; batches.values().map(|(positions, _)| positions.len()).sum()];
+        for symbol in batches.keys() {
+            self.run_schedule(symbol);
+        }
+
+        let jobs: Vec<(&mut OrderBook, Vec<usize>, Vec<BatchOrder>)> = self
+            .books
+            .iter_mut()
+            .filter_map(|(symbol, book)| {
+                let (positions, batch) = batches.remove(symbol)?;
+                Some((book, positions, batch))
+            })
+            .collect();
+        let run = |(book, positions, batch): (&mut OrderBook, Vec<usize>, Vec<BatchOrder>)| {
+            (positions, book.batch_add_orders(batch))
+        };
+        let results: Vec<_> = if self.parallel_batches {
+            jobs.into_par_iter().map(run).collect()
+        } else {
+            jobs.into_iter().map(run).collect()
+        };
+        for (positions, ids) in results {
+            for (position, id) in positions.into_iter().zip(ids?) {
+                order_ids[position] = id;
+            }
+        }
+        Ok(order_ids)
+    }
+
+    /// Process the symbols of `batch_add_orders` in parallel rather than one by one
+    pub fn set_parallel_batches(&mut self, enabled: bool) {
+        self.parallel_batches = enabled;
+    }
+
+    pub fn parallel_batches(&self) -> bool {
+        self.parallel_batches
+    }
+
+    /// Cancel an order of a symbol. Returns false if the symbol is not listed or the
+    /// order is not open.
+    pub fn cancel_order(&mut self, symbol: &str, order_id: u64) -> bool {
+        self.books
+            .get_mut(symbol)
+            .is_some_and(|book| book.cancel_order(order_id))
+    }
+
+    pub fn get_order(&self, symbol: &str, order_id: u64) -> Option<OrderView> {
+        self.books.get(symbol)?.get_order(order_id)
+    }
+
+    /// Trades of every symbol, delisted ones included, in timestamp order
+    pub fn trades(&self) -> Vec<&Trade> {
+        let mut trades: Vec<&Trade> = self
+            .archived_trades
+            .values()
+            .flatten()
+            .chain(self.books.values().flat_map(|book| &book.trades))
+            .collect();
+        trades.sort_by_key(|trade| trade.timestamp);
+        trades
+    }
+}
+
+/// Python order class
+#[pyclass]
+#[derive(Clone)]
+struct PyOrder {
+    #[pyo3(get)]
+    id: u64,
+    #[pyo3(get)]
+    side: PyOrderSide,
+    #[pyo3(get)]
+    order_type: PyOrderType,
+    #[pyo3(get)]
+    price: Option<f64>,
+    #[pyo3(get)]
+    quantity: f64,
+    #[pyo3(get)]
+    filled_quantity: f64,
+    #[pyo3(get)]
+    remaining_quantity: f64,
+    #[pyo3(get)]
+    status: PyOrderStatus,
+    #[pyo3(get)]
+    timestamp: u64,
+    #[pyo3(get)]
+    symbol: Option<String>,
+    #[pyo3(get)]
+    time_in_force: PyTimeInForce,
+    #[pyo3(get)]
+    expire_at: Option<u64>,
+    #[pyo3(get)]
+    reject_reason: Option<PyRejectReason>,
+    #[pyo3(get)]
+    client_order_id: Option<String>,
+    #[pyo3(get)]
+    owner_id: Option<u64>,
+    #[pyo3(get)]
+    version: u64,
+}
+
+impl From<OrderView> for PyOrder {
+    fn from(view: OrderView) -> Self {
+        PyOrder {
+            id: view.id,
+            side: view.side.into(),
+            order_type: view.order_type.into(),
+            price: view.price,
+            quantity: view.quantity,
+            filled_quantity: view.filled_quantity,
+            remaining_quantity: view.remaining_quantity,
+            status: view.status.into(),
+            timestamp: view.timestamp,
+            symbol: view.symbol,
+            time_in_force: view.time_in_force.into(),
+            expire_at: view.time_in_force.expire_at(),
+            reject_reason: view.reject_reason.map(PyRejectReason::from),
+            client_order_id: view.client_order_id,
+            owner_id: view.owner_id,
+            version: view.version,
+        }
+    }
+}
+
+/// Python trade class
+#[pyclass]
+#[derive(Clone)]
+struct PyTrade {
+    #[pyo3(get)]
+    id: u64,
+    #[pyo3(get)]
+    buy_order_id: u64,
+    #[pyo3(get)]
+    sell_order_id: u64,
+    #[pyo3(get)]
+    price: f64,
+    #[pyo3(get)]
+    quantity: f64,
+    #[pyo3(get)]
+    timestamp: u64,
+    #[pyo3(get)]
+    symbol: Option<String>,
+    #[pyo3(get)]
+    maker_fee: f64,
+    #[pyo3(get)]
+    taker_fee: f64,
+    #[pyo3(get)]
+    buyer_owner_id: Option<u64>,
+    #[pyo3(get)]
+    seller_owner_id: Option<u64>,
+    #[pyo3(get)]
+    busted: bool,
+    #[pyo3(get)]
+    aggressor_side: PyOrderSide,
+    #[pyo3(get)]
+    maker_order_id: u64,
+    #[pyo3(get)]
+    taker_order_id: u64,
+    #[pyo3(get)]
+    buyer_liquidity: PyLiquidity,
+    #[pyo3(get)]
+    seller_liquidity: PyLiquidity,
+    #[pyo3(get)]
+    wash_trade: bool,
+    #[pyo3(get)]
+    aggressor_limit_price: Option<f64>,
+    #[pyo3(get)]
+    price_improvement: Option<f64>,
+    #[pyo3(get)]
+    flags: u32,
+    #[pyo3(get)]
+    notional: f64,
+}
+
+impl From<&Trade> for PyTrade {
+    fn from(trade: &Trade) -> Self {
+        PyTrade {
+            id: trade.id,
+            buy_order_id: trade.buy_order_id,
+            sell_order_id: trade.sell_order_id,
+            price: trade.price,
+            quantity: trade.quantity,
+            timestamp: trade.timestamp,
+            symbol: trade.symbol.clone(), // Clone symbol String if needed
+            maker_fee: trade.maker_fee,
+            taker_fee: trade.taker_fee,
+            buyer_owner_id: trade.buyer_owner_id,
+            seller_owner_id: trade.seller_owner_id,
+            busted: trade.busted,
+            aggressor_side: trade.aggressor_side.into(),
+            maker_order_id: trade.maker_order_id,
+            taker_order_id: trade.taker_order_id,
+            buyer_liquidity: trade.liquidity(trade.buy_order_id).unwrap().into(),
+            seller_liquidity: trade.liquidity(trade.sell_order_id).unwrap().into(),
+            wash_trade: trade.wash_trade,
+            aggressor_limit_price: trade.aggressor_limit_price,
+            price_improvement: trade.price_improvement(),
+            flags: trade.flags.bits(),
+            notional: trade.notional,
+        }
+    }
+}
+
+/// Python order book statistics class
+#[pyclass]
+#[derive(Clone)]
+struct PyOrderBookStats {
+    #[pyo3(get)]
+    orders_processed: u64,
+    #[pyo3(get)]
+    trades_executed: u64,
+    #[pyo3(get)]
+    total_trade_volume: f64,
+    #[pyo3(get)]
+    total_notional: f64,
+    #[pyo3(get)]
+    resting_order_count: usize,
+    #[pyo3(get)]
+    bid_levels: usize,
+    #[pyo3(get)]
+    ask_levels: usize,
+    #[pyo3(get)]
+    wash_trades: u64,
+    #[pyo3(get)]
+    wash_trade_volume: f64,
+    #[pyo3(get)]
+    buy_price_improved_trades: u64,
+    #[pyo3(get)]
+    buy_price_improvement: f64,
+    #[pyo3(get)]
+    sell_price_improved_trades: u64,
+    #[pyo3(get)]
+    sell_price_improvement: f64,
+    #[pyo3(get)]
+    last_trade_price: Option<f64>,
+    #[pyo3(get)]
+    open_price: Option<f64>,
+    #[pyo3(get)]
+    high_price: Option<f64>,
+    #[pyo3(get)]
+    low_price: Option<f64>,
+    #[pyo3(get)]
+    close_price: Option<f64>,
+    #[pyo3(get)]
+    session_volume: f64,
+    #[pyo3(get)]
+    opening_price: Option<f64>,
+}
+
+/// Python trading session statistics class
+#[pyclass]
+#[derive(Clone)]
+struct PySessionStats {
+    #[pyo3(get)]
+    session: u64,
+    #[pyo3(get)]
+    orders_processed: u64,
+    #[pyo3(get)]
+    trades_executed: u64,
+    #[pyo3(get)]
+    total_trade_volume: f64,
+    #[pyo3(get)]
+    open_price: Option<f64>,
+    #[pyo3(get)]
+    high_price: Option<f64>,
+    #[pyo3(get)]
+    low_price: Option<f64>,
+    #[pyo3(get)]
+    close_price: Option<f64>,
+    #[pyo3(get)]
+    day_orders_cancelled: usize,
+    #[pyo3(get)]
+    official_close_price: Option<f64>,
+    #[pyo3(get)]
+    opening_price: Option<f64>,
+}
+
+/// Python order audit trail entry class
+#[pyclass]
+#[derive(Clone)]
+struct PyAuditEntry {
+    #[pyo3(get)]
+    sequence: u64,
+    #[pyo3(get)]
+    timestamp: u64,
+    #[pyo3(get)]
+    action: PyAuditAction,
+    #[pyo3(get)]
+    filled_quantity: f64,
+    #[pyo3(get)]
+    remaining_quantity: f64,
+    #[pyo3(get)]
+    version: u64,
+}
+
+impl From<&AuditEntry> for PyAuditEntry {
+    fn from(entry: &AuditEntry) -> Self {
+        PyAuditEntry {
+            sequence: entry.sequence,
+            timestamp: entry.timestamp,
+            action: entry.action.into(),
+            filled_quantity: entry.filled_quantity,
+            remaining_quantity: entry.remaining_quantity,
+            version: entry.version,
+        }
+    }
+}
+
+impl From<SessionStats> for PySessionStats {
+    fn from(stats: SessionStats) -> Self {
+        PySessionStats {
+            session: stats.session,
+            orders_processed: stats.orders_processed,
+            trades_executed: stats.trades_executed,
+            total_trade_volume: stats.total_trade_volume,
+            open_price: stats.open_price,
+            high_price: stats.high_price,
+            low_price: stats.low_price,
+            close_price: stats.close_price,
+            day_orders_cancelled: stats.day_orders_cancelled,
+            official_close_price: stats.official_close_price,
+            opening_price: stats.opening_price,
+        }
+    }
+}
+
+impl From<OrderBookStats> for PyOrderBookStats {
+    fn from(stats: OrderBookStats) -> Self {
+        PyOrderBookStats {
+            orders_processed: stats.orders_processed,
+            trades_executed: stats.trades_executed,
+            total_trade_volume: stats.total_trade_volume,
+            total_notional: stats.total_notional,
+            resting_order_count: stats.resting_order_count,
+            bid_levels: stats.bid_levels,
+            ask_levels: stats.ask_levels,
+            wash_trades: stats.wash_trades,
+            wash_trade_volume: stats.wash_trade_volume,
+            buy_price_improved_trades: stats.buy_price_improved_trades,
+            buy_price_improvement: stats.buy_price_improvement,
+            sell_price_improved_trades: stats.sell_price_improved_trades,
+            sell_price_improvement: stats.sell_price_improvement,
+            last_trade_price: stats.last_trade_price,
+            open_price: stats.open_price,
+            high_price: stats.high_price,
+            low_price: stats.low_price,
+            close_price: stats.close_price,
+            session_volume: stats.session_volume,
+            opening_price: stats.opening_price,
+        }
+    }
+}
+
+/// Python book condition class, built with the static constructors
+#[pyclass]
+#[derive(Clone)]
+struct PyBookCondition {
+    condition: BookCondition,
+}
+
+#[pymethods]
+impl PyBookCondition {
+    #[staticmethod]
+    fn depth_at_least(side: PyOrderSide, price: f64, quantity: f64) -> Self {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+        PyBookCondition {
+            condition: BookCondition::DepthAtLeast {
+                side,
+                price,
+                quantity,
+            },
+        }
+    }
+
+    #[staticmethod]
+    fn best_depth_at_least(side: PyOrderSide, quantity: f64) -> Self {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+        PyBookCondition {
+            condition: BookCondition::BestDepthAtLeast { side, quantity },
+        }
+    }
+
+    #[staticmethod]
+    fn spread_at_most(spread: f64) -> Self {
+        PyBookCondition {
+            condition: BookCondition::SpreadAtMost(spread),
+        }
+    }
+
+    #[staticmethod]
+    fn all_of(conditions: Vec<PyBookCondition>) -> Self {
+        PyBookCondition {
+            condition: BookCondition::All(conditions.into_iter().map(|c| c.condition).collect()),
+        }
+    }
+
+    #[staticmethod]
+    fn any_of(conditions: Vec<PyBookCondition>) -> Self {
+        PyBookCondition {
+            condition: BookCondition::Any(conditions.into_iter().map(|c| c.condition).collect()),
+        }
+    }
+}
+
+// Band given from Python as either a tick count or a percentage; neither turns it off
+fn protection_band(ticks: Option<u32>, percent: Option<f64>) -> PyResult<Option<ProtectionBand>> {
+    match (ticks, percent) {
+        (Some(_), Some(_)) => Err(PyValueError::new_err(
+            "give the band in ticks or percent, not both",
+        )),
+        (Some(ticks), None) => Ok(Some(ProtectionBand::Ticks(ticks))),
+        (None, Some(percent)) => Ok(Some(ProtectionBand::Percent(percent))),
+        (None, None) => Ok(None),
+    }
+}
+
+// Price bands from the band arguments of the Python API, `None` if neither band is given
+fn price_bands(
+    static_reference: Option<f64>,
+    static_ticks: Option<u32>,
+    static_percent: Option<f64>,
+    dynamic_ticks: Option<u32>,
+    dynamic_percent: Option<f64>,
+    halt_on_breach: bool,
+) -> PyResult<Option<PriceBands>> {
+    let static_band = protection_band(static_ticks, static_percent)?;
+    let dynamic_band = protection_band(dynamic_ticks, dynamic_percent)?;
+    Ok(
+        (static_band.is_some() || dynamic_band.is_some()).then_some(PriceBands {
+            static_reference,
+            static_band,
+            dynamic_band,
+            on_breach: if halt_on_breach {
+                BandBreach::Halt
+            } else {
+                BandBreach::Reject
+            },
+        }),
+    )
+}
+
+fn instrument_metadata(
+    quote_currency: Option<String>,
+    contract_multiplier: f64,
+    asset_class: Option<PyAssetClass>,
+) -> PyResult<InstrumentMetadata> {
+    if contract_multiplier <= 0.0 {
+        return Err(PyValueError::new_err(
+            "contract_multiplier must be positive",
+        ));
+    }
+    Ok(InstrumentMetadata {
+        quote_currency,
+        contract_multiplier,
+        asset_class: asset_class.map(AssetClass::from).unwrap_or_default(),
+    })
+}
+
+fn matching_policy(policy: PyMatchingPolicy, top_order_share: f64) -> MatchingPolicy {
+    match policy {
+        PyMatchingPolicy::PriceTime => MatchingPolicy::PriceTime,
+        PyMatchingPolicy::ProRata => MatchingPolicy::ProRata,
+        PyMatchingPolicy::TopOrderProRata => MatchingPolicy::TopOrderProRata { top_order_share },
+    }
+}
+
+/// Per-quote `(order ids, rejection reason)` returned by `PyOrderBook::mass_quote`
+type PyQuoteResult = (Option<(u64, u64)>, Option<String>);
+
+/// Python order book class
+#[pyclass]
+struct PyOrderBook {
+    order_book: OrderBook,
+}
+
+#[pymethods]
+impl PyOrderBook {
+    #[new]
+    fn new() -> Self {
+        PyOrderBook {
+            order_book: OrderBook::new(),
+        }
+    }
+
+    #[pyo3(signature = (side, price, quantity, timestamp, owner_id = None, expire_at = None, symbol = None, display_quantity = None, hidden = false, min_execution_quantity = None, all_or_none = false, reduce_only = false, time_in_force = None, auction_only = false, discretion = None, auction_target = None, priority = false, client_order_id = None, session_id = None, order_id = None, short_sale = false))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_limit_order(
+        &mut self,
+        side: PyOrderSide,
+        price: f64,
+        quantity: f64,
+        timestamp: u64,
+        owner_id: Option<u64>,
+        expire_at: Option<u64>,
+        symbol: Option<String>,
+        display_quantity: Option<f64>,
+        hidden: bool,
+        min_execution_quantity: Option<f64>,
+        all_or_none: bool,
+        reduce_only: bool,
+        time_in_force: Option<PyTimeInForce>,
+        auction_only: bool,
+        discretion: Option<f64>,
+        auction_target: Option<PyAuctionSession>,
+        priority: bool,
+        client_order_id: Option<String>,
+        session_id: Option<u64>,
+        order_id: Option<u64>,
+        short_sale: bool,
+    ) -> PyResult<u64> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+        // `expire_at` alone implies GTD; any other time in force must not carry one
+        let time_in_force = match (time_in_force, expire_at) {
+            (None, None) | (Some(PyTimeInForce::Gtc), None) => TimeInForce::Gtc,
+            (None, Some(expire_at)) | (Some(PyTimeInForce::Gtd), Some(expire_at)) => {
+                TimeInForce::Gtd { expire_at }
+            }
+            (Some(PyTimeInForce::Gtd), None) => {
+                return Err(PyValueError::new_err("GTD orders require expire_at"))
+            }
+            (Some(_), Some(_)) => {
+                return Err(PyValueError::new_err(
+                    "expire_at is only valid for GTD orders",
+                ))
+            }
+            (Some(PyTimeInForce::Ioc), None) => TimeInForce::Ioc,
+            (Some(PyTimeInForce::Fok), None) => TimeInForce::Fok,
+            (Some(PyTimeInForce::Day), None) => TimeInForce::Day,
+        };
+
+        Ok(self.order_book.add_order_with_options(
+            side,
+            OrderType::Limit,
+            Some(price),
+            quantity,
+            timestamp,
+            symbol,
+            OrderOptions {
+                owner_id,
+                time_in_force,
+                display_quantity,
+                hidden,
+                min_execution_quantity,
+                all_or_none,
+                reduce_only,
+                auction_only,
+                discretion,
+                auction_target: auction_target.map(AuctionSession::from),
+                priority,
+                client_order_id,
+                session_id,
+                order_id,
+                short_sale,
+                ..OrderOptions::default()
+            },
+        )?)
+    }
+
+    #[pyo3(signature = (side, quantity, timestamp, max_slippage_bps = None, symbol = None, min_execution_quantity = None, convert_to_limit = false, owner_id = None, reduce_only = false, worst_price = None, auction_only = false, auction_target = None, client_order_id = None, order_id = None, short_sale = false))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_market_order(
+        &mut self,
+        side: PyOrderSide,
+        quantity: f64,
+        timestamp: u64,
+        max_slippage_bps: Option<f64>,
+        symbol: Option<String>,
+        min_execution_quantity: Option<f64>,
+        convert_to_limit: bool,
+        owner_id: Option<u64>,
+        reduce_only: bool,
+        worst_price: Option<f64>,
+        auction_only: bool,
+        auction_target: Option<PyAuctionSession>,
+        client_order_id: Option<String>,
+        order_id: Option<u64>,
+        short_sale: bool,
+    ) -> PyResult<u64> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+
+        Ok(self.order_book.add_order_with_options(
+            side,
+            OrderType::Market,
+            None,
+            quantity,
+            timestamp,
+            symbol,
+            OrderOptions {
+                max_slippage_bps,
+                worst_price,
+                min_execution_quantity,
+                market_remainder: if convert_to_limit {
+                    MarketRemainder::ConvertToLimit
+                } else {
+                    MarketRemainder::Cancel
+                },
+                owner_id,
+                reduce_only,
+                auction_only,
+                auction_target: auction_target.map(AuctionSession::from),
+                client_order_id,
+                order_id,
+                short_sale,
+                ..OrderOptions::default()
+            },
+        )?)
+    }
+
+    #[pyo3(signature = (side, price, quantity, timestamp, reprice = false))]
+    fn add_post_only_order(
+        &mut self,
+        side: PyOrderSide,
+        price: f64,
+        quantity: f64,
+        timestamp: u64,
+        reprice: bool,
+    ) -> PyResult<u64> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+
+        Ok(self.order_book.add_order_with_options(
+            side,
+            OrderType::Limit,
+            Some(price),
+            quantity,
+            timestamp,
+            None,
+            OrderOptions {
+                post_only: true,
+                post_only_reprice: reprice,
+                ..OrderOptions::default()
+            },
+        )?)
+    }
+
+    #[pyo3(signature = (side, limit_price, quantity, timestamp))]
+    fn add_midpoint_peg_order(
+        &mut self,
+        side: PyOrderSide,
+        limit_price: f64,
+        quantity: f64,
+        timestamp: u64,
+    ) -> PyResult<u64> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+
+        Ok(self.order_book.add_order_with_options(
+            side,
+            OrderType::Limit,
+            Some(limit_price),
+            quantity,
+            timestamp,
+            None,
+            OrderOptions {
+                peg: Some(Peg::Midpoint),
+                ..OrderOptions::default()
+            },
+        )?)
+    }
+
+    #[pyo3(signature = (side, peg, limit_price, quantity, timestamp, offset_ticks = 0))]
+    fn add_pegged_order(
+        &mut self,
+        side: PyOrderSide,
+        peg: PyPeg,
+        limit_price: f64,
+        quantity: f64,
+        timestamp: u64,
+        offset_ticks: i64,
+    ) -> PyResult<u64> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+        let peg = match peg {
+            PyPeg::Midpoint if offset_ticks != 0 => {
+                return Err(PyValueError::new_err(
+                    "midpoint pegs do not support a tick offset",
+                ))
+            }
+            PyPeg::Midpoint => Peg::Midpoint,
+            PyPeg::Primary => Peg::Primary { offset_ticks },
+            PyPeg::Market => Peg::Market { offset_ticks },
+        };
+
+        Ok(self.order_book.add_order_with_options(
+            side,
+            OrderType::Limit,
+            Some(limit_price),
+            quantity,
+            timestamp,
+            None,
+            OrderOptions {
+                peg: Some(peg),
+                ..OrderOptions::default()
+            },
+        )?)
+    }
+
+    #[pyo3(signature = (side, price, quantity, timestamp, take_profit, stop_loss))]
+    fn add_bracket_order(
+        &mut self,
+        side: PyOrderSide,
+        price: f64,
+        quantity: f64,
+        timestamp: u64,
+        take_profit: f64,
+        stop_loss: f64,
+    ) -> PyResult<u64> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+
+        Ok(self.order_book.add_order_with_options(
+            side,
+            OrderType::Limit,
+            Some(price),
+            quantity,
+            timestamp,
+            None,
+            OrderOptions {
+                bracket: Some(Bracket {
+                    take_profit,
+                    stop_loss,
+                }),
+                ..OrderOptions::default()
+            },
+        )?)
+    }
+
+    fn get_bracket_children(&self, parent_id: u64) -> PyResult<Option<(u64, u64)>> {
+        Ok(self.order_book.bracket_children(parent_id))
+    }
+
+    #[pyo3(signature = (side, limit_price, stop_price, quantity, timestamp))]
+    fn add_oco_order(
+        &mut self,
+        side: PyOrderSide,
+        limit_price: f64,
+        stop_price: f64,
+        quantity: f64,
+        timestamp: u64,
+    ) -> PyResult<(u64, u64)> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+
+        Ok(self.order_book.add_oco_orders(
+            (
+                (
+                    side,
+                    OrderType::Limit,
+                    Some(limit_price),
+                    quantity,
+                    timestamp,
+                    None,
+                ),
+                OrderOptions::default(),
+            ),
+            (
+                (side, OrderType::Market, None, quantity, timestamp, None),
+                OrderOptions {
+                    stop_price: Some(stop_price),
+                    ..OrderOptions::default()
+                },
+            ),
+        )?)
+    }
+
+    #[pyo3(signature = (side, stop_price, quantity, timestamp, trigger = PyStopTrigger::LastTrade))]
+    fn add_stop_market_order(
+        &mut self,
+        side: PyOrderSide,
+        stop_price: f64,
+        quantity: f64,
+        timestamp: u64,
+        trigger: PyStopTrigger,
+    ) -> PyResult<u64> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+
+        Ok(self.order_book.add_order_with_options(
+            side,
+            OrderType::Market,
+            None,
+            quantity,
+            timestamp,
+            None,
+            OrderOptions {
+                stop_price: Some(stop_price),
+                stop_trigger: trigger.into(),
+                ..OrderOptions::default()
+            },
+        )?)
+    }
+
+    #[pyo3(signature = (side, stop_price, price, quantity, timestamp, trigger = PyStopTrigger::LastTrade))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_stop_limit_order(
+        &mut self,
+        side: PyOrderSide,
+        stop_price: f64,
+        price: f64,
+        quantity: f64,
+        timestamp: u64,
+        trigger: PyStopTrigger,
+    ) -> PyResult<u64> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+
+        Ok(self.order_book.add_order_with_options(
+            side,
+            OrderType::Limit,
+            Some(price),
+            quantity,
+            timestamp,
+            None,
+            OrderOptions {
+                stop_price: Some(stop_price),
+                stop_trigger: trigger.into(),
+                ..OrderOptions::default()
+            },
+        )?)
+    }
+
+    #[pyo3(signature = (side, condition, quantity, timestamp, price = None))]
+    fn add_conditional_order(
+        &mut self,
+        side: PyOrderSide,
+        condition: PyBookCondition,
+        quantity: f64,
+        timestamp: u64,
+        price: Option<f64>,
+    ) -> PyResult<u64> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+        let order_type = match price {
+            Some(_) => OrderType::Limit,
+            None => OrderType::Market,
+        };
+
+        Ok(self.order_book.add_order_with_options(
+            side,
+            order_type,
+            price,
+            quantity,
+            timestamp,
+            None,
+            OrderOptions {
+                condition: Some(condition.condition),
+                ..OrderOptions::default()
+            },
+        )?)
+    }
+
+    #[pyo3(signature = (participant, bid_price, bid_quantity, ask_price, ask_quantity, timestamp, symbol = None))]
+    #[allow(clippy::too_many_arguments)]
+    fn quote(
+        &mut self,
+        participant: u64,
+        bid_price: f64,
+        bid_quantity: f64,
+        ask_price: f64,
+        ask_quantity: f64,
+        timestamp: u64,
+        symbol: Option<String>,
+    ) -> PyResult<(u64, u64)> {
+        Ok(self.order_book.quote(
+            participant,
+            bid_price,
+            bid_quantity,
+            ask_price,
+            ask_quantity,
+            timestamp,
+            symbol,
+        )?)
+    }
+
+    #[pyo3(signature = (participant, quotes, timestamp, all_or_nothing = false, symbol = None))]
+    fn mass_quote(
+        &mut self,
+        participant: u64,
+        quotes: Vec<(f64, f64, f64, f64)>,
+        timestamp: u64,
+        all_or_nothing: bool,
+        symbol: Option<String>,
+    ) -> PyResult<Vec<PyQuoteResult>> {
+        let quotes = quotes
+            .into_iter()
+            .map(|(bid_price, bid_quantity, ask_price, ask_quantity)| Quote {
+                bid_price,
+                bid_quantity,
+                ask_price,
+                ask_quantity,
+            })
+            .collect();
+        let mode = if all_or_nothing {
+            MassQuoteMode::AllOrNothing
+        } else {
+            MassQuoteMode::BestEffort
+        };
+
+        Ok(self
+            .order_book
+            .mass_quote(participant, quotes, mode, timestamp, symbol)
+            .into_iter()
+            .map(|result| match result {
+                Ok(order_ids) => (Some(order_ids), None),
+                Err(err) => (None, Some(err.to_string())),
+            })
+            .collect())
+    }
+
+    #[pyo3(signature = (side, trigger_price, quantity, timestamp, trigger = PyStopTrigger::LastTrade))]
+    fn add_market_if_touched_order(
+        &mut self,
+        side: PyOrderSide,
+        trigger_price: f64,
+        quantity: f64,
+        timestamp: u64,
+        trigger: PyStopTrigger,
+    ) -> PyResult<u64> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+
+        Ok(self.order_book.add_order_with_options(
+            side,
+            OrderType::Market,
+            None,
+            quantity,
+            timestamp,
+            None,
+            OrderOptions {
+                stop_price: Some(trigger_price),
+                stop_trigger: trigger.into(),
+                if_touched: true,
+                ..OrderOptions::default()
+            },
+        )?)
+    }
+
+    #[pyo3(signature = (side, notional, timestamp, symbol = None))]
+    fn add_market_order_by_notional(
+        &mut self,
+        side: PyOrderSide,
+        notional: f64,
+        timestamp: u64,
+        symbol: Option<String>,
+    ) -> PyResult<u64> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+
+        Ok(self
+            .order_book
+            .add_market_order_by_notional(side, notional, timestamp, symbol)?)
+    }
+
+    fn cancel_order(&mut self, order_id: u64) -> PyResult<bool> {
+        Ok(self.order_book.cancel_order(order_id))
+    }
+
+    #[pyo3(signature = (order_id, new_price = None, new_quantity = None))]
+    fn modify_order(
+        &mut self,
+        order_id: u64,
+        new_price: Option<f64>,
+        new_quantity: Option<f64>,
+    ) -> PyResult<bool> {
+        Ok(self
+            .order_book
+            .modify_order(order_id, new_price, new_quantity)?)
+    }
+
+    fn modify_orders(
+        &mut self,
+        amendments: Vec<(u64, Option<f64>, Option<f64>)>,
+    ) -> PyResult<Vec<(bool, Option<PyRejectReason>)>> {
+        Ok(self
+            .order_book
+            .modify_orders(&amendments)
+            .into_iter()
+            .map(|result| match result {
+                Ok(modified) => (modified, None),
+                Err(err) => (false, Some(err.reject_reason().into())),
+            })
+            .collect())
+    }
+
+    fn cancel_replace(
+        &mut self,
+        order_id: u64,
+        new_price: f64,
+        new_quantity: f64,
+        timestamp: u64,
+    ) -> PyResult<Option<u64>> {
+        Ok(self
+            .order_book
+            .cancel_replace(order_id, new_price, new_quantity, timestamp)?)
+    }
+
+    #[pyo3(signature = (side = None, price_range = None, owner_id = None))]
+    fn cancel_all(
+        &mut self,
+        side: Option<PyOrderSide>,
+        price_range: Option<(f64, f64)>,
+        owner_id: Option<u64>,
+    ) -> PyResult<Vec<u64>> {
+        let side = side.map(|side| match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        });
+
+        Ok(self.order_book.cancel_all(side, price_range, owner_id))
+    }
+
+    fn cancel_participant_orders(&mut self, owner_id: u64) -> PyResult<Vec<u64>> {
+        Ok(self.order_book.cancel_participant_orders(owner_id))
+    }
+
+    #[pyo3(signature = (symbol, owner_id = None))]
+    fn cancel_symbol_orders(&mut self, symbol: &str, owner_id: Option<u64>) -> PyResult<Vec<u64>> {
+        Ok(self.order_book.cancel_symbol_orders(symbol, owner_id))
+    }
+
+    #[pyo3(signature = (timestamp, heartbeat_timeout = None))]
+    fn open_session(&mut self, timestamp: u64, heartbeat_timeout: Option<u64>) -> PyResult<u64> {
+        Ok(self.order_book.open_session(heartbeat_timeout, timestamp))
+    }
+
+    fn heartbeat(&mut self, session_id: u64, timestamp: u64) -> PyResult<bool> {
+        Ok(self.order_book.heartbeat(session_id, timestamp))
+    }
+
+    fn close_session(&mut self, session_id: u64) -> PyResult<Vec<u64>> {
+        Ok(self.order_book.close_session(session_id))
+    }
+
+    fn expire_sessions(&mut self, now: u64) -> PyResult<Vec<u64>> {
+        Ok(self.order_book.expire_sessions(now))
+    }
+
+    fn complete_auction(&mut self, session: PyAuctionSession) -> PyResult<Vec<u64>> {
+        Ok(self.order_book.complete_auction(session.into()))
+    }
+
+    fn set_trading_state(&mut self, state: PyTradingState) -> PyResult<()> {
+        self.order_book.set_trading_state(state.into());
+        Ok(())
+    }
+
+    fn trading_state(&self) -> PyResult<PyTradingState> {
+        Ok(self.order_book.trading_state().into())
+    }
+
+    fn start_auction(&mut self, session: PyAuctionSession) -> PyResult<bool> {
+        Ok(self.order_book.start_auction(session.into()))
+    }
+
+    fn indicative_auction(&self) -> PyResult<Option<(f64, f64, f64)>> {
+        Ok(self
+            .order_book
+            .indicative_auction()
+            .map(|result| (result.price, result.volume, result.imbalance)))
+    }
+
+    fn run_auction(&mut self, session: PyAuctionSession) -> PyResult<Option<(f64, f64, f64)>> {
+        Ok(self
+            .order_book
+            .run_auction(session.into())
+            .map(|result| (result.price, result.volume, result.imbalance)))
+    }
+
+    fn expire_orders(&mut self, now: u64) -> PyResult<Vec<u64>> {
+        Ok(self.order_book.expire_orders(now))
+    }
+
+    fn advance_time(&mut self, now: u64) -> PyResult<Vec<PyOrder>> {
+        Ok(self
+            .order_book
+            .advance_time(now)
+            .into_iter()
+            .map(PyOrder::from)
+            .collect())
+    }
+
+    fn set_day_close(&mut self, day_close: Option<u64>) -> PyResult<()> {
+        self.order_book.set_day_close(day_close);
+        Ok(())
+    }
+
+    fn get_order_book_snapshot(&mut self) -> PyResult<DepthSnapshot> {
+        Ok(self.order_book.get_order_book_snapshot())
+    }
 
-    // Helper function to convert f64 to i64 bits for stable sorting
-    fn price_to_bits(price: f64, is_buy: bool) -> i64 {
-        let bits = price.to_bits() as i64;
-        if is_buy {
-            // For buy orders, negate to get descending order
-            -bits
-        } else {
-            bits
-        }
+    fn get_full_order_book_snapshot(&mut self) -> PyResult<FullDepthSnapshot> {
+        Ok(self.order_book.get_full_order_book_snapshot())
     }
 
-    // Helper function to convert i64 bits back to f64
-    fn bits_to_price(bits: i64, is_buy: bool) -> f64 {
-        let abs_bits = if is_buy { -bits } else { bits };
-        f64::from_bits(abs_bits as u64)
+    #[pyo3(signature = (side = None, price_range = None, owner_id = None))]
+    fn open_orders(
+        &self,
+        side: Option<PyOrderSide>,
+        price_range: Option<(f64, f64)>,
+        owner_id: Option<u64>,
+    ) -> PyResult<Vec<PyOrder>> {
+        let side = side.map(|side| match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        });
+
+        Ok(self
+            .order_book
+            .open_orders(side, price_range, owner_id)
+            .into_iter()
+            .map(PyOrder::from)
+            .collect())
     }
 
-    // Get or create price level with caching
-    fn get_or_create_price_level(
-        &mut self,
-        is_buy: bool,
-        price_bits: i64,
-        create_new: bool,
-    ) -> Option<&mut PriceLevel> {
-        let price_map = if is_buy {
-            &mut self.buy_price_levels
-        } else {
-            &mut self.sell_price_levels
-        };
+    fn l3_snapshot(&self) -> PyResult<(Vec<PyOrder>, Vec<PyOrder>)> {
+        let (bids, asks) = self.order_book.l3_snapshot();
+        Ok((
+            bids.into_iter().map(PyOrder::from).collect(),
+            asks.into_iter().map(PyOrder::from).collect(),
+        ))
+    }
 
-        if price_map.contains_key(&price_bits) {
-            Some(price_map.get_mut(&price_bits).unwrap())
-        } else if create_new {
-            let price = Self::bits_to_price(price_bits, is_buy);
-            let level = PriceLevel::new(price);
-            price_map.insert(price_bits, level);
-            Some(price_map.get_mut(&price_bits).unwrap())
-        } else {
-            None
-        }
+    #[pyo3(signature = (levels = 1))]
+    fn imbalance(&mut self, levels: usize) -> PyResult<Option<f64>> {
+        Ok(self.order_book.imbalance(levels))
     }
 
-    pub fn add_order(
-        &mut self,
-        side: OrderSide,
-        order_type: OrderType,
-        price: Option<f64>,
-        quantity: f64,
-        timestamp: u64,
-        symbol: Option<String>,
-    ) -> u64 {
-        let order_id = self.next_order_id;
-        self.next_order_id += 1;
-        self.stats.orders_processed += 1;
+    fn microprice(&self) -> PyResult<Option<f64>> {
+        Ok(self.order_book.microprice())
+    }
 
-        // Create the order
-        let mut order = Order::new(
-            order_id, side, order_type, price, quantity, timestamp, symbol,
-        );
+    #[pyo3(signature = (limit = None))]
+    fn get_trades(&self, limit: Option<usize>) -> PyResult<Vec<PyTrade>> {
+        self.order_book.get_trades(limit)
+    }
 
-        // Process the order
-        self.process_order(&mut order);
+    #[pyo3(signature = (symbol = None, start_ts = None, end_ts = None))]
+    fn get_trades_filtered(
+        &self,
+        symbol: Option<&str>,
+        start_ts: Option<u64>,
+        end_ts: Option<u64>,
+    ) -> PyResult<Vec<PyTrade>> {
+        Ok(self
+            .order_book
+            .trades_filtered(symbol, start_ts, end_ts)
+            .into_iter()
+            .map(PyTrade::from)
+            .collect())
+    }
 
-        // Return the order ID
-        order_id
+    fn get_fills(&self, order_id: u64) -> PyResult<Vec<PyTrade>> {
+        Ok(self
+            .order_book
+            .fills(order_id)
+            .into_iter()
+            .map(PyTrade::from)
+            .collect())
+    }
+
+    fn verify_integrity(&self) -> PyResult<()> {
+        Ok(self.order_book.verify_integrity()?)
+    }
+
+    #[pyo3(signature = (trade_id, restore_orders = false))]
+    fn bust_trade(&mut self, trade_id: u64, restore_orders: bool) -> PyResult<bool> {
+        Ok(self.order_book.bust_trade(trade_id, restore_orders))
+    }
+
+    fn get_statistics(&self) -> PyResult<PyOrderBookStats> {
+        Ok(self.order_book.get_statistics().into())
+    }
+
+    fn end_session(&mut self) -> PyResult<PySessionStats> {
+        Ok(self.order_book.end_session().into())
+    }
+
+    fn get_session_stats(&self) -> PyResult<PySessionStats> {
+        Ok(self.order_book.session_stats().clone().into())
+    }
+
+    fn get_session_history(&self) -> PyResult<Vec<PySessionStats>> {
+        Ok(self
+            .order_book
+            .session_history()
+            .iter()
+            .cloned()
+            .map(PySessionStats::from)
+            .collect())
+    }
+
+    #[pyo3(signature = (client_order_id, owner_id = None))]
+    fn get_order_by_client_id(
+        &self,
+        client_order_id: &str,
+        owner_id: Option<u64>,
+    ) -> PyResult<Option<PyOrder>> {
+        Ok(self
+            .order_book
+            .get_order_by_client_id(owner_id, client_order_id)
+            .map(PyOrder::from))
     }
 
-    pub fn batch_add_orders(
+    #[pyo3(signature = (client_order_id, owner_id = None))]
+    fn cancel_order_by_client_id(
         &mut self,
-        orders: Vec<(OrderSide, OrderType, Option<f64>, f64, u64, Option<String>)>,
-    ) -> Vec<u64> {
-        if orders.is_empty() {
-            return Vec::new();
-        }
+        client_order_id: &str,
+        owner_id: Option<u64>,
+    ) -> PyResult<bool> {
+        Ok(self
+            .order_book
+            .cancel_order_by_client_id(owner_id, client_order_id))
+    }
 
-        let mut order_ids = Vec::with_capacity(orders.len());
-        let mut batch = OrderBatch::new();
+    #[pyo3(signature = (client_order_id, owner_id = None, new_price = None, new_quantity = None))]
+    fn modify_order_by_client_id(
+        &mut self,
+        client_order_id: &str,
+        owner_id: Option<u64>,
+        new_price: Option<f64>,
+        new_quantity: Option<f64>,
+    ) -> PyResult<bool> {
+        Ok(self.order_book.modify_order_by_client_id(
+            owner_id,
+            client_order_id,
+            new_price,
+            new_quantity,
+        )?)
+    }
 
-        // Create all orders first
-        for (side, order_type, price, quantity, timestamp, symbol) in orders {
-            let order_id = self.next_order_id;
-            self.next_order_id += 1;
-            order_ids.push(order_id);
-            self.stats.orders_processed += 1;
-
-            let order = Order::new(
-                order_id, side, order_type, price, quantity, timestamp, symbol,
-            );
-            batch.add_order(order);
-        }
+    fn get_order(&self, order_id: u64) -> PyResult<Option<PyOrder>> {
+        Ok(self.order_book.get_order(order_id).map(PyOrder::from))
+    }
 
-        // Process orders in optimized batches
-        self.process_batch(batch);
+    fn queue_position(&self, order_id: u64) -> PyResult<Option<(usize, f64)>> {
+        Ok(self
+            .order_book
+            .queue_position(order_id)
+            .map(|position| (position.orders_ahead, position.quantity_ahead)))
+    }
 
-        order_ids
+    #[pyo3(signature = (policy, top_order_share = 0.0))]
+    fn set_matching_policy(
+        &mut self,
+        policy: PyMatchingPolicy,
+        top_order_share: f64,
+    ) -> PyResult<()> {
+        self.order_book
+            .set_matching_policy(matching_policy(policy, top_order_share));
+        Ok(())
     }
 
-    fn process_batch(&mut self, mut batch: OrderBatch) {
-        // Sort orders within each category for optimal processing
-        batch.sort();
+    fn set_hidden_priority(&mut self, priority: PyHiddenPriority) -> PyResult<()> {
+        self.order_book.set_hidden_priority(priority.into());
+        Ok(())
+    }
 
-        // Process market orders first
-        for order in batch.buy_market_orders {
-            self.process_market_order(order);
-        }
+    fn set_priority_share(&mut self, share: f64) -> PyResult<()> {
+        self.order_book.set_priority_share(share);
+        Ok(())
+    }
 
-        for order in batch.sell_market_orders {
-            self.process_market_order(order);
-        }
+    #[pyo3(signature = (owner_id = None, share = 0.0))]
+    fn set_lead_market_maker(&mut self, owner_id: Option<u64>, share: f64) -> PyResult<()> {
+        self.order_book
+            .set_lead_market_maker(owner_id.map(|owner_id| LeadMarketMaker { owner_id, share }));
+        Ok(())
+    }
 
-        // Then process limit orders
-        for mut order in batch.buy_limit_orders {
-            self.match_limit_order(&mut order);
+    fn set_iceberg_refill(&mut self, refill: PyIcebergRefill) -> PyResult<()> {
+        self.order_book.set_iceberg_refill(refill.into());
+        Ok(())
+    }
 
-            // Add to order book if not completely filled
-            if order.remaining_quantity > 0.0 {
-                let price = order.price.unwrap();
-                let price_bits = Self::price_to_bits(price, true);
+    #[pyo3(signature = (lot_size, min_quantity, cancel_dust = false, max_quantity = 0.0))]
+    fn set_quantity_rules(
+        &mut self,
+        lot_size: f64,
+        min_quantity: f64,
+        cancel_dust: bool,
+        max_quantity: f64,
+    ) -> PyResult<()> {
+        self.order_book.set_quantity_rules(QuantityRules {
+            lot_size,
+            min_quantity,
+            max_quantity,
+            cancel_dust,
+        });
+        Ok(())
+    }
 
-                let level = self
-                    .get_or_create_price_level(true, price_bits, true)
-                    .unwrap();
-                level.add_order(order.clone());
+    fn set_fee_schedule(&mut self, maker_bps: f64, taker_bps: f64) -> PyResult<()> {
+        self.order_book.set_fee_schedule(FeeSchedule {
+            maker_bps,
+            taker_bps,
+        });
+        Ok(())
+    }
 
-                self.orders_by_id
-                    .insert(order.id, (OrderSide::Buy, price_bits));
-            }
-        }
+    #[pyo3(signature = (quote_currency = None, contract_multiplier = 1.0, asset_class = None))]
+    fn set_instrument(
+        &mut self,
+        quote_currency: Option<String>,
+        contract_multiplier: f64,
+        asset_class: Option<PyAssetClass>,
+    ) -> PyResult<()> {
+        self.order_book.set_instrument(instrument_metadata(
+            quote_currency,
+            contract_multiplier,
+            asset_class,
+        )?);
+        Ok(())
+    }
 
-        for mut order in batch.sell_limit_orders {
-            self.match_limit_order(&mut order);
+    fn set_quantity_decimals(&mut self, decimals: Option<u32>) -> PyResult<()> {
+        self.order_book.set_quantity_decimals(decimals);
+        Ok(())
+    }
 
-            // Add to order book if not completely filled
-            if order.remaining_quantity > 0.0 {
-                let price = order.price.unwrap();
-                let price_bits = Self::price_to_bits(price, false);
+    fn set_tick_size(&mut self, tick_size: f64) -> PyResult<()> {
+        self.order_book.set_tick_size(tick_size);
+        Ok(())
+    }
 
-                let level = self
-                    .get_or_create_price_level(false, price_bits, true)
-                    .unwrap();
-                level.add_order(order.clone());
+    #[pyo3(signature = (bands, round_off_tick = false))]
+    fn set_tick_table(&mut self, bands: Vec<(f64, f64)>, round_off_tick: bool) -> PyResult<()> {
+        let off_tick_price = if round_off_tick {
+            OffTickPrice::Round
+        } else {
+            OffTickPrice::Reject
+        };
+        self.order_book
+            .set_tick_table(TickTable::new(bands), off_tick_price);
+        Ok(())
+    }
 
-                self.orders_by_id
-                    .insert(order.id, (OrderSide::Sell, price_bits));
-            }
-        }
+    fn set_integer_ticks(&mut self, enabled: bool) -> PyResult<()> {
+        self.order_book.set_integer_ticks(enabled);
+        Ok(())
     }
 
-    fn process_order(&mut self, order: &mut Order) {
-        // Handle market orders first
-        if order.order_type == OrderType::Market {
-            self.process_market_order(order.clone());
-            return;
-        }
+    fn set_finished_order_retention(&mut self, retention: Option<usize>) -> PyResult<()> {
+        self.order_book.set_finished_order_retention(retention);
+        Ok(())
+    }
 
-        // Then handle limit orders
-        let price = order.price.unwrap(); // Safe unwrap since we know it's a limit order
+    #[pyo3(signature = (ticks = None, percent = None))]
+    fn set_market_protection(&mut self, ticks: Option<u32>, percent: Option<f64>) -> PyResult<()> {
+        self.order_book
+            .set_market_protection(protection_band(ticks, percent)?);
+        Ok(())
+    }
 
-        // Try to match the order first
-        self.match_limit_order(order);
+    #[pyo3(signature = (ticks = None, percent = None))]
+    fn set_price_collar(&mut self, ticks: Option<u32>, percent: Option<f64>) -> PyResult<()> {
+        self.order_book
+            .set_price_collar(protection_band(ticks, percent)?);
+        Ok(())
+    }
 
-        // If order is not completely filled, add it to the order book
-        if order.remaining_quantity > 0.0 {
-            match order.side {
-                OrderSide::Buy => {
-                    // For buy orders, use negative price for descending sort
-                    let price_bits = Self::price_to_bits(price, true);
-                    let level = self
-                        .get_or_create_price_level(true, price_bits, true)
-                        .unwrap();
-                    level.add_order(order.clone());
-                    self.orders_by_id
-                        .insert(order.id, (OrderSide::Buy, price_bits));
-                }
-                OrderSide::Sell => {
-                    let price_bits = Self::price_to_bits(price, false);
-                    let level = self
-                        .get_or_create_price_level(false, price_bits, true)
-                        .unwrap();
-                    level.add_order(order.clone());
-                    self.orders_by_id
-                        .insert(order.id, (OrderSide::Sell, price_bits));
-                }
-            }
-        }
+    #[pyo3(signature = (window, auction_duration, ticks = None, percent = None))]
+    fn set_volatility_interruption(
+        &mut self,
+        window: u64,
+        auction_duration: u64,
+        ticks: Option<u32>,
+        percent: Option<f64>,
+    ) -> PyResult<()> {
+        let interruption = protection_band(ticks, percent)?.map(|band| VolatilityInterruption {
+            band,
+            window,
+            auction_duration,
+        });
+        self.order_book.set_volatility_interruption(interruption);
+        Ok(())
     }
 
-    fn process_market_order(&mut self, mut order: Order) {
-        match order.side {
-            OrderSide::Buy => {
-                // Collect keys of sell levels to process
-                let mut sell_level_keys: Vec<i64> = Vec::new();
-                for (&price_bits, _level) in &self.sell_price_levels {
-                    sell_level_keys.push(price_bits);
-                    // Optimization: If market order is already filled, no need to check further levels
-                    if order.remaining_quantity <= 0.0 {
-                        break;
-                    }
-                }
+    #[pyo3(signature = (
+        static_reference = None,
+        static_ticks = None,
+        static_percent = None,
+        dynamic_ticks = None,
+        dynamic_percent = None,
+        halt_on_breach = false
+    ))]
+    fn set_price_bands(
+        &mut self,
+        static_reference: Option<f64>,
+        static_ticks: Option<u32>,
+        static_percent: Option<f64>,
+        dynamic_ticks: Option<u32>,
+        dynamic_percent: Option<f64>,
+        halt_on_breach: bool,
+    ) -> PyResult<()> {
+        let bands = price_bands(
+            static_reference,
+            static_ticks,
+            static_percent,
+            dynamic_ticks,
+            dynamic_percent,
+            halt_on_breach,
+        )?;
+        self.order_book.set_price_bands(bands);
+        Ok(())
+    }
 
-                let mut levels_to_remove = Vec::new();
+    fn set_short_sale_rule(&mut self, rule: PyShortSaleRule) -> PyResult<()> {
+        self.order_book.set_short_sale_rule(rule.into());
+        Ok(())
+    }
 
-                // Process each level by key
-                for price_bits in sell_level_keys {
-                    if order.remaining_quantity <= 0.0 {
-                        break; // Stop if the order is filled
-                    }
+    fn last_trade_price(&self) -> PyResult<Option<f64>> {
+        Ok(self.order_book.last_trade_price())
+    }
 
-                    // Perform the matching - extract orders to be processed from the level
-                    if let Some(level) = self.sell_price_levels.get_mut(&price_bits) {
-                        // Extract orders to process
-                        let orders_to_process = std::mem::take(&mut level.orders);
-                        let price = level.price;
-
-                        // Match orders
-                        let mut orders_to_keep = Vec::new();
-                        for mut sell_order in orders_to_process {
-                            if order.remaining_quantity <= 0.0 {
-                                // No more quantity to fill, keep the remaining orders
-                                orders_to_keep.push(sell_order);
-                                continue;
-                            }
-
-                            // Calculate trade quantity
-                            let trade_qty =
-                                order.remaining_quantity.min(sell_order.remaining_quantity);
-
-                            if trade_qty > 0.0 {
-                                // Record the trade - INLINE execution logic
-                                // Update filled quantities
-                                order.filled_quantity += trade_qty;
-                                order.remaining_quantity -= trade_qty;
-                                sell_order.filled_quantity += trade_qty;
-                                sell_order.remaining_quantity -= trade_qty;
-
-                                // Update order statuses
-                                if order.filled_quantity >= order.quantity {
-                                    order.status = OrderStatus::Filled;
-                                } else if order.filled_quantity > 0.0 {
-                                    order.status = OrderStatus::PartiallyFilled;
-                                }
-
-                                if sell_order.filled_quantity >= sell_order.quantity {
-                                    sell_order.status = OrderStatus::Filled;
-                                } else if sell_order.filled_quantity > 0.0 {
-                                    sell_order.status = OrderStatus::PartiallyFilled;
-                                }
-
-                                // Record the trade
-                                let symbol =
-                                    order.symbol.clone().or_else(|| sell_order.symbol.clone());
-                                let trade = Trade {
-                                    id: self.next_trade_id,
-                                    buy_order_id: order.id,
-                                    sell_order_id: sell_order.id,
-                                    price,
-                                    quantity: trade_qty,
-                                    timestamp: std::cmp::max(order.timestamp, sell_order.timestamp),
-                                    symbol,
-                                };
-                                self.next_trade_id += 1;
-                                self.trades.push(trade);
-                                self.stats.trades_executed += 1;
-
-                                // Keep partially filled orders
-                                if sell_order.status != OrderStatus::Filled {
-                                    orders_to_keep.push(sell_order);
-                                } else {
-                                    // Remove filled orders from the lookup map
-                                    self.orders_by_id.remove(&sell_order.id);
-                                }
-                            } else {
-                                orders_to_keep.push(sell_order);
-                            }
-                        }
+    fn reference_price(&self) -> PyResult<Option<f64>> {
+        Ok(self.order_book.reference_price())
+    }
 
-                        // Update the level with remaining orders
-                        level.orders = orders_to_keep;
-                        level.is_dirty = true;
+    fn accrued_fees(&self) -> PyResult<(f64, f64)> {
+        Ok(self.order_book.accrued_fees())
+    }
 
-                        // Check if level became empty after matching
-                        if level.is_empty() {
-                            levels_to_remove.push(price_bits);
-                        }
-                    }
-                }
+    fn get_position(&self, owner_id: u64) -> PyResult<f64> {
+        Ok(self.order_book.position(owner_id))
+    }
 
-                // Remove empty levels after processing
-                for key in levels_to_remove {
-                    self.sell_price_levels.remove(&key);
-                }
-            }
-            OrderSide::Sell => {
-                // Collect keys of buy levels to process
-                let mut buy_level_keys: Vec<i64> = Vec::new();
-                for (&price_bits, _level) in &self.buy_price_levels {
-                    buy_level_keys.push(price_bits);
-                    if order.remaining_quantity <= 0.0 {
-                        break;
-                    }
-                }
+    #[pyo3(signature = (owner_id, group = None))]
+    fn set_account_group(&mut self, owner_id: u64, group: Option<u64>) -> PyResult<()> {
+        self.order_book.set_account_group(owner_id, group);
+        Ok(())
+    }
 
-                let mut levels_to_remove = Vec::new();
+    #[pyo3(signature = (reset_ids = false, keep_trades = false, keep_statistics = false))]
+    fn clear(&mut self, reset_ids: bool, keep_trades: bool, keep_statistics: bool) -> PyResult<()> {
+        self.order_book.clear_with(ClearOptions {
+            reset_ids,
+            keep_trades,
+            keep_statistics,
+        });
+        Ok(())
+    }
 
-                // Process each level by key
-                for price_bits in buy_level_keys {
-                    if order.remaining_quantity <= 0.0 {
-                        break; // Stop if the order is filled
-                    }
+    fn reset_ids(&mut self) -> PyResult<()> {
+        self.order_book.reset_ids();
+        Ok(())
+    }
 
-                    // Perform the matching - extract orders to be processed from the level
-                    if let Some(level) = self.buy_price_levels.get_mut(&price_bits) {
-                        // Extract orders to process
-                        let orders_to_process = std::mem::take(&mut level.orders);
-                        let price = level.price;
-
-                        // Match orders
-                        let mut orders_to_keep = Vec::new();
-                        for mut buy_order in orders_to_process {
-                            if order.remaining_quantity <= 0.0 {
-                                // No more quantity to fill, keep the remaining orders
-                                orders_to_keep.push(buy_order);
-                                continue;
-                            }
-
-                            // Calculate trade quantity
-                            let trade_qty =
-                                buy_order.remaining_quantity.min(order.remaining_quantity);
-
-                            if trade_qty > 0.0 {
-                                // Record the trade - INLINE execution logic
-                                // Update filled quantities
-                                buy_order.filled_quantity += trade_qty;
-                                buy_order.remaining_quantity -= trade_qty;
-                                order.filled_quantity += trade_qty;
-                                order.remaining_quantity -= trade_qty;
-
-                                // Update order statuses
-                                if buy_order.filled_quantity >= buy_order.quantity {
-                                    buy_order.status = OrderStatus::Filled;
-                                } else if buy_order.filled_quantity > 0.0 {
-                                    buy_order.status = OrderStatus::PartiallyFilled;
-                                }
-
-                                if order.filled_quantity >= order.quantity {
-                                    order.status = OrderStatus::Filled;
-                                } else if order.filled_quantity > 0.0 {
-                                    order.status = OrderStatus::PartiallyFilled;
-                                }
-
-                                // Record the trade
-                                let symbol =
-                                    buy_order.symbol.clone().or_else(|| order.symbol.clone());
-                                let trade = Trade {
-                                    id: self.next_trade_id,
-                                    buy_order_id: buy_order.id,
-                                    sell_order_id: order.id,
-                                    price,
-                                    quantity: trade_qty,
-                                    timestamp: std::cmp::max(buy_order.timestamp, order.timestamp),
-                                    symbol,
-                                };
-                                self.next_trade_id += 1;
-                                self.trades.push(trade);
-                                self.stats.trades_executed += 1;
-
-                                // Keep partially filled orders
-                                if buy_order.status != OrderStatus::Filled {
-                                    orders_to_keep.push(buy_order);
-                                } else {
-                                    // Remove filled orders from the lookup map
-                                    self.orders_by_id.remove(&buy_order.id);
-                                }
-                            } else {
-                                orders_to_keep.push(buy_order);
-                            }
-                        }
+    fn enable_recording(&mut self) -> PyResult<()> {
+        self.order_book.enable_recording();
+        Ok(())
+    }
 
-                        // Update the level with remaining orders
-                        level.orders = orders_to_keep;
-                        level.is_dirty = true;
+    fn enable_audit_trail(&mut self) -> PyResult<()> {
+        self.order_book.enable_audit_trail();
+        Ok(())
+    }
 
-                        // Check if level became empty after matching
-                        if level.is_empty() {
-                            levels_to_remove.push(price_bits);
-                        }
-                    }
-                }
+    fn disable_audit_trail(&mut self) -> PyResult<()> {
+        self.order_book.disable_audit_trail();
+        Ok(())
+    }
 
-                // Remove empty levels after processing
-                for key in levels_to_remove {
-                    self.buy_price_levels.remove(&key);
-                }
-            }
-        }
+    fn audit_trail(&self, order_id: u64) -> PyResult<Vec<PyAuditEntry>> {
+        Ok(self
+            .order_book
+            .audit_trail(order_id)
+            .iter()
+            .map(PyAuditEntry::from)
+            .collect())
+    }
 
-        // Update order status
-        if order.remaining_quantity <= 0.0 {
-            order.status = OrderStatus::Filled;
-        } else if order.filled_quantity > 0.0 {
-            order.status = OrderStatus::PartiallyFilled;
-        } else {
-            order.status = OrderStatus::Rejected; // Market orders that can't be filled are rejected
-        }
+    fn recorded_events_json(&self) -> PyResult<String> {
+        serde_json::to_string(self.order_book.recorded_events())
+            .map_err(|e| PyValueError::new_err(e.to_string()))
     }
 
-    fn match_limit_order(&mut self, order: &mut Order) {
-        let price = order.price.unwrap(); // Safe unwrap since we know it's a limit order
+    #[staticmethod]
+    fn replay_json(json: &str) -> PyResult<Self> {
+        let events: Vec<BookEvent> =
+            serde_json::from_str(json).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyOrderBook {
+            order_book: OrderBook::replay(&events),
+        })
+    }
 
-        match order.side {
-            OrderSide::Buy => {
-                // Collect keys of potential matching sell levels
-                let mut sell_level_keys: Vec<i64> = Vec::new();
-                for (&price_bits, _level) in &self.sell_price_levels {
-                    let level_price = Self::bits_to_price(price_bits, false);
-
-                    // Stop if sell price is higher than buy price or order is filled
-                    if level_price > price || order.remaining_quantity <= 0.0 {
-                        break;
-                    }
-                    sell_level_keys.push(price_bits);
-                }
+    fn to_snapshot_json(&self) -> PyResult<String> {
+        serde_json::to_string(&self.order_book.to_snapshot())
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
 
-                let mut levels_to_remove = Vec::new();
+    #[staticmethod]
+    fn from_snapshot_json(json: &str) -> PyResult<Self> {
+        let snapshot: BookSnapshot =
+            serde_json::from_str(json).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyOrderBook {
+            order_book: OrderBook::from_snapshot(snapshot),
+        })
+    }
+}
 
-                // Process each potential matching level by key
-                for price_bits in sell_level_keys {
-                    if order.remaining_quantity <= 0.0 {
-                        break; // Stop if the order is filled
-                    }
+/// Python exchange symbol configuration class
+#[pyclass]
+#[derive(Clone)]
+struct PySymbolConfig {
+    config: SymbolConfig,
+}
 
-                    // Perform the matching - extract orders to be processed from the level
-                    if let Some(level) = self.sell_price_levels.get_mut(&price_bits) {
-                        // Extract orders to process
-                        let orders_to_process = std::mem::take(&mut level.orders);
-                        let price = level.price;
-
-                        // Match orders
-                        let mut orders_to_keep = Vec::new();
-                        for mut sell_order in orders_to_process {
-                            if order.remaining_quantity <= 0.0 {
-                                // No more quantity to fill, keep the remaining orders
-                                orders_to_keep.push(sell_order);
-                                continue;
-                            }
-
-                            // Calculate trade quantity
-                            let trade_qty =
-                                order.remaining_quantity.min(sell_order.remaining_quantity);
-
-                            if trade_qty > 0.0 {
-                                // Record the trade - INLINE execution logic
-                                // Update filled quantities
-                                order.filled_quantity += trade_qty;
-                                order.remaining_quantity -= trade_qty;
-                                sell_order.filled_quantity += trade_qty;
-                                sell_order.remaining_quantity -= trade_qty;
-
-                                // Update order statuses
-                                if order.filled_quantity >= order.quantity {
-                                    order.status = OrderStatus::Filled;
-                                } else if order.filled_quantity > 0.0 {
-                                    order.status = OrderStatus::PartiallyFilled;
-                                }
-
-                                if sell_order.filled_quantity >= sell_order.quantity {
-                                    sell_order.status = OrderStatus::Filled;
-                                } else if sell_order.filled_quantity > 0.0 {
-                                    sell_order.status = OrderStatus::PartiallyFilled;
-                                }
-
-                                // Record the trade
-                                let symbol =
-                                    order.symbol.clone().or_else(|| sell_order.symbol.clone());
-                                let trade = Trade {
-                                    id: self.next_trade_id,
-                                    buy_order_id: order.id,
-                                    sell_order_id: sell_order.id,
-                                    price,
-                                    quantity: trade_qty,
-                                    timestamp: std::cmp::max(order.timestamp, sell_order.timestamp),
-                                    symbol,
-                                };
-                                self.next_trade_id += 1;
-                                self.trades.push(trade);
-                                self.stats.trades_executed += 1;
-
-                                // Keep partially filled orders
-                                if sell_order.status != OrderStatus::Filled {
-                                    orders_to_keep.push(sell_order);
-                                } else {
-                                    // Remove filled orders from the lookup map
-                                    self.orders_by_id.remove(&sell_order.id);
-                                }
-                            } else {
-                                orders_to_keep.push(sell_order);
-                            }
-                        }
+#[pymethods]
+impl PySymbolConfig {
+    #[new]
+    #[pyo3(signature = (
+        tick_size = 0.0,
+        lot_size = 0.0,
+        min_quantity = 0.0,
+        max_quantity = 0.0,
+        matching_policy = None,
+        top_order_share = 0.0,
+        static_reference = None,
+        static_ticks = None,
+        static_percent = None,
+        dynamic_ticks = None,
+        dynamic_percent = None,
+        halt_on_breach = false,
+        schedule = None,
+        quote_currency = None,
+        contract_multiplier = 1.0,
+        asset_class = None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        tick_size: f64,
+        lot_size: f64,
+        min_quantity: f64,
+        max_quantity: f64,
+        matching_policy: Option<PyMatchingPolicy>,
+        top_order_share: f64,
+        static_reference: Option<f64>,
+        static_ticks: Option<u32>,
+        static_percent: Option<f64>,
+        dynamic_ticks: Option<u32>,
+        dynamic_percent: Option<f64>,
+        halt_on_breach: bool,
+        schedule: Option<Vec<(u64, PyTradingState)>>,
+        quote_currency: Option<String>,
+        contract_multiplier: f64,
+        asset_class: Option<PyAssetClass>,
+    ) -> PyResult<Self> {
+        Ok(PySymbolConfig {
+            config: SymbolConfig {
+                tick_size,
+                quantity_rules: QuantityRules {
+                    lot_size,
+                    min_quantity,
+                    max_quantity,
+                    ..QuantityRules::default()
+                },
+                price_bands: price_bands(
+                    static_reference,
+                    static_ticks,
+                    static_percent,
+                    dynamic_ticks,
+                    dynamic_percent,
+                    halt_on_breach,
+                )?,
+                matching_policy: matching_policy
+                    .map(|policy| self::matching_policy(policy, top_order_share))
+                    .unwrap_or_default(),
+                schedule: schedule
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(timestamp, state)| (timestamp, state.into()))
+                    .collect(),
+                instrument: instrument_metadata(quote_currency, contract_multiplier, asset_class)?,
+            },
+        })
+    }
 
-                        // Update the level with remaining orders
-                        level.orders = orders_to_keep;
-                        level.is_dirty = true;
+    #[getter]
+    fn tick_size(&self) -> f64 {
+        self.config.tick_size
+    }
 
-                        // Check if level became empty after matching
-                        if level.is_empty() {
-                            levels_to_remove.push(price_bits);
-                        }
-                    }
-                }
+    #[getter]
+    fn lot_size(&self) -> f64 {
+        self.config.quantity_rules.lot_size
+    }
 
-                // Remove empty levels after processing
-                for key in levels_to_remove {
-                    self.sell_price_levels.remove(&key);
-                }
-            }
-            OrderSide::Sell => {
-                // Collect keys of potential matching buy levels
-                let mut buy_level_keys: Vec<i64> = Vec::new();
-                for (&price_bits, _level) in &self.buy_price_levels {
-                    let level_price = Self::bits_to_price(price_bits, true);
+    #[getter]
+    fn min_quantity(&self) -> f64 {
+        self.config.quantity_rules.min_quantity
+    }
 
-                    // Stop if buy price is lower than sell price or order is filled
-                    if level_price < price || order.remaining_quantity <= 0.0 {
-                        break;
-                    }
-                    buy_level_keys.push(price_bits);
-                }
+    #[getter]
+    fn max_quantity(&self) -> f64 {
+        self.config.quantity_rules.max_quantity
+    }
 
-                let mut levels_to_remove = Vec::new();
+    #[getter]
+    fn quote_currency(&self) -> Option<String> {
+        self.config.instrument.quote_currency.clone()
+    }
 
-                // Process each potential matching level by key
-                for price_bits in buy_level_keys {
-                    if order.remaining_quantity <= 0.0 {
-                        break; // Stop if the order is filled
-                    }
+    #[getter]
+    fn contract_multiplier(&self) -> f64 {
+        self.config.instrument.contract_multiplier
+    }
 
-                    // Perform the matching - extract orders to be processed from the level
-                    if let Some(level) = self.buy_price_levels.get_mut(&price_bits) {
-                        // Extract orders to process
-                        let orders_to_process = std::mem::take(&mut level.orders);
-                        let price = level.price;
-
-                        // Match orders
-                        let mut orders_to_keep = Vec::new();
-                        for mut buy_order in orders_to_process {
-                            if order.remaining_quantity <= 0.0 {
-                                // No more quantity to fill, keep the remaining orders
-                                orders_to_keep.push(buy_order);
-                                continue;
-                            }
-
-                            // Calculate trade quantity
-                            let trade_qty =
-                                buy_order.remaining_quantity.min(order.remaining_quantity);
-
-                            if trade_qty > 0.0 {
-                                // Record the trade - INLINE execution logic
-                                // Update filled quantities
-                                buy_order.filled_quantity += trade_qty;
-                                buy_order.remaining_quantity -= trade_qty;
-                                order.filled_quantity += trade_qty;
-                                order.remaining_quantity -= trade_qty;
-
-                                // Update order statuses
-                                if buy_order.filled_quantity >= buy_order.quantity {
-                                    buy_order.status = OrderStatus::Filled;
-                                } else if buy_order.filled_quantity > 0.0 {
-                                    buy_order.status = OrderStatus::PartiallyFilled;
-                                }
-
-                                if order.filled_quantity >= order.quantity {
-                                    order.status = OrderStatus::Filled;
-                                } else if order.filled_quantity > 0.0 {
-                                    order.status = OrderStatus::PartiallyFilled;
-                                }
-
-                                // Record the trade
-                                let symbol =
-                                    buy_order.symbol.clone().or_else(|| order.symbol.clone());
-                                let trade = Trade {
-                                    id: self.next_trade_id,
-                                    buy_order_id: buy_order.id,
-                                    sell_order_id: order.id,
-                                    price,
-                                    quantity: trade_qty,
-                                    timestamp: std::cmp::max(buy_order.timestamp, order.timestamp),
-                                    symbol,
-                                };
-                                self.next_trade_id += 1;
-                                self.trades.push(trade);
-                                self.stats.trades_executed += 1;
-
-                                // Keep partially filled orders
-                                if buy_order.status != OrderStatus::Filled {
-                                    orders_to_keep.push(buy_order);
-                                } else {
-                                    // Remove filled orders from the lookup map
-                                    self.orders_by_id.remove(&buy_order.id);
-                                }
-                            } else {
-                                orders_to_keep.push(buy_order);
-                            }
-                        }
+    #[getter]
+    fn asset_class(&self) -> PyAssetClass {
+        self.config.instrument.asset_class.into()
+    }
 
-                        // Update the level with remaining orders
-                        level.orders = orders_to_keep;
-                        level.is_dirty = true;
+    #[getter]
+    fn schedule(&self) -> Vec<(u64, PyTradingState)> {
+        self.config
+            .schedule
+            .iter()
+            .map(|&(timestamp, state)| (timestamp, state.into()))
+            .collect()
+    }
+}
 
-                        // Check if level became empty after matching
-                        if level.is_empty() {
-                            levels_to_remove.push(price_bits);
-                        }
-                    }
-                }
+/// Python multi-symbol exchange class
+#[pyclass]
+struct PyExchange {
+    exchange: Exchange,
+}
 
-                // Remove empty levels after processing
-                for key in levels_to_remove {
-                    self.buy_price_levels.remove(&key);
-                }
-            }
+#[pymethods]
+impl PyExchange {
+    #[new]
+    fn new() -> Self {
+        PyExchange {
+            exchange: Exchange::new(),
         }
+    }
 
-        // Update order status
-        if order.remaining_quantity <= 0.0 {
-            order.status = OrderStatus::Filled;
-        } else if order.filled_quantity > 0.0 {
-            order.status = OrderStatus::PartiallyFilled;
-        }
+    fn add_symbol(&mut self, symbol: &str) -> PyResult<()> {
+        Ok(self.exchange.add_symbol(symbol)?)
     }
 
-    pub fn cancel_order(&mut self, order_id: u64) -> bool {
-        if let Some((side, price_bits)) = self.orders_by_id.remove(&order_id) {
-            let price_levels = match side {
-                OrderSide::Buy => &mut self.buy_price_levels,
-                OrderSide::Sell => &mut self.sell_price_levels,
-            };
+    #[pyo3(signature = (symbol, config = None))]
+    fn register_symbol(&mut self, symbol: &str, config: Option<PySymbolConfig>) -> PyResult<()> {
+        let config = config.map(|config| config.config).unwrap_or_default();
+        Ok(self.exchange.register_symbol(symbol, config)?)
+    }
+
+    fn update_symbol(&mut self, symbol: &str, config: PySymbolConfig) -> PyResult<()> {
+        Ok(self.exchange.update_symbol(symbol, config.config)?)
+    }
+
+    fn symbol_config(&self, symbol: &str) -> PyResult<Option<PySymbolConfig>> {
+        Ok(self
+            .exchange
+            .registry()
+            .config(symbol)
+            .map(|config| PySymbolConfig {
+                config: config.clone(),
+            }))
+    }
+
+    fn symbols(&self) -> PyResult<Vec<String>> {
+        Ok(self.exchange.symbols().map(str::to_string).collect())
+    }
+
+    fn advance_time(&mut self, timestamp: u64) -> PyResult<()> {
+        self.exchange.advance_time(timestamp);
+        Ok(())
+    }
+
+    #[pyo3(signature = (symbol, cancel_resting = false))]
+    fn suspend_symbol(&mut self, symbol: &str, cancel_resting: bool) -> PyResult<Vec<u64>> {
+        Ok(self.exchange.suspend_symbol(symbol, cancel_resting)?)
+    }
 
-            if let Some(level) = price_levels.get_mut(&price_bits) {
-                if let Some(_order) = level.remove_order(order_id) {
-                    // Handle empty price level
-                    if level.is_empty() {
-                        price_levels.remove(&price_bits);
-                    }
-                    return true;
-                }
-            }
-        }
-        false
+    fn resume_symbol(&mut self, symbol: &str) -> PyResult<()> {
+        Ok(self.exchange.resume_symbol(symbol)?)
     }
 
-    pub fn get_order_book_snapshot(&mut self) -> (Vec<(f64, f64)>, Vec<(f64, f64)>) {
-        // Get buy side: price level and total quantity
-        let mut buy_snapshot = Vec::with_capacity(self.buy_price_levels.len());
-        for (&price_bits, level) in &mut self.buy_price_levels {
-            // Use mutable ref to update cache
-            let price = Self::bits_to_price(price_bits, true);
-            buy_snapshot.push((price, level.total_quantity())); // Use cached quantity
-        }
+    fn is_suspended(&self, symbol: &str) -> PyResult<bool> {
+        Ok(self.exchange.registry().is_suspended(symbol))
+    }
 
-        // Get sell side: price level and total quantity
-        let mut sell_snapshot = Vec::with_capacity(self.sell_price_levels.len());
-        for (&price_bits, level) in &mut self.sell_price_levels {
-            // Use mutable ref to update cache
-            let price = Self::bits_to_price(price_bits, false);
-            sell_snapshot.push((price, level.total_quantity())); // Use cached quantity
-        }
+    fn delist_symbol(&mut self, symbol: &str) -> PyResult<()> {
+        Ok(self.exchange.delist_symbol(symbol)?)
+    }
 
-        // Sort by price (unnecessary but consistent with original)
-        buy_snapshot.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
-        sell_snapshot.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+    fn get_archived_trades(&self, symbol: &str) -> PyResult<Vec<PyTrade>> {
+        Ok(self
+            .exchange
+            .archived_trades(symbol)
+            .iter()
+            .map(PyTrade::from)
+            .collect())
+    }
 
-        (buy_snapshot, sell_snapshot)
+    #[pyo3(signature = (symbol, side, price, quantity, timestamp, owner_id = None, time_in_force = None))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_limit_order(
+        &mut self,
+        symbol: &str,
+        side: PyOrderSide,
+        price: f64,
+        quantity: f64,
+        timestamp: u64,
+        owner_id: Option<u64>,
+        time_in_force: Option<PyTimeInForce>,
+    ) -> PyResult<u64> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
+        };
+        let time_in_force = match time_in_force {
+            None | Some(PyTimeInForce::Gtc) => TimeInForce::Gtc,
+            Some(PyTimeInForce::Ioc) => TimeInForce::Ioc,
+            Some(PyTimeInForce::Fok) => TimeInForce::Fok,
+            Some(PyTimeInForce::Day) => TimeInForce::Day,
+            Some(PyTimeInForce::Gtd) => {
+                return Err(PyValueError::new_err("GTD orders require expire_at"))
+            }
+        };
+        Ok(self.exchange.add_order(
+            symbol,
+            side,
+            OrderType::Limit,
+            Some(price),
+            quantity,
+            timestamp,
+            OrderOptions {
+                owner_id,
+                time_in_force,
+                ..OrderOptions::default()
+            },
+        )?)
     }
 
-    fn get_trades(&self, limit: Option<usize>) -> PyResult<Vec<PyTrade>> {
-        let trades = if let Some(l) = limit {
-            // Take the last 'l' trades
-            let start_index = self.trades.len().saturating_sub(l);
-            &self.trades[start_index..]
-        } else {
-            // Return all trades (slice of the whole vector)
-            &self.trades[..]
+    #[pyo3(signature = (symbol, side, quantity, timestamp, owner_id = None))]
+    fn add_market_order(
+        &mut self,
+        symbol: &str,
+        side: PyOrderSide,
+        quantity: f64,
+        timestamp: u64,
+        owner_id: Option<u64>,
+    ) -> PyResult<u64> {
+        let side = match side {
+            PyOrderSide::Buy => OrderSide::Buy,
+            PyOrderSide::Sell => OrderSide::Sell,
         };
+        Ok(self.exchange.add_order(
+            symbol,
+            side,
+            OrderType::Market,
+            None,
+            quantity,
+            timestamp,
+            OrderOptions {
+                owner_id,
+                ..OrderOptions::default()
+            },
+        )?)
+    }
 
-        let py_trades = trades
-            .iter() // Iterate over the slice, not cloning the Vec
-            .map(|t| PyTrade {
-                id: t.id,
-                buy_order_id: t.buy_order_id,
-                sell_order_id: t.sell_order_id,
-                price: t.price,
-                quantity: t.quantity,
-                timestamp: t.timestamp,
-                symbol: t.symbol.clone(), // Clone symbol String if needed
+    fn batch_add_orders(
+        &mut self,
+        orders: Vec<(String, PyOrderSide, PyOrderType, Option<f64>, f64, u64)>,
+    ) -> PyResult<Vec<u64>> {
+        let orders = orders
+            .into_iter()
+            .map(|(symbol, side, order_type, price, quantity, timestamp)| {
+                let side = match side {
+                    PyOrderSide::Buy => OrderSide::Buy,
+                    PyOrderSide::Sell => OrderSide::Sell,
+                };
+                let order_type = match order_type {
+                    PyOrderType::Market => OrderType::Market,
+                    PyOrderType::Limit => OrderType::Limit,
+                };
+                (
+                    (side, order_type, price, quantity, timestamp, Some(symbol)),
+                    TimeInForce::Gtc,
+                )
             })
             .collect();
+        Ok(self.exchange.batch_add_orders(orders)?)
+    }
 
-        Ok(py_trades)
+    fn set_parallel_batches(&mut self, enabled: bool) -> PyResult<()> {
+        self.exchange.set_parallel_batches(enabled);
+        Ok(())
     }
 
-    pub fn get_statistics(&self) -> OrderBookStats {
-        self.stats.clone()
+    fn cancel_order(&mut self, symbol: &str, order_id: u64) -> PyResult<bool> {
+        Ok(self.exchange.cancel_order(symbol, order_id))
     }
-}
 
-impl Clone for OrderBook {
-    fn clone(&self) -> Self {
-        OrderBook {
-            buy_price_levels: self.buy_price_levels.clone(),
-            sell_price_levels: self.sell_price_levels.clone(),
-            orders_by_id: self.orders_by_id.clone(),
-            next_order_id: self.next_order_id,
-            next_trade_id: self.next_trade_id,
-            trades: self.trades.clone(),
-            stats: self.stats.clone(),
+    fn get_order(&self, symbol: &str, order_id: u64) -> PyResult<Option<PyOrder>> {
+        Ok(self.exchange.get_order(symbol, order_id).map(PyOrder::from))
+    }
+
+    #[pyo3(signature = (symbol = None, limit = None))]
+    fn get_trades(&self, symbol: Option<&str>, limit: Option<usize>) -> PyResult<Vec<PyTrade>> {
+        match symbol {
+            Some(symbol) => self.listed_book(symbol)?.get_trades(limit),
+            None => {
+                let trades = self.exchange.trades();
+                let start = limit.map_or(0, |limit| trades.len().saturating_sub(limit));
+                Ok(trades[start..]
+                    .iter()
+                    .map(|&trade| PyTrade::from(trade))
+                    .collect())
+            }
         }
     }
-}
 
-/// Python order class
-#[pyclass]
-#[derive(Clone)]
-struct PyOrder {
-    #[pyo3(get)]
-    id: u64,
-    #[pyo3(get)]
-    side: PyOrderSide,
-    #[pyo3(get)]
-    order_type: PyOrderType,
-    #[pyo3(get)]
-    price: Option<f64>,
-    #[pyo3(get)]
-    quantity: f64,
-    #[pyo3(get)]
-    filled_quantity: f64,
-    #[pyo3(get)]
-    status: PyOrderStatus,
-    #[pyo3(get)]
-    timestamp: u64,
-    #[pyo3(get)]
-    symbol: Option<String>,
+    fn get_order_book_snapshot(&mut self, symbol: &str) -> PyResult<DepthSnapshot> {
+        Ok(self
+            .exchange
+            .listed_book_mut(symbol)?
+            .get_order_book_snapshot())
+    }
+
+    fn get_statistics(&self, symbol: &str) -> PyResult<PyOrderBookStats> {
+        Ok(self.listed_book(symbol)?.get_statistics().into())
+    }
 }
 
-/// Python trade class
-#[pyclass]
-#[derive(Clone)]
-struct PyTrade {
-    #[pyo3(get)]
-    id: u64,
-    #[pyo3(get)]
-    buy_order_id: u64,
-    #[pyo3(get)]
-    sell_order_id: u64,
-    #[pyo3(get)]
-    price: f64,
-    #[pyo3(get)]
-    quantity: f64,
-    #[pyo3(get)]
-    timestamp: u64,
-    #[pyo3(get)]
-    symbol: Option<String>,
+impl PyExchange {
+    fn listed_book(&self, symbol: &str) -> Result<&OrderBook, ExchangeError> {
+        self.exchange
+            .book(symbol)
+            .ok_or_else(|| self.exchange.unlisted(symbol))
+    }
 }
 
-/// Python order book class
+/// Python calendar spread market class
 #[pyclass]
-struct PyOrderBook {
-    order_book: OrderBook,
+struct PySpreadMarket {
+    market: SpreadMarket,
 }
 
 #[pymethods]
-impl PyOrderBook {
+impl PySpreadMarket {
     #[new]
     fn new() -> Self {
-        PyOrderBook {
-            order_book: OrderBook::new(),
+        PySpreadMarket {
+            market: SpreadMarket::new(),
         }
     }
 
-    #[pyo3(signature = (side, price, quantity, timestamp))]
+    #[pyo3(signature = (leg, side, price, quantity, timestamp, owner_id = None))]
     fn add_limit_order(
         &mut self,
+        leg: PySpreadLeg,
         side: PyOrderSide,
         price: f64,
         quantity: f64,
         timestamp: u64,
+        owner_id: Option<u64>,
     ) -> PyResult<u64> {
         let side = match side {
             PyOrderSide::Buy => OrderSide::Buy,
             PyOrderSide::Sell => OrderSide::Sell,
         };
-
-        Ok(self.order_book.add_order(
+        Ok(self.market.add_order(
+            leg.into(),
             side,
             OrderType::Limit,
             Some(price),
             quantity,
             timestamp,
-            None,
-        ))
+            OrderOptions {
+                owner_id,
+                ..OrderOptions::default()
+            },
+        )?)
     }
 
-    #[pyo3(signature = (side, quantity, timestamp))]
+    #[pyo3(signature = (leg, side, quantity, timestamp, owner_id = None))]
     fn add_market_order(
         &mut self,
+        leg: PySpreadLeg,
         side: PyOrderSide,
         quantity: f64,
         timestamp: u64,
+        owner_id: Option<u64>,
     ) -> PyResult<u64> {
         let side = match side {
             PyOrderSide::Buy => OrderSide::Buy,
             PyOrderSide::Sell => OrderSide::Sell,
         };
+        Ok(self.market.add_order(
+            leg.into(),
+            side,
+            OrderType::Market,
+            None,
+            quantity,
+            timestamp,
+            OrderOptions {
+                owner_id,
+                ..OrderOptions::default()
+            },
+        )?)
+    }
 
+    fn cancel_order(&mut self, leg: PySpreadLeg, order_id: u64) -> PyResult<bool> {
+        Ok(self.market.cancel_order(leg.into(), order_id))
+    }
+
+    fn get_order(&self, leg: PySpreadLeg, order_id: u64) -> PyResult<Option<PyOrder>> {
         Ok(self
-            .order_book
-            .add_order(side, OrderType::Market, None, quantity, timestamp, None))
+            .market
+            .book(leg.into())
+            .get_order(order_id)
+            .map(PyOrder::from))
     }
 
-    fn cancel_order(&mut self, order_id: u64) -> PyResult<bool> {
-        Ok(self.order_book.cancel_order(order_id))
+    #[pyo3(signature = (leg, limit = None))]
+    fn get_trades(&self, leg: PySpreadLeg, limit: Option<usize>) -> PyResult<Vec<PyTrade>> {
+        self.market.book(leg.into()).get_trades(limit)
     }
 
-    fn get_order_book_snapshot(&mut self) -> PyResult<(Vec<(f64, f64)>, Vec<(f64, f64)>)> {
-        Ok(self.order_book.get_order_book_snapshot())
+    fn get_order_book_snapshot(&mut self, leg: PySpreadLeg) -> PyResult<DepthSnapshot> {
+        Ok(self.market.book_mut(leg.into()).get_order_book_snapshot())
     }
 
-    #[pyo3(signature = (limit = None))]
-    fn get_trades(&self, limit: Option<usize>) -> PyResult<Vec<PyTrade>> {
-        self.order_book.get_trades(limit)
+    fn implied_orders(&self) -> PyResult<Vec<(PySpreadLeg, PyOrderSide, f64, f64)>> {
+        Ok(self
+            .market
+            .implied_orders()
+            .into_iter()
+            .map(|(leg, side, price, quantity)| (leg.into(), side.into(), price, quantity))
+            .collect())
+    }
+
+    fn get_position(&self, leg: PySpreadLeg, owner_id: u64) -> PyResult<f64> {
+        Ok(self.market.book(leg.into()).position(owner_id))
     }
 }
 
@@ -1101,9 +9584,644 @@ fn matching_engine(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyOrderType>()?;
     m.add_class::<PyOrderSide>()?;
     m.add_class::<PyOrderStatus>()?;
+    m.add_class::<PyMatchingPolicy>()?;
+    m.add_class::<PyHiddenPriority>()?;
+    m.add_class::<PyIcebergRefill>()?;
+    m.add_class::<PyShortSaleRule>()?;
+    m.add_class::<PyRejectReason>()?;
+    m.add_class::<PyAuditAction>()?;
+    m.add_class::<PyAuctionSession>()?;
+    m.add_class::<PyTradingState>()?;
+    m.add_class::<PyLiquidity>()?;
+    m.add_class::<PyAssetClass>()?;
+    m.add_class::<PyTimeInForce>()?;
+    m.add_class::<PyStopTrigger>()?;
+    m.add_class::<PyPeg>()?;
+    m.add_class::<PyBookCondition>()?;
     m.add_class::<PyOrder>()?;
     m.add_class::<PyTrade>()?;
+    m.add_class::<PyOrderBookStats>()?;
+    m.add_class::<PySessionStats>()?;
+    m.add_class::<PyAuditEntry>()?;
     m.add_class::<PyOrderBook>()?;
+    m.add_class::<PySpreadLeg>()?;
+    m.add_class::<PySpreadMarket>()?;
+    m.add_class::<PySymbolConfig>()?;
+    m.add_class::<PyExchange>()?;
+    m.add("TRADE_FLAG_AUCTION", TradeFlags::AUCTION.bits())?;
+    m.add("TRADE_FLAG_DISCRETION", TradeFlags::DISCRETION.bits())?;
+    m.add("TRADE_FLAG_WASH_TRADE", TradeFlags::WASH_TRADE.bits())?;
+    m.add("TRADE_FLAG_IMPLIED", TradeFlags::IMPLIED.bits())?;
+    m.add("TRADE_FLAG_BUSTED", TradeFlags::BUSTED.bits())?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_limit(book: &mut OrderBook, side: OrderSide, price: f64, quantity: f64, ts: u64) -> u64 {
+        book.add_order(
+            side,
+            OrderType::Limit,
+            Some(price),
+            quantity,
+            ts,
+            None,
+            TimeInForce::Gtc,
+        )
+        .unwrap()
+    }
+
+    // (buy order id, sell order id, price, quantity) of every trade, in execution order
+    fn fills(book: &OrderBook) -> Vec<(u64, u64, f64, f64)> {
+        book.trades
+            .iter()
+            .map(|t| (t.buy_order_id, t.sell_order_id, t.price, t.quantity))
+            .collect()
+    }
+
+    fn resting_ids(orders: &[OrderView]) -> Vec<u64> {
+        orders.iter().map(|order| order.id).collect()
+    }
+
+    #[test]
+    fn snapshot_round_trip_matches_identically() {
+        let mut book = OrderBook::new();
+        book.enable_audit_trail();
+        add_limit(&mut book, OrderSide::Sell, 101.0, 5.0, 1);
+        add_limit(&mut book, OrderSide::Sell, 101.0, 3.0, 2);
+        add_limit(&mut book, OrderSide::Sell, 102.0, 4.0, 3);
+        add_limit(&mut book, OrderSide::Buy, 99.0, 2.0, 4);
+        add_limit(&mut book, OrderSide::Buy, 101.0, 2.0, 5);
+
+        let json = serde_json::to_string(&book.to_snapshot()).unwrap();
+        let mut restored = OrderBook::from_snapshot(serde_json::from_str(&json).unwrap());
+        assert_eq!(restored.l3_snapshot(), book.l3_snapshot());
+        assert_eq!(restored.to_snapshot().clock, 5);
+        assert_eq!(restored.audit_trail(1), book.audit_trail(1));
+
+        book.trades.clear();
+        let original_id = add_limit(&mut book, OrderSide::Buy, 102.0, 8.0, 6);
+        let restored_id = add_limit(&mut restored, OrderSide::Buy, 102.0, 8.0, 6);
+        assert_eq!(restored_id, original_id);
+        assert_eq!(fills(&restored), fills(&book));
+        assert_eq!(restored.trades[0].id, book.trades[0].id);
+        assert_eq!(restored.l3_snapshot(), book.l3_snapshot());
+        assert_eq!(restored.audit_trail(2), book.audit_trail(2));
+    }
+
+    #[test]
+    fn pro_rata_splits_by_resting_size() {
+        let mut book = OrderBook::new();
+        book.set_matching_policy(MatchingPolicy::ProRata);
+        let first = add_limit(&mut book, OrderSide::Sell, 100.0, 60.0, 1);
+        let second = add_limit(&mut book, OrderSide::Sell, 100.0, 40.0, 2);
+
+        let buy = add_limit(&mut book, OrderSide::Buy, 100.0, 50.0, 3);
+        assert_eq!(
+            fills(&book),
+            vec![(buy, first, 100.0, 30.0), (buy, second, 100.0, 20.0)]
+        );
+
+        // A 100-lot against the original 60 and 40 takes them in the same proportion
+        let mut book = OrderBook::new();
+        book.set_matching_policy(MatchingPolicy::ProRata);
+        add_limit(&mut book, OrderSide::Sell, 100.0, 60.0, 1);
+        add_limit(&mut book, OrderSide::Sell, 100.0, 40.0, 2);
+        book.add_order(
+            OrderSide::Buy,
+            OrderType::Market,
+            None,
+            100.0,
+            3,
+            None,
+            TimeInForce::Gtc,
+        )
+        .unwrap();
+        let quantities: Vec<f64> = book.trades.iter().map(|t| t.quantity).collect();
+        assert_eq!(quantities, vec![60.0, 40.0]);
+    }
+
+    #[test]
+    fn post_only_order_that_would_cross_is_rejected() {
+        let mut book = OrderBook::new();
+        add_limit(&mut book, OrderSide::Sell, 100.0, 5.0, 1);
+        let options = OrderOptions {
+            post_only: true,
+            ..OrderOptions::default()
+        };
+        let order_id = book
+            .add_order_with_options(
+                OrderSide::Buy,
+                OrderType::Limit,
+                Some(100.0),
+                5.0,
+                2,
+                None,
+                options.clone(),
+            )
+            .unwrap();
+
+        let order = book.get_order(order_id).unwrap();
+        assert_eq!(order.status, OrderStatus::Rejected);
+        assert_eq!(order.reject_reason, Some(RejectReason::PostOnlyWouldCross));
+        assert!(book.trades.is_empty());
+        assert_eq!(book.l3_snapshot().0, Vec::new());
+
+        // Below the ask it rests as a maker
+        let order_id = book
+            .add_order_with_options(
+                OrderSide::Buy,
+                OrderType::Limit,
+                Some(99.0),
+                5.0,
+                3,
+                None,
+                options,
+            )
+            .unwrap();
+        assert_eq!(book.get_order(order_id).unwrap().status, OrderStatus::New);
+    }
+
+    #[test]
+    fn cancel_all_returns_the_cancelled_ids() {
+        let mut book = OrderBook::new();
+        let bid_99 = add_limit(&mut book, OrderSide::Buy, 99.0, 1.0, 1);
+        let bid_98 = add_limit(&mut book, OrderSide::Buy, 98.0, 1.0, 2);
+        let bid_97 = add_limit(&mut book, OrderSide::Buy, 97.0, 1.0, 3);
+        let ask = add_limit(&mut book, OrderSide::Sell, 101.0, 1.0, 4);
+
+        let mut cancelled = book.cancel_all(Some(OrderSide::Buy), Some((97.5, 99.0)), None);
+        cancelled.sort();
+        assert_eq!(cancelled, vec![bid_99, bid_98]);
+        let (bids, asks) = book.l3_snapshot();
+        assert_eq!(resting_ids(&bids), vec![bid_97]);
+        assert_eq!(resting_ids(&asks), vec![ask]);
+
+        assert_eq!(
+            book.cancel_all(Some(OrderSide::Buy), None, None),
+            vec![bid_97]
+        );
+        assert_eq!(resting_ids(&book.l3_snapshot().1), vec![ask]);
+    }
+
+    #[test]
+    fn ordered_batch_matches_sequential_adds() {
+        let orders: Vec<BatchOrder> = vec![
+            (
+                (OrderSide::Sell, OrderType::Limit, Some(100.0), 5.0, 1, None),
+                TimeInForce::Gtc,
+            ),
+            (
+                (OrderSide::Buy, OrderType::Market, None, 3.0, 2, None),
+                TimeInForce::Gtc,
+            ),
+            (
+                (OrderSide::Buy, OrderType::Limit, Some(100.0), 4.0, 3, None),
+                TimeInForce::Gtc,
+            ),
+            (
+                (OrderSide::Sell, OrderType::Limit, Some(99.0), 2.0, 4, None),
+                TimeInForce::Gtc,
+            ),
+        ];
+
+        let mut sequential = OrderBook::new();
+        for ((side, order_type, price, quantity, ts, symbol), time_in_force) in orders.clone() {
+            sequential
+                .add_order(side, order_type, price, quantity, ts, symbol, time_in_force)
+                .unwrap();
+        }
+
+        let mut ordered = OrderBook::new();
+        ordered.batch_add_orders_ordered(orders.clone()).unwrap();
+        assert_eq!(fills(&ordered), fills(&sequential));
+        assert_eq!(ordered.l3_snapshot(), sequential.l3_snapshot());
+
+        // Grouping market orders ahead of limits runs the market buy into an empty book
+        let mut grouped = OrderBook::new();
+        grouped.batch_add_orders(orders).unwrap();
+        assert_ne!(fills(&grouped), fills(&sequential));
+    }
+
+    #[test]
+    fn gtd_order_expires_at_its_expiry_time() {
+        let mut book = OrderBook::new();
+        let order_id = book
+            .add_order(
+                OrderSide::Buy,
+                OrderType::Limit,
+                Some(100.0),
+                1.0,
+                1,
+                None,
+                TimeInForce::Gtd { expire_at: 1000 },
+            )
+            .unwrap();
+
+        assert!(book.expire_orders(999).is_empty());
+        assert_eq!(resting_ids(&book.l3_snapshot().0), vec![order_id]);
+
+        assert_eq!(book.expire_orders(1000), vec![order_id]);
+        assert!(book.l3_snapshot().0.is_empty());
+        assert!(book.get_order_book_snapshot().0.is_empty());
+    }
+
+    #[test]
+    fn notional_market_order_stops_at_the_target_cost() {
+        let mut book = OrderBook::new();
+        add_limit(&mut book, OrderSide::Sell, 100.0, 10.0, 1);
+        add_limit(&mut book, OrderSide::Sell, 101.0, 10.0, 2);
+
+        let symbol = Some("ACME".to_string());
+        let order_id = book
+            .add_market_order_by_notional(OrderSide::Buy, 1505.0, 3, symbol.clone())
+            .unwrap();
+        let quantities: Vec<(f64, f64)> =
+            book.trades.iter().map(|t| (t.price, t.quantity)).collect();
+        assert_eq!(quantities, vec![(100.0, 10.0), (101.0, 5.0)]);
+        let spent: f64 = book.trades.iter().map(|t| t.price * t.quantity).sum();
+        assert!((spent - 1505.0).abs() < 1e-9);
+        let order = book.get_order(order_id).unwrap();
+        assert_eq!(order.status, OrderStatus::Filled);
+        assert_eq!(order.symbol, symbol);
+        assert_eq!(book.get_statistics().orders_processed, 3);
+        assert_eq!(book.get_order_book_snapshot().1, vec![(101.0, 5.0)]);
+    }
+
+    #[test]
+    fn replaying_a_recording_reproduces_the_book() {
+        let mut book = OrderBook::new();
+        book.enable_recording();
+        let mut ts = 0;
+        for i in 0..40u64 {
+            ts += 1;
+            let side = if i % 3 == 0 {
+                OrderSide::Buy
+            } else {
+                OrderSide::Sell
+            };
+            let price = 95.0 + ((i * 7) % 11) as f64;
+            add_limit(&mut book, side, price, 1.0 + (i % 4) as f64, ts);
+            if i % 5 == 4 {
+                book.cancel_order(i - 2);
+            }
+            // The order may already have traded away, which the replay must reproduce too
+            if i % 7 == 6 {
+                let _ = book.modify_order(i, None, Some(2.0));
+            }
+            if i % 9 == 8 {
+                book.add_order(
+                    OrderSide::Buy,
+                    OrderType::Market,
+                    None,
+                    3.0,
+                    ts,
+                    None,
+                    TimeInForce::Gtc,
+                )
+                .unwrap();
+            }
+        }
+        assert!(!book.trades.is_empty());
+
+        let replayed = OrderBook::replay(book.recorded_events());
+        assert_eq!(
+            serde_json::to_string(&replayed.trades).unwrap(),
+            serde_json::to_string(&book.trades).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_string(&replayed.to_snapshot()).unwrap(),
+            serde_json::to_string(&book.to_snapshot()).unwrap()
+        );
+    }
+
+    #[test]
+    fn limit_order_without_a_price_is_an_error_while_recording() {
+        let mut book = OrderBook::new();
+        book.enable_recording();
+        let result = book.add_order(
+            OrderSide::Buy,
+            OrderType::Limit,
+            None,
+            1.0,
+            1,
+            None,
+            TimeInForce::Gtc,
+        );
+        assert_eq!(result, Err(OrderError::MissingLimitPrice));
+        assert!(book.recorded_events().is_empty());
+    }
+
+    #[test]
+    fn l3_snapshot_keeps_fifo_order_within_a_level() {
+        let mut book = OrderBook::new();
+        let first = add_limit(&mut book, OrderSide::Buy, 100.0, 1.0, 1);
+        let second = add_limit(&mut book, OrderSide::Buy, 100.0, 2.0, 2);
+        let better = add_limit(&mut book, OrderSide::Buy, 101.0, 1.0, 3);
+        let ask = add_limit(&mut book, OrderSide::Sell, 102.0, 1.0, 4);
+
+        let (bids, asks) = book.l3_snapshot();
+        assert_eq!(resting_ids(&bids), vec![better, first, second]);
+        assert_eq!(resting_ids(&asks), vec![ask]);
+        assert_eq!(bids[2].remaining_quantity, 2.0);
+    }
+
+    #[test]
+    fn notional_order_quantity_is_rounded_down_to_the_quantity_decimals() {
+        let mut book = OrderBook::new();
+        book.set_quantity_decimals(Some(2));
+        add_limit(&mut book, OrderSide::Sell, 100.0, 10.0, 1);
+        add_limit(&mut book, OrderSide::Sell, 101.0, 10.0, 2);
+
+        // 1500.5 buys 10 at 100 and 4.955... at 101, rounded down to 4.95
+        let order_id = book
+            .add_market_order_by_notional(OrderSide::Buy, 1500.5, 3, None)
+            .unwrap();
+        let order = book.get_order(order_id).unwrap();
+        assert_eq!(order.quantity, 14.95);
+        assert_eq!(order.filled_quantity, 14.95);
+        let spent: f64 = book.trades.iter().map(|t| t.price * t.quantity).sum();
+        assert!(spent <= 1500.5);
+    }
+
+    #[test]
+    fn quantity_rules_reject_orders_and_cancel_dust() {
+        let mut book = OrderBook::new();
+        book.set_quantity_rules(QuantityRules {
+            lot_size: 1.0,
+            min_quantity: 2.0,
+            max_quantity: 0.0,
+            cancel_dust: true,
+        });
+
+        let below_minimum = book.add_order(
+            OrderSide::Buy,
+            OrderType::Limit,
+            Some(100.0),
+            1.0,
+            1,
+            None,
+            TimeInForce::Gtc,
+        );
+        assert!(matches!(
+            below_minimum,
+            Err(OrderError::QuantityBelowMinimum { .. })
+        ));
+        let off_lot = book.add_order(
+            OrderSide::Buy,
+            OrderType::Limit,
+            Some(100.0),
+            2.5,
+            1,
+            None,
+            TimeInForce::Gtc,
+        );
+        assert!(matches!(
+            off_lot,
+            Err(OrderError::QuantityNotLotMultiple { .. })
+        ));
+
+        // Filling 4 of 5 would leave a 1-lot sliver below the minimum, so it is cancelled
+        let sell = add_limit(&mut book, OrderSide::Sell, 100.0, 5.0, 2);
+        add_limit(&mut book, OrderSide::Buy, 100.0, 4.0, 3);
+        let order = book.get_order(sell).unwrap();
+        assert_eq!(order.status, OrderStatus::Cancelled);
+        assert_eq!(order.filled_quantity, 4.0);
+        assert!(book.l3_snapshot().1.is_empty());
+    }
+
+    #[test]
+    fn notional_order_is_held_to_the_quantity_limits() {
+        let mut book = OrderBook::new();
+        book.set_quantity_rules(QuantityRules {
+            lot_size: 1.0,
+            min_quantity: 5.0,
+            max_quantity: 8.0,
+            cancel_dust: false,
+        });
+        add_limit(&mut book, OrderSide::Sell, 100.0, 8.0, 1);
+        add_limit(&mut book, OrderSide::Sell, 100.0, 8.0, 1);
+
+        let too_small = book.add_market_order_by_notional(OrderSide::Buy, 100.0, 2, None);
+        assert!(matches!(
+            too_small,
+            Err(OrderError::QuantityBelowMinimum { .. })
+        ));
+        assert!(book.trades.is_empty());
+
+        // 900 would buy 9, one more than the maximum order quantity
+        let order_id = book
+            .add_market_order_by_notional(OrderSide::Buy, 900.0, 3, None)
+            .unwrap();
+        let order = book.get_order(order_id).unwrap();
+        assert_eq!(order.filled_quantity, 8.0);
+        assert_eq!(order.status, OrderStatus::Filled);
+    }
+
+    #[test]
+    fn trades_filtered_by_symbol_and_time_window() {
+        let mut book = OrderBook::new();
+        for (ts, symbol) in [(1, "AAA"), (3, "BBB"), (5, "AAA"), (7, "AAA"), (9, "BBB")] {
+            let symbol = Some(symbol.to_string());
+            book.add_order(
+                OrderSide::Sell,
+                OrderType::Limit,
+                Some(100.0),
+                1.0,
+                ts,
+                symbol.clone(),
+                TimeInForce::Gtc,
+            )
+            .unwrap();
+            book.add_order(
+                OrderSide::Buy,
+                OrderType::Limit,
+                Some(100.0),
+                1.0,
+                ts,
+                symbol,
+                TimeInForce::Gtc,
+            )
+            .unwrap();
+        }
+
+        let timestamps =
+            |trades: Vec<&Trade>| trades.iter().map(|t| t.timestamp).collect::<Vec<_>>();
+        assert_eq!(
+            timestamps(book.trades_filtered(Some("AAA"), Some(2), Some(7))),
+            vec![5, 7]
+        );
+        assert_eq!(
+            timestamps(book.trades_filtered(Some("BBB"), None, None)),
+            vec![3, 9]
+        );
+        assert_eq!(
+            timestamps(book.trades_filtered(None, Some(3), Some(5))),
+            vec![3, 5]
+        );
+        assert!(book.trades_filtered(Some("CCC"), None, None).is_empty());
+        assert!(book.trades_filtered(None, Some(8), Some(4)).is_empty());
+    }
+
+    #[test]
+    fn trades_filtered_scans_a_log_out_of_time_order() {
+        let mut book = OrderBook::new();
+        add_limit(&mut book, OrderSide::Sell, 100.0, 1.0, 10);
+        add_limit(&mut book, OrderSide::Buy, 100.0, 1.0, 11);
+        // Older timestamps submitted later trade after the first pair
+        add_limit(&mut book, OrderSide::Sell, 100.0, 1.0, 1);
+        add_limit(&mut book, OrderSide::Buy, 100.0, 1.0, 2);
+
+        let timestamps =
+            |trades: Vec<&Trade>| trades.iter().map(|t| t.timestamp).collect::<Vec<_>>();
+        assert_eq!(
+            timestamps(book.trades_filtered(None, Some(0), Some(5))),
+            vec![2]
+        );
+        assert_eq!(
+            timestamps(book.trades_filtered(None, Some(5), None)),
+            vec![11]
+        );
+        assert_eq!(
+            timestamps(book.trades_filtered(None, None, None)),
+            vec![11, 2]
+        );
+    }
+
+    #[test]
+    fn pro_rata_rounds_down_and_hands_out_the_residual_in_time_priority() {
+        // 10 over 5/3/3 is 4.5/2.7/2.7: rounded down to 4/2/2, the 2 left over goes to
+        // the first orders in the queue
+        let allocations = MatchingPolicy::ProRata.allocate(10.0, &[5.0, 3.0, 3.0]);
+        assert_eq!(allocations, vec![5.0, 3.0, 2.0]);
+
+        // Shares are rounded down to whole lots
+        let allocations = MatchingPolicy::ProRata.allocate_in_lots(9.0, &[10.0, 10.0], 2.0);
+        assert_eq!(allocations, vec![5.0, 4.0]);
+
+        // Top-order pro-rata serves the head of the queue first
+        let policy = MatchingPolicy::TopOrderProRata {
+            top_order_share: 0.5,
+        };
+        assert_eq!(policy.allocate(10.0, &[10.0, 10.0]), vec![7.0, 3.0]);
+    }
+
+    #[test]
+    fn fill_or_kill_probe_respects_the_slippage_limit() {
+        let mut book = OrderBook::new();
+        add_limit(&mut book, OrderSide::Sell, 100.0, 5.0, 1);
+        add_limit(&mut book, OrderSide::Sell, 110.0, 5.0, 2);
+
+        // 100 bps of slippage stops the sweep at 101, so only 5 of the 10 are fillable
+        let order_id = book
+            .add_order_with_options(
+                OrderSide::Buy,
+                OrderType::Market,
+                None,
+                10.0,
+                3,
+                None,
+                OrderOptions {
+                    time_in_force: TimeInForce::Fok,
+                    max_slippage_bps: Some(100.0),
+                    ..OrderOptions::default()
+                },
+            )
+            .unwrap();
+        let order = book.get_order(order_id).unwrap();
+        assert_eq!(order.status, OrderStatus::Rejected);
+        assert_eq!(order.reject_reason, Some(RejectReason::FillOrKillUnfilled));
+        assert!(book.trades.is_empty());
+    }
+
+    #[test]
+    fn fill_or_kill_probe_respects_the_worst_price() {
+        let mut book = OrderBook::new();
+        add_limit(&mut book, OrderSide::Sell, 100.0, 5.0, 1);
+        add_limit(&mut book, OrderSide::Sell, 110.0, 5.0, 2);
+
+        let order_id = book
+            .add_order_with_options(
+                OrderSide::Buy,
+                OrderType::Market,
+                None,
+                10.0,
+                3,
+                None,
+                OrderOptions {
+                    time_in_force: TimeInForce::Fok,
+                    worst_price: Some(105.0),
+                    ..OrderOptions::default()
+                },
+            )
+            .unwrap();
+        let order = book.get_order(order_id).unwrap();
+        assert_eq!(order.status, OrderStatus::Rejected);
+        assert_eq!(order.reject_reason, Some(RejectReason::FillOrKillUnfilled));
+        assert!(book.trades.is_empty());
+    }
+
+    #[test]
+    fn cancel_replace_is_refused_while_halted_or_outside_the_collar() {
+        let mut book = OrderBook::new();
+        let bid = add_limit(&mut book, OrderSide::Buy, 99.0, 5.0, 1);
+        add_limit(&mut book, OrderSide::Sell, 101.0, 5.0, 2);
+
+        book.set_trading_state(TradingState::Halted);
+        let refused = book.cancel_replace(bid, 101.0, 5.0, 3);
+        assert_eq!(
+            refused,
+            Err(OrderError::Refused {
+                reason: RejectReason::TradingHalted
+            })
+        );
+        assert_eq!(book.get_order(bid).unwrap().status, OrderStatus::New);
+        book.set_trading_state(TradingState::ContinuousTrading);
+        assert!(book.trades.is_empty());
+
+        book.set_price_collar(Some(ProtectionBand::Percent(1.0)));
+        let refused = book.cancel_replace(bid, 105.0, 5.0, 4);
+        assert_eq!(
+            refused,
+            Err(OrderError::Refused {
+                reason: RejectReason::PriceCollar
+            })
+        );
+        assert_eq!(resting_ids(&book.l3_snapshot().0), vec![bid]);
+    }
+
+    #[test]
+    fn busting_a_trade_reverses_its_statistics() {
+        let mut book = OrderBook::new();
+        book.enable_recording();
+        add_limit(&mut book, OrderSide::Sell, 100.0, 5.0, 1);
+        add_limit(&mut book, OrderSide::Buy, 100.0, 5.0, 2);
+        let trade_id = book.trades[0].id;
+
+        assert!(!book.bust_trade(trade_id + 1, false));
+        let recorded = book.recorded_events().len();
+        assert!(book.bust_trade(trade_id, false));
+        assert_eq!(book.recorded_events().len(), recorded + 1);
+
+        let stats = book.get_statistics();
+        assert_eq!(stats.trades_executed, 0);
+        assert_eq!(stats.total_trade_volume, 0.0);
+        assert_eq!(stats.total_notional, 0.0);
+        assert_eq!(stats.high_price, None);
+        assert_eq!(stats.last_trade_price, None);
+        assert_eq!(book.session.trades_executed, 0);
+        assert_eq!(book.session.close_price, None);
+    }
+
+    #[test]
+    fn trades_serialized_without_the_aggressor_still_deserialize() {
+        let json = r#"{"id":1,"buy_order_id":2,"sell_order_id":1,"price":100.0,"quantity":5.0,
+            "timestamp":2,"symbol":null,"maker_fee":0.0,"taker_fee":0.0}"#;
+        let trade: Trade = serde_json::from_str(json).unwrap();
+        assert_eq!(trade.aggressor_side, OrderSide::Buy);
+        assert_eq!((trade.maker_order_id, trade.taker_order_id), (0, 0));
+    }
+}